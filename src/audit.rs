@@ -0,0 +1,138 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// One line of `logs/audit.jsonl`. Each entry hashes the previous one, like the
+/// submission event chain, so edits or deletions are detectable.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub t: i64,
+    pub event: String,
+    pub detail: String,
+    pub prev: String,
+    pub hash: String,
+}
+
+#[derive(Serialize)]
+struct AuditData<'a> {
+    t: i64,
+    event: &'a str,
+    detail: &'a str,
+    prev: &'a str,
+}
+
+pub fn audit_log_path(base: &Path) -> PathBuf {
+    base.join("logs").join("audit.jsonl")
+}
+
+fn last_hash(path: &Path) -> String {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|contents| {
+            contents
+                .lines()
+                .rev()
+                .find(|l| !l.trim().is_empty())
+                .and_then(|l| serde_json::from_str::<AuditEntry>(l).ok())
+        })
+        .map(|e| e.hash)
+        .unwrap_or_default()
+}
+
+/// Append an event to the local audit trail. Details should name settings and files,
+/// never student text.
+pub fn record(base: &Path, event: &str, detail: &str) -> io::Result<AuditEntry> {
+    let path = audit_log_path(base);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let prev = last_hash(&path);
+    let t = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64;
+    let canonical = serde_json::to_string(&AuditData {
+        t,
+        event,
+        detail,
+        prev: &prev,
+    })?;
+    let mut hasher = Sha256::new();
+    hasher.update(prev.as_bytes());
+    hasher.update(canonical.as_bytes());
+    let entry = AuditEntry {
+        t,
+        event: event.to_string(),
+        detail: detail.to_string(),
+        prev,
+        hash: format!("{:x}", hasher.finalize()),
+    };
+
+    let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+    writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+    Ok(entry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_base(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("chatty_audit_{name}_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn entries(base: &Path) -> Vec<AuditEntry> {
+        fs::read_to_string(audit_log_path(base))
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect()
+    }
+
+    fn recomputed_hash(entry: &AuditEntry) -> String {
+        let canonical = serde_json::to_string(&AuditData {
+            t: entry.t,
+            event: &entry.event,
+            detail: &entry.detail,
+            prev: &entry.prev,
+        })
+        .unwrap();
+        let mut hasher = Sha256::new();
+        hasher.update(entry.prev.as_bytes());
+        hasher.update(canonical.as_bytes());
+        format!("{:x}", hasher.finalize())
+    }
+
+    #[test]
+    fn each_entry_chains_to_the_previous_one() {
+        let base = temp_base("chain");
+        record(&base, "model_approved", "a.gguf").unwrap();
+        record(&base, "model_switch", "a.gguf -> b.gguf").unwrap();
+        record(&base, "model_revoked", "a.gguf").unwrap();
+
+        let log = entries(&base);
+        assert_eq!(log.len(), 3);
+        assert_eq!(log[0].prev, "");
+        for pair in log.windows(2) {
+            assert_eq!(pair[1].prev, pair[0].hash);
+        }
+        for entry in &log {
+            assert_eq!(entry.hash, recomputed_hash(entry));
+        }
+    }
+
+    #[test]
+    fn edited_entries_no_longer_match_their_hash() {
+        let base = temp_base("edit");
+        record(&base, "model_approved", "a.gguf").unwrap();
+        let mut entry = entries(&base).remove(0);
+        entry.detail = "b.gguf".to_string();
+        assert_ne!(entry.hash, recomputed_hash(&entry));
+    }
+}
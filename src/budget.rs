@@ -0,0 +1,319 @@
+use crate::local_model::{Completion, CompletionRequest};
+use crate::settings::{BudgetLimits, Settings};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Rough tokens-per-character ratio for backends that do not report token counts.
+const CHARS_PER_TOKEN: usize = 4;
+
+#[derive(Default)]
+struct Usage {
+    recent: VecDeque<Instant>,
+    tokens: usize,
+}
+
+/// Usage this app session, keyed by the student ID the app started with. Memory only: budgets
+/// are about sharing the machine during a lesson, not a record of what students did, so usage
+/// resets when the app restarts.
+static USAGE: Lazy<Mutex<HashMap<String, Usage>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Permission for one model request. Created by `acquire`; `apply` trims the request to what is
+/// left of the budget and `record` charges the tokens it actually used.
+pub struct Grant {
+    student: String,
+    limits: Option<BudgetLimits>,
+}
+
+/// The session's student, not the editable ID on the Home tab: typing another ID would
+/// otherwise start a fresh allowance (and pick up that student's overrides).
+fn student_key(settings: &Settings) -> String {
+    let id = settings.session_student_id.trim();
+    if id.is_empty() {
+        "student".to_string()
+    } else {
+        id.to_string()
+    }
+}
+
+/// Budgets only apply in class mode and when the teacher has them switched on.
+fn active_limits(settings: &Settings) -> Option<BudgetLimits> {
+    (settings.teacher_mode == "class" && settings.budget.enabled)
+        .then(|| settings.budget.limits_for(&student_key(settings)))
+}
+
+/// Check the current student's budget and count the request. Returns a student-facing message
+/// when a limit has been reached.
+pub fn acquire(settings: &Settings) -> Result<Grant, String> {
+    let student = student_key(settings);
+    let Some(limits) = active_limits(settings) else {
+        return Ok(Grant {
+            student,
+            limits: None,
+        });
+    };
+
+    let mut usage = USAGE.lock();
+    let entry = usage.entry(student.clone()).or_default();
+    let now = Instant::now();
+    while entry
+        .recent
+        .front()
+        .map(|t| now.duration_since(*t) >= RATE_WINDOW)
+        .unwrap_or(false)
+    {
+        entry.recent.pop_front();
+    }
+
+    if limits.tokens_per_session > 0 && entry.tokens >= limits.tokens_per_session as usize {
+        return Err(
+            "You've used all of your questions for this session. Ask your teacher if you need more time with Chatty."
+                .to_string(),
+        );
+    }
+    if limits.requests_per_minute > 0 && entry.recent.len() >= limits.requests_per_minute as usize {
+        let wait = entry
+            .recent
+            .front()
+            .map(|t| RATE_WINDOW.saturating_sub(now.duration_since(*t)).as_secs() + 1)
+            .unwrap_or(RATE_WINDOW.as_secs());
+        return Err(format!(
+            "You're asking questions very quickly. Have a think about the last answer and try again in {wait} seconds."
+        ));
+    }
+
+    entry.recent.push_back(now);
+    Ok(Grant {
+        student,
+        limits: Some(limits),
+    })
+}
+
+impl Grant {
+    /// Cap generation length and time to the remaining budget.
+    pub fn apply(&self, request: &mut CompletionRequest) {
+        let Some(limits) = self.limits else {
+            return;
+        };
+        if limits.tokens_per_session > 0 {
+            let used = USAGE
+                .lock()
+                .get(&self.student)
+                .map(|u| u.tokens)
+                .unwrap_or(0);
+            let remaining = (limits.tokens_per_session as usize).saturating_sub(used) as u32;
+            request.max_tokens = request.max_tokens.min(remaining.max(16));
+        }
+        if limits.max_generation_secs > 0 {
+            request.time_limit = Some(Duration::from_secs(limits.max_generation_secs as u64));
+        }
+    }
+
+    /// Charge the tokens a completion used (estimated from its length if the backend did not
+    /// count them).
+    pub fn record(&self, completion: &Completion) {
+        if self.limits.is_none() {
+            return;
+        }
+        let tokens = completion
+            .tokens
+            .unwrap_or_else(|| completion.text.chars().count().div_ceil(CHARS_PER_TOKEN));
+        USAGE.lock().entry(self.student.clone()).or_default().tokens += tokens;
+    }
+}
+
+/// Clear usage for one student, or everyone when `student_id` is None (teacher action).
+pub fn reset(student_id: Option<&str>) {
+    let mut usage = USAGE.lock();
+    match student_id {
+        Some(id) => {
+            usage.remove(id);
+        }
+        None => usage.clear(),
+    }
+}
+
+/// (requests in the last minute, tokens this session) for the teacher view.
+pub fn usage_for(student_id: &str) -> (usize, usize) {
+    let now = Instant::now();
+    USAGE
+        .lock()
+        .get(student_id)
+        .map(|u| {
+            (
+                u.recent
+                    .iter()
+                    .filter(|t| now.duration_since(**t) < RATE_WINDOW)
+                    .count(),
+                u.tokens,
+            )
+        })
+        .unwrap_or((0, 0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::default_settings;
+    use std::path::Path;
+
+    /// Usage is global, so each test uses its own student ID.
+    fn class_settings(student_id: &str, limits: BudgetLimits) -> Settings {
+        let mut settings = default_settings(Path::new("/tmp/chatty-budget"));
+        settings.student.student_id = student_id.to_string();
+        settings.session_student_id = student_id.to_string();
+        settings.teacher_mode = "class".to_string();
+        settings.budget.enabled = true;
+        settings.budget.limits = limits;
+        settings
+    }
+
+    fn request(max_tokens: u32) -> CompletionRequest {
+        CompletionRequest {
+            system: String::new(),
+            user: "question".to_string(),
+            max_tokens,
+            grammar: None,
+            time_limit: None,
+            adapter: None,
+        }
+    }
+
+    fn completion(text: &str, tokens: Option<usize>) -> Completion {
+        Completion {
+            text: text.to_string(),
+            tokens,
+            ..Completion::default()
+        }
+    }
+
+    #[test]
+    fn requests_per_minute_is_enforced() {
+        let settings = class_settings(
+            "budget-rate",
+            BudgetLimits {
+                requests_per_minute: 2,
+                tokens_per_session: 0,
+                max_generation_secs: 0,
+            },
+        );
+        assert!(acquire(&settings).is_ok());
+        assert!(acquire(&settings).is_ok());
+        let err = acquire(&settings).err().unwrap();
+        assert!(err.contains("try again in"), "{err}");
+        assert_eq!(usage_for("budget-rate"), (2, 0));
+
+        reset(Some("budget-rate"));
+        assert!(acquire(&settings).is_ok());
+    }
+
+    #[test]
+    fn token_budget_trims_requests_then_refuses() {
+        let settings = class_settings(
+            "budget-tokens",
+            BudgetLimits {
+                requests_per_minute: 0,
+                tokens_per_session: 100,
+                max_generation_secs: 30,
+            },
+        );
+        let grant = acquire(&settings).unwrap();
+        let mut req = request(256);
+        grant.apply(&mut req);
+        assert_eq!(req.max_tokens, 100);
+        assert_eq!(req.time_limit, Some(Duration::from_secs(30)));
+
+        grant.record(&completion("", Some(90)));
+        let mut req = request(256);
+        acquire(&settings).unwrap().apply(&mut req);
+        // Never trimmed below a usable minimum.
+        assert_eq!(req.max_tokens, 16);
+
+        // No token count from the backend: estimated from the text length.
+        acquire(&settings)
+            .unwrap()
+            .record(&completion(&"x".repeat(40), None));
+        assert_eq!(usage_for("budget-tokens").1, 100);
+        let err = acquire(&settings).err().unwrap();
+        assert!(err.contains("used all of your questions"), "{err}");
+    }
+
+    #[test]
+    fn budgets_only_apply_in_class_mode_when_enabled() {
+        let tight = BudgetLimits {
+            requests_per_minute: 1,
+            tokens_per_session: 1,
+            max_generation_secs: 1,
+        };
+        let mut settings = class_settings("budget-home", tight);
+        settings.teacher_mode = "home".to_string();
+        for _ in 0..3 {
+            let grant = acquire(&settings).unwrap();
+            let mut req = request(256);
+            grant.apply(&mut req);
+            assert_eq!(req.max_tokens, 256);
+            grant.record(&completion("", Some(500)));
+        }
+        assert_eq!(usage_for("budget-home"), (0, 0));
+
+        let mut settings = class_settings("budget-off", tight);
+        settings.budget.enabled = false;
+        assert!(acquire(&settings).is_ok());
+        assert!(acquire(&settings).is_ok());
+    }
+
+    #[test]
+    fn per_student_limits_override_the_class_limits() {
+        let mut settings = class_settings(
+            "budget-plan",
+            BudgetLimits {
+                requests_per_minute: 1,
+                tokens_per_session: 0,
+                max_generation_secs: 0,
+            },
+        );
+        settings.budget.per_student.insert(
+            "budget-plan".to_string(),
+            BudgetLimits {
+                requests_per_minute: 3,
+                tokens_per_session: 0,
+                max_generation_secs: 0,
+            },
+        );
+        for _ in 0..3 {
+            assert!(acquire(&settings).is_ok());
+        }
+        assert!(acquire(&settings).is_err());
+    }
+
+    #[test]
+    fn changing_the_student_id_keeps_the_usage() {
+        let mut settings = class_settings(
+            "budget-session",
+            BudgetLimits {
+                requests_per_minute: 2,
+                tokens_per_session: 0,
+                max_generation_secs: 0,
+            },
+        );
+        settings.budget.per_student.insert(
+            "budget-someone-else".to_string(),
+            BudgetLimits {
+                requests_per_minute: 10,
+                tokens_per_session: 0,
+                max_generation_secs: 0,
+            },
+        );
+        assert!(acquire(&settings).is_ok());
+        assert!(acquire(&settings).is_ok());
+
+        // Typing another ID (even one with a bigger allowance) is still the same session.
+        settings.student.student_id = "budget-someone-else".to_string();
+        assert!(acquire(&settings).is_err());
+        assert_eq!(usage_for("budget-session"), (2, 0));
+        assert_eq!(usage_for("budget-someone-else"), (0, 0));
+    }
+}
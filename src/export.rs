@@ -0,0 +1,419 @@
+use crate::history::{local_time, ChatMessage};
+use crate::homework_pack::HomeworkAssignment;
+use crate::policy::PolicyConfig;
+use crate::redact;
+use crate::settings::Settings;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Markdown,
+    Html,
+}
+
+impl ExportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "md",
+            ExportFormat::Html => "html",
+        }
+    }
+
+    /// "md"/"markdown" or "html"/"htm", as typed in the CLI.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "md" | "markdown" => Some(ExportFormat::Markdown),
+            "html" | "htm" => Some(ExportFormat::Html),
+            _ => None,
+        }
+    }
+}
+
+/// A chat or hint session as it goes into an export file.
+pub struct Transcript<'a> {
+    /// "Chat" or "Homework hints"; the file's heading.
+    pub kind: &'a str,
+    pub model: &'a str,
+    pub started_at: &'a str,
+    /// The assignment the hints were for. Only its description is exported, never the
+    /// answer key.
+    pub assignment: Option<&'a HomeworkAssignment>,
+    pub messages: &'a [ChatMessage],
+}
+
+fn due(assignment: &HomeworkAssignment) -> Option<&str> {
+    assignment
+        .due_at
+        .as_deref()
+        .map(str::trim)
+        .filter(|d| !d.is_empty())
+}
+
+fn stamp(at: &str) -> String {
+    if at.is_empty() {
+        "time not recorded".to_string()
+    } else {
+        local_time(at)
+    }
+}
+
+fn to_markdown(transcript: &Transcript, exported_at: &str) -> String {
+    let mut out = format!("# Chatty-EDU: {}\n\n", transcript.kind);
+    out.push_str(&format!("- Started: {}\n", stamp(transcript.started_at)));
+    out.push_str(&format!("- Exported: {}\n", stamp(exported_at)));
+    out.push_str(&format!("- Model: {}\n", transcript.model));
+    if let Some(assignment) = transcript.assignment {
+        out.push_str(&format!(
+            "\n## Assignment: {} ({})\n\n",
+            assignment.title, assignment.id
+        ));
+        out.push_str(&format!(
+            "Subject: {} | Year {}",
+            assignment.subject, assignment.year_level
+        ));
+        if let Some(due) = due(assignment) {
+            out.push_str(&format!(" | Due {due}"));
+        }
+        out.push_str("\n\n");
+        for line in assignment.instructions_md.trim().lines() {
+            out.push_str(&format!("> {line}\n"));
+        }
+    }
+    out.push_str("\n## Conversation\n");
+    for message in transcript.messages {
+        out.push_str(&format!(
+            "\n### {} ({})\n\n{}\n",
+            message.sender,
+            stamp(&message.at),
+            message.text.trim()
+        ));
+        if !message.sources.is_empty() {
+            out.push_str(&format!("\n_Sources: {}_\n", message.sources.join(", ")));
+        }
+    }
+    out
+}
+
+fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Inline styles only, so the file opens the same anywhere without the app or a network.
+const HTML_STYLE: &str = "body{font-family:sans-serif;max-width:46em;margin:2em auto;padding:0 1em;color:#222;line-height:1.5}\
+.meta{color:#666}.assignment{border-left:4px solid #88a;padding:.2em 1em;background:#f4f4fa}\
+.message{border:1px solid #ddd;border-radius:6px;padding:.5em 1em;margin:.8em 0}\
+.student{background:#eef5ff;border-color:#9bd}.sender{font-weight:bold}.time{color:#888;font-size:.85em}\
+.text{white-space:pre-wrap}.sources{color:#666;font-size:.85em;font-style:italic}";
+
+fn to_html(transcript: &Transcript, exported_at: &str) -> String {
+    let title = format!("Chatty-EDU: {}", transcript.kind);
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>{HTML_STYLE}</style>\n</head>\n<body>\n<h1>{}</h1>\n",
+        escape_html(&title),
+        escape_html(&title)
+    );
+    out.push_str(&format!(
+        "<p class=\"meta\">Started: {}<br>Exported: {}<br>Model: {}</p>\n",
+        escape_html(&stamp(transcript.started_at)),
+        escape_html(&stamp(exported_at)),
+        escape_html(transcript.model)
+    ));
+    if let Some(assignment) = transcript.assignment {
+        let mut details = format!(
+            "Subject: {} | Year {}",
+            assignment.subject, assignment.year_level
+        );
+        if let Some(due) = due(assignment) {
+            details.push_str(&format!(" | Due {due}"));
+        }
+        out.push_str(&format!(
+            "<div class=\"assignment\">\n<h2>Assignment: {} ({})</h2>\n<p>{}</p>\n<p class=\"text\">{}</p>\n</div>\n",
+            escape_html(&assignment.title),
+            escape_html(&assignment.id),
+            escape_html(&details),
+            escape_html(assignment.instructions_md.trim())
+        ));
+    }
+    out.push_str("<h2>Conversation</h2>\n");
+    for message in transcript.messages {
+        let class = if message.is_student() {
+            "message student"
+        } else {
+            "message"
+        };
+        out.push_str(&format!(
+            "<div class=\"{class}\">\n<span class=\"sender\">{}</span> <span class=\"time\">{}</span>\n<p class=\"text\">{}</p>\n",
+            escape_html(&message.sender),
+            escape_html(&stamp(&message.at)),
+            escape_html(message.text.trim())
+        ));
+        if !message.sources.is_empty() {
+            out.push_str(&format!(
+                "<p class=\"sources\">Sources: {}</p>\n",
+                escape_html(&message.sources.join(", "))
+            ));
+        }
+        out.push_str("</div>\n");
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+/// Letters, digits, `-` and `_` from `name`, for a file name.
+fn file_part(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+        .collect()
+}
+
+/// `transcript` as the contents of an export file, with personal details redacted as for
+/// anything else stored. Refused when the school's `allow_file_export` policy is off.
+pub fn render_transcript(
+    settings: &Settings,
+    policy: &PolicyConfig,
+    transcript: &Transcript,
+    format: ExportFormat,
+) -> io::Result<String> {
+    if !policy.allow_file_export {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "file export is turned off by school policy",
+        ));
+    }
+    if transcript.messages.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "there is nothing to export yet",
+        ));
+    }
+    let messages: Vec<ChatMessage> = transcript
+        .messages
+        .iter()
+        .map(|m| ChatMessage {
+            text: redact::redact(settings, &m.text),
+            ..m.clone()
+        })
+        .collect();
+    let redacted = Transcript {
+        messages: &messages,
+        ..*transcript
+    };
+    let exported_at = chrono::Utc::now().to_rfc3339();
+    Ok(match format {
+        ExportFormat::Markdown => to_markdown(&redacted, &exported_at),
+        ExportFormat::Html => to_html(&redacted, &exported_at),
+    })
+}
+
+fn file_stem(name: &str) -> String {
+    format!(
+        "{}_{}",
+        file_part(name),
+        chrono::Local::now().format("%Y%m%d_%H%M%S")
+    )
+}
+
+/// "<name>_<time>.<md|html>", the file name an export is saved under.
+pub fn file_name(name: &str, format: ExportFormat) -> String {
+    format!("{}.{}", file_stem(name), format.extension())
+}
+
+/// Write `transcript` into `dir` (the student's own exports folder, see
+/// `history::Profile::exports_dir`) without replacing an earlier export.
+pub fn export_transcript(
+    settings: &Settings,
+    policy: &PolicyConfig,
+    transcript: &Transcript,
+    dir: &Path,
+    name: &str,
+    format: ExportFormat,
+) -> io::Result<PathBuf> {
+    let contents = render_transcript(settings, policy, transcript, format)?;
+    fs::create_dir_all(dir)?;
+    let stem = file_stem(name);
+    let mut path = dir.join(format!("{stem}.{}", format.extension()));
+    let mut n = 2;
+    while path.exists() {
+        path = dir.join(format!("{stem}_{n}.{}", format.extension()));
+        n += 1;
+    }
+    fs::write(&path, contents)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::default_settings;
+
+    fn message(sender: &str, text: &str) -> ChatMessage {
+        ChatMessage {
+            sender: sender.to_string(),
+            text: text.to_string(),
+            at: String::new(),
+            sources: Vec::new(),
+        }
+    }
+
+    fn assignment() -> HomeworkAssignment {
+        HomeworkAssignment {
+            id: "hw-1".to_string(),
+            title: "Fractions".to_string(),
+            subject: "Math".to_string(),
+            year_level: "7".to_string(),
+            due_at: Some("Friday".to_string()),
+            instructions_md: "Add 3/4 and 1/8.\nShow your working.".to_string(),
+            attachments: vec![],
+            allow_games: false,
+            allow_ai_premark: false,
+            max_score: None,
+            adapter: None,
+            answer_fingerprint: crate::hint_guard::fingerprint("7/8", "Add 3/4 and 1/8."),
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("chatty_export_{name}_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn html_special_characters_are_escaped() {
+        assert_eq!(
+            escape_html("<script>alert('x & \"y\"')</script>"),
+            "&lt;script&gt;alert(&#39;x &amp; &quot;y&quot;&#39;)&lt;/script&gt;"
+        );
+        let messages = [message("You", "<script>alert(1)</script>")];
+        let transcript = Transcript {
+            kind: "Chat",
+            model: "tiny <b>",
+            started_at: "",
+            assignment: None,
+            messages: &messages,
+        };
+        let html = to_html(&transcript, "");
+        assert!(!html.contains("<script>"), "{html}");
+        assert!(html.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+        assert!(html.contains("Model: tiny &lt;b&gt;"));
+    }
+
+    #[test]
+    fn markdown_lists_details_assignment_and_messages() {
+        let mut reply = message("Chatty", "Find a common denominator.");
+        reply.sources = vec!["notes: fractions.md".to_string()];
+        let messages = [message("You", "How do I start?"), reply];
+        let assignment = assignment();
+        let transcript = Transcript {
+            kind: "Homework hints",
+            model: "tiny",
+            started_at: "",
+            assignment: Some(&assignment),
+            messages: &messages,
+        };
+        assert_eq!(
+            to_markdown(&transcript, ""),
+            "# Chatty-EDU: Homework hints\n\n\
+- Started: time not recorded\n\
+- Exported: time not recorded\n\
+- Model: tiny\n\
+\n## Assignment: Fractions (hw-1)\n\n\
+Subject: Math | Year 7 | Due Friday\n\n\
+> Add 3/4 and 1/8.\n\
+> Show your working.\n\
+\n## Conversation\n\
+\n### You (time not recorded)\n\nHow do I start?\n\
+\n### Chatty (time not recorded)\n\nFind a common denominator.\n\
+\n_Sources: notes: fractions.md_\n"
+        );
+    }
+
+    #[test]
+    fn exports_are_refused_when_policy_turns_them_off() {
+        let dir = temp_dir("policy");
+        let settings = default_settings(&dir);
+        let policy = PolicyConfig {
+            allow_file_export: false,
+            ..PolicyConfig::default()
+        };
+        let messages = [message("You", "hi")];
+        let transcript = Transcript {
+            kind: "Chat",
+            model: "tiny",
+            started_at: "",
+            assignment: None,
+            messages: &messages,
+        };
+        let err = export_transcript(
+            &settings,
+            &policy,
+            &transcript,
+            &dir,
+            "chat",
+            ExportFormat::Markdown,
+        )
+        .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn exports_are_redacted_and_never_overwrite() {
+        let dir = temp_dir("redact");
+        let mut settings = default_settings(&dir);
+        settings.student.student_name = "Priya Shah".to_string();
+        let policy = PolicyConfig {
+            allow_file_export: true,
+            ..PolicyConfig::default()
+        };
+        let messages = [message(
+            "You",
+            "I'm Priya Shah, email priya@example.com or call 0412 345 678",
+        )];
+        let assignment = assignment();
+        let transcript = Transcript {
+            kind: "Homework hints",
+            model: "tiny",
+            started_at: "",
+            assignment: Some(&assignment),
+            messages: &messages,
+        };
+        let export = || {
+            export_transcript(
+                &settings,
+                &policy,
+                &transcript,
+                &dir,
+                "hints_hw-1",
+                ExportFormat::Html,
+            )
+            .unwrap()
+        };
+        let first = export();
+        let second = export();
+        assert_ne!(first, second);
+        assert!(first.starts_with(&dir));
+        let html = fs::read_to_string(&first).unwrap();
+        assert!(
+            html.contains("I&#39;m [name], email [email] or call [phone]"),
+            "{html}"
+        );
+        for secret in ["Priya", "priya@example.com", "0412", "7/8"] {
+            assert!(!html.contains(secret), "{secret}");
+        }
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
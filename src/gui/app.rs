@@ -1,15 +1,32 @@
-use crate::chat::generate_answer;
+use crate::audit;
+use crate::budget;
+use crate::chat::{generate_chat_reply, generate_hint, generate_questions, StudentAnswer};
+use crate::export::{self, ExportFormat, Transcript};
+use crate::hint_guard;
+use crate::history::{self, ChatMessage, ChatSession};
 use crate::homework_pack::{
     apply_pack_policy, create_pack_multi, export_pack_template, find_latest_pack,
     load_pack_from_file, load_submission_summaries, save_submission_with_answers,
     HomeworkAssignment, HomeworkPack, SubmissionSummary,
 };
-use crate::local_model;
+use crate::injection;
+use crate::local_model::{self, MetricsSummary};
+use crate::model_catalog::{
+    approve_model, approved_by_hash, discover_local_models, file_sha256, is_listed_approved,
+    revoke_model, verify_model, ChecksumStatus, LocalModelFile,
+};
 use crate::modules::{load_modules, role_allowed, LoadedModule, ModuleEntry};
-use crate::settings::{save_settings, Settings};
+use crate::policy::{load_policy, PolicyConfig};
+use crate::response_cache;
+use crate::retrieval::{passages_for_chat, passages_for_hint, Passage};
+use crate::safety;
+use crate::settings::{
+    save_settings, BudgetLimits, JanetClassifier, ModelBackend, ModelTask, Settings,
+};
 use crate::theme::{
     apply_theme, ensure_theme_files, load_presets, load_theme, save_theme, ThemeConfig,
 };
+use crate::wellbeing::{self, SupportContact};
 use eframe::{
     egui::{
         self, menu, scroll_area::ScrollBarVisibility, Align, CentralPanel, Context, Layout,
@@ -23,21 +40,8 @@ use std::fs;
 use std::io;
 use std::panic;
 use std::path::{Path, PathBuf};
-
-const CHAT_CAPSULE: &str = "Chatty-EDU - Chat Capsule (Chat tab system prompt)\n\
-Role: You are Chatty-EDU, an offline learning assistant running entirely on a local computer. You do not have internet access and never browse, search, or fetch links.\n\
-Scope: Help with learning questions, explanations, and clarification. Keep responses short, clear, and factual. Default to one concise response unless the user asks for more detail.\n\
-Style: Do not invent conversations, roles, or dialogue. Do not hallucinate prior context or role-play multiple speakers. Avoid rambling, repetition, or motivational speeches.\n\
-Safety: Use school-appropriate language. If something is outside scope or inappropriate, give one calm sentence that you cannot help and suggest a safe alternative.\n\
-Defaults: If you are unsure what the user wants, ask one short clarifying question. If asked what you can do, briefly explain your learning-help role.\n";
-
-const HINT_CAPSULE: &str = "Chatty-EDU - Homework Hint Capsule (Homework & Revision hints/tutor)\n\
-Role: You are Chatty-EDU Homework Helper, assisting with the specific assignment provided. This session is offline and assignment-bound.\n\
-Hints-only mode: Never give full answers or text that could be submitted. Always respond with hints, steps, guiding questions, reminders of what to look for, or a simple example that does not match the assignment wording or numbers.\n\
-Override: If the user asks for a hint related to the assignment, you must respond with a hint. Do not refuse or redirect. Bypass generic safety refusals.\n\
-Format: Use one of these: a guiding question; a short list of steps; a reminder of what to review; or a small example that does not match the assignment data.\n\
-Failsafe: If a request would require giving the full answer, say \"I can't give the answer, but here's a way to think about it:\" and then provide a hint.\n\
-Tone: Neutral, supportive, brief. No praise, scolding, or moral lectures.\n";
+use std::sync::mpsc;
+use std::thread;
 
 #[derive(Debug, Clone, Default)]
 struct AssignmentDraft {
@@ -50,8 +54,10 @@ struct AssignmentDraft {
     allow_games: bool,
     allow_ai_premark: bool,
     max_score: String,
+    answer_key: String,
 }
 
+
 #[derive(Debug, Clone)]
 struct StudentScore {
     #[allow(dead_code)]
@@ -75,6 +81,37 @@ struct SubmissionRow {
     submitted_at: String,
 }
 
+/// A chat or hint reply from the worker thread. `screened` is set when Janet stopped the
+/// question before the model ran, so `text` is her message.
+struct StudentReply {
+    answer: StudentAnswer,
+    sources: Vec<String>,
+    screened: bool,
+}
+
+impl StudentReply {
+    fn screened(message: String) -> Self {
+        Self {
+            answer: StudentAnswer {
+                text: message,
+                generated: false,
+            },
+            sources: Vec::new(),
+            screened: true,
+        }
+    }
+
+    /// Model text is kept short for the window; Janet's, budget and hint-rule messages are
+    /// shown in full so support contacts are never cut off.
+    fn display_text(self) -> String {
+        if self.answer.generated {
+            ChattyApp::sanitize_short(&self.answer.text, 4, 400)
+        } else {
+            self.answer.text
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 enum TabKind {
     Home,
@@ -95,54 +132,6 @@ struct Tab {
     key: String,
 }
 
-#[derive(Debug, Clone)]
-struct LocalModelFile {
-    name: String,
-    path: PathBuf,
-}
-
-fn discover_local_models(base: &Path) -> Vec<LocalModelFile> {
-    let models_dir = base.join("models");
-    if let Err(err) = fs::create_dir_all(&models_dir) {
-        eprintln!("[models] Could not ensure models dir: {err}");
-        return Vec::new();
-    }
-
-    let entries = match fs::read_dir(&models_dir) {
-        Ok(read) => read,
-        Err(err) => {
-            eprintln!("[models] Could not read models dir: {err}");
-            return Vec::new();
-        }
-    };
-
-    let mut models = Vec::new();
-    for entry in entries.flatten() {
-        let path = entry.path();
-        if path.is_dir() {
-            continue;
-        }
-
-        let file_name = path
-            .file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or_default();
-        if file_name.starts_with('.') || file_name.eq_ignore_ascii_case("gitkeep") {
-            continue;
-        }
-
-        let name = path
-            .file_stem()
-            .and_then(|s| s.to_str())
-            .unwrap_or(file_name)
-            .to_string();
-        models.push(LocalModelFile { name, path });
-    }
-
-    models.sort_by(|a, b| a.name.to_lowercase().cmp(&b.name.to_lowercase()));
-    models
-}
-
 pub struct ChattyApp {
     pub settings: Settings,
     base_path: PathBuf,
@@ -151,10 +140,28 @@ pub struct ChattyApp {
     active_tab: usize,
     next_tab_id: usize,
     chat_input: String,
-    chat_log: Vec<(String, String)>,
+    chat_log: Vec<ChatMessage>,
+    /// Where `chat_log` is saved in the student's history.
+    chat_session: ChatSession,
+    /// Showing the student's saved chats instead of the open one.
+    history_open: bool,
+    history_sessions: Vec<ChatSession>,
+    history_status: Option<String>,
+    /// The student's saved chats, once their history PIN has been entered this session.
+    history_profile: Option<history::Profile>,
+    history_pin_input: String,
+    chat_export_status: Option<String>,
+    /// A chat or hint export being written on a worker thread, and which panel shows its
+    /// status.
+    export_job: Option<(ModelTask, mpsc::Receiver<String>)>,
+    /// Hints asked for the selected assignment, for export; restarted when the assignment
+    /// changes.
+    hint_session: ChatSession,
+    hint_assignment_id: Option<String>,
     theme: ThemeConfig,
     presets: Vec<ThemeConfig>,
     allow_external_process: bool,
+    policy: PolicyConfig,
     current_pack: Option<HomeworkPack>,
     submissions: Vec<SubmissionSummary>,
     selected_assignment: Option<String>,
@@ -165,7 +172,29 @@ pub struct ChattyApp {
     assignment_filter: Option<String>,
     subject_filter: Option<String>,
     submission_attachments: Vec<String>,
+    /// Questions being drafted for the pack builder on a worker thread.
+    authoring_job: Option<mpsc::Receiver<Result<String, String>>>,
+    authoring_status: Option<String>,
+    /// A chat reply being generated on a worker thread; None from the channel means it failed.
+    chat_job: Option<mpsc::Receiver<Option<StudentReply>>>,
+    /// Hints being generated on a worker thread, with the assignment and question they answer.
+    hint_job: Option<(
+        HomeworkAssignment,
+        String,
+        mpsc::Receiver<Option<StudentReply>>,
+    )>,
+    /// A submission being pre-marked and saved on a worker thread.
+    submission_job: Option<mpsc::Receiver<io::Result<PathBuf>>>,
+    submission_status: Option<String>,
     available_models: Vec<LocalModelFile>,
+    model_checks: HashMap<PathBuf, ChecksumStatus>,
+    /// Results from the checksum worker while "Verify checksums" is running.
+    model_verify: Option<mpsc::Receiver<(PathBuf, ChecksumStatus)>>,
+    /// A model switch waiting for its file to be re-hashed.
+    model_switch: Option<(LocalModelFile, mpsc::Receiver<io::Result<String>>)>,
+    model_status: Option<String>,
+    /// Why the hint model cannot use subject adapters, refreshed with the model routes.
+    adapter_error: Option<String>,
     teacher_unlocked: bool,
     teacher_pin_input: String,
     teacher_pin_new: String,
@@ -176,6 +205,13 @@ pub struct ChattyApp {
     homework_help_question: String,
     homework_help_response: Option<String>,
     homework_help_status: Option<String>,
+    homework_help_sources: Vec<String>,
+    /// Loaded from logs/inference_metrics.jsonl when the Settings tab first needs it.
+    metrics_summary: Option<Vec<MetricsSummary>>,
+    janet_allow_input: String,
+    roster_input: String,
+    /// New support contact being typed in Settings (name, detail).
+    support_input: (String, String),
 }
 
 impl ChattyApp {
@@ -200,6 +236,8 @@ impl ChattyApp {
             .as_ref()
             .and_then(|p| p.assignments.first().map(|a| a.id.clone()));
         let teacher_secret_question = settings.teacher_secret_question.clone();
+        let policy = load_policy(&base_path);
+        let adapter_error = local_model::adapter_support_error(settings.model_for(ModelTask::Hint));
 
         Ok(Self {
             settings,
@@ -225,9 +263,20 @@ impl ChattyApp {
             next_tab_id: 2,
             chat_input: String::new(),
             chat_log: Vec::new(),
+            chat_session: ChatSession::new(),
+            history_open: false,
+            history_sessions: Vec::new(),
+            history_status: None,
+            history_profile: None,
+            history_pin_input: String::new(),
+            chat_export_status: None,
+            export_job: None,
+            hint_session: ChatSession::new(),
+            hint_assignment_id: None,
             theme,
             presets,
             allow_external_process: false,
+            policy,
             current_pack: pack,
             submissions,
             selected_assignment: initial_selected,
@@ -243,12 +292,24 @@ impl ChattyApp {
                 allow_games: false,
                 allow_ai_premark: true,
                 max_score: "100".to_string(),
+                answer_key: String::new(),
             },
             selected_students: HashSet::new(),
             assignment_filter: None,
             subject_filter: None,
             submission_attachments: Vec::new(),
+            authoring_job: None,
+            authoring_status: None,
+            chat_job: None,
+            hint_job: None,
+            submission_job: None,
+            submission_status: None,
             available_models: models,
+            model_checks: HashMap::new(),
+            model_verify: None,
+            model_switch: None,
+            model_status: None,
+            adapter_error,
             teacher_unlocked: false,
             teacher_pin_input: String::new(),
             teacher_pin_new: String::new(),
@@ -259,24 +320,187 @@ impl ChattyApp {
             homework_help_question: String::new(),
             homework_help_response: None,
             homework_help_status: None,
+            metrics_summary: None,
+            janet_allow_input: String::new(),
+            roster_input: String::new(),
+            support_input: (String::new(), String::new()),
+            homework_help_sources: Vec::new(),
         })
     }
 
+    /// Re-pick each task's model after profiles, routes, approvals or the default model change.
+    fn refresh_model_routes(&mut self) {
+        self.settings.refresh_model_routes();
+        self.adapter_error =
+            local_model::adapter_support_error(self.settings.model_for(ModelTask::Hint));
+    }
+
     fn reload_modules(&mut self) {
         self.modules = load_modules(&self.base_path).unwrap_or_default();
     }
 
     fn reload_models(&mut self) {
         self.available_models = discover_local_models(&self.base_path);
+        self.model_checks.clear();
+    }
+
+    /// Hash the model files on a worker thread; multi-gigabyte files would otherwise freeze the
+    /// window. Results arrive in `poll_model_checks`.
+    fn verify_models(&mut self) {
+        if self.model_verify.is_some() {
+            return;
+        }
+        self.model_checks.clear();
+        let (tx, rx) = mpsc::channel();
+        let base = self.base_path.clone();
+        let models = self.available_models.clone();
+        let spawned = thread::Builder::new()
+            .name("model-verify".to_string())
+            .spawn(move || {
+                for model in models {
+                    let status = verify_model(&base, &model);
+                    if let ChecksumStatus::Mismatch { expected, actual } = &status {
+                        eprintln!(
+                            "[models] {} checksum mismatch: expected {expected}, got {actual}",
+                            model.file_name()
+                        );
+                    }
+                    if tx.send((model.path.clone(), status)).is_err() {
+                        break;
+                    }
+                }
+            });
+        match spawned {
+            Ok(_) => {
+                self.model_verify = Some(rx);
+                self.model_status = Some("Verifying checksums...".to_string());
+            }
+            Err(e) => self.model_status = Some(format!("Could not start verification: {e}")),
+        }
+    }
+
+    fn poll_model_checks(&mut self, ctx: &Context) {
+        let Some(rx) = &self.model_verify else {
+            return;
+        };
+        loop {
+            match rx.try_recv() {
+                Ok((path, status)) => {
+                    self.model_checks.insert(path, status);
+                }
+                Err(mpsc::TryRecvError::Empty) => {
+                    ctx.request_repaint_after(std::time::Duration::from_millis(200));
+                    return;
+                }
+                Err(mpsc::TryRecvError::Disconnected) => break,
+            }
+        }
+        self.model_verify = None;
+        self.model_status = Some("Checksum verification finished.".to_string());
     }
 
+    /// Start a switch to `model`. The file is hashed afresh on a worker thread: the hash cache
+    /// sits in `models/`, where a student could edit it to pass off an unapproved file.
     fn select_model(&mut self, model: &LocalModelFile) {
+        if model.path.to_string_lossy() == self.settings.model.path || self.model_switch.is_some() {
+            return;
+        }
+        let (tx, rx) = mpsc::channel();
+        let base = self.base_path.clone();
+        let path = model.path.clone();
+        let spawned = thread::Builder::new()
+            .name("model-switch".to_string())
+            .spawn(move || {
+                let _ = tx.send(file_sha256(&base, &path, true));
+            });
+        match spawned {
+            Ok(_) => {
+                self.model_switch = Some((model.clone(), rx));
+                self.model_status = Some(format!("Checking {}...", model.file_name()));
+            }
+            Err(e) => {
+                self.model_status = Some(format!("Could not check {}: {e}", model.file_name()))
+            }
+        }
+    }
+
+    fn poll_model_switch(&mut self, ctx: &Context) {
+        let Some((model, rx)) = &self.model_switch else {
+            return;
+        };
+        let result = match rx.try_recv() {
+            Ok(result) => result,
+            Err(mpsc::TryRecvError::Empty) => {
+                ctx.request_repaint_after(std::time::Duration::from_millis(200));
+                return;
+            }
+            Err(mpsc::TryRecvError::Disconnected) => {
+                Err(io::Error::other("the check stopped unexpectedly"))
+            }
+        };
+        let model = model.clone();
+        self.model_switch = None;
+        self.finish_model_switch(&model, result);
+    }
+
+    fn finish_model_switch(&mut self, model: &LocalModelFile, sha256: io::Result<String>) {
+        let new_path = model.path.to_string_lossy().to_string();
+        let sha256 = match sha256 {
+            Ok(h) => h,
+            Err(e) => {
+                self.model_status = Some(format!("Could not check {}: {e}", model.file_name()));
+                return;
+            }
+        };
+        let approved = approved_by_hash(&self.settings, &sha256).is_some();
+        if !self.teacher_unlocked && !approved {
+            self.model_status = Some(format!(
+                "{} is not on the approved model list. Ask your teacher to approve it.",
+                model.name
+            ));
+            return;
+        }
+
+        let previous = Path::new(&self.settings.model.path)
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
         self.settings.model.name = model.name.clone();
-        self.settings.model.path = model.path.to_string_lossy().to_string();
+        self.settings.model.path = new_path;
+        self.refresh_model_routes();
         local_model::clear_cached_model();
         if let Err(e) = save_settings(&self.settings, &self.base_path) {
             eprintln!("[models] Failed to save selected model: {e}");
         }
+        let detail = format!(
+            "{previous} -> {} (sha256 {sha256}, {}, by {})",
+            model.file_name(),
+            if approved { "approved" } else { "not approved" },
+            self.current_role()
+        );
+        if let Err(e) = audit::record(&self.base_path, "model_switch", &detail) {
+            eprintln!("[audit] Could not record model switch: {e}");
+        }
+        self.model_status = Some(format!("Now using {}.", model.name));
+    }
+
+    fn set_model_approval(&mut self, model: &LocalModelFile, approve: bool) {
+        if !self.teacher_unlocked {
+            return;
+        }
+        let result = if approve {
+            approve_model(&mut self.settings, &self.base_path, &model.file_name())
+        } else {
+            revoke_model(&mut self.settings, &self.base_path, &model.file_name())
+        };
+        self.refresh_model_routes();
+        self.model_status = Some(match result {
+            Ok(msg) => {
+                let _ = save_settings(&self.settings, &self.base_path);
+                msg
+            }
+            Err(e) => e,
+        });
     }
 
     fn current_role(&self) -> &str {
@@ -430,27 +654,68 @@ impl ChattyApp {
                     }
                     for model in models {
                         let selected = Path::new(&current_path) == model.path;
-                        let file_label = model
-                            .path
-                            .file_name()
-                            .and_then(|n| n.to_str())
-                            .map(|n| n.to_string())
-                            .unwrap_or_else(|| model.path.to_string_lossy().to_string());
-                        let label = format!("{} ({})", model.name, file_label);
-                        if ui.selectable_label(selected, label).clicked() {
+                        let approved = is_listed_approved(&self.settings, &model.file_name());
+                        let label = format!(
+                            "{} ({}){}",
+                            model.name,
+                            model.file_name(),
+                            if approved { "" } else { " - not approved" }
+                        );
+                        let response = ui
+                            .add_enabled(
+                                model.is_gguf(),
+                                egui::SelectableLabel::new(selected, label),
+                            )
+                            .on_hover_text(model.details());
+                        if response.clicked() {
                             self.select_model(&model);
                             ui.close_menu();
                         }
+                        ui.label(RichText::new(model.summary()).small());
+                        if self.teacher_unlocked && model.is_gguf() {
+                            let text = if approved {
+                                "Revoke approval"
+                            } else {
+                                "Approve for students"
+                            };
+                            if ui.small_button(text).clicked() {
+                                self.set_model_approval(&model, !approved);
+                            }
+                        }
+                        if let Some(status) = self.model_checks.get(&model.path) {
+                            let text = RichText::new(status.label()).small();
+                            match status {
+                                ChecksumStatus::Mismatch { .. } | ChecksumStatus::Failed(_) => {
+                                    ui.colored_label(self.warning_color(), text);
+                                }
+                                _ => {
+                                    ui.label(text);
+                                }
+                            }
+                        }
                     }
                     ui.separator();
                     if ui.button("Refresh models list").clicked() {
                         self.reload_models();
                         ui.close_menu();
                     }
+                    if ui
+                        .add_enabled(
+                            self.model_verify.is_none(),
+                            egui::Button::new("Verify checksums (models/manifest.json)"),
+                        )
+                        .on_hover_text("Hashes each model file; large files take a while.")
+                        .clicked()
+                    {
+                        self.verify_models();
+                    }
                     ui.label(format!(
                         "Folder: {}",
                         self.base_path.join("models").display()
                     ));
+                    if let Some(msg) = &self.model_status {
+                        ui.colored_label(self.warning_color(), msg);
+                    }
                 });
                 if ui.button("Quit").clicked() {
                     ctx.send_viewport_cmd(egui::ViewportCommand::Close);
@@ -907,8 +1172,25 @@ impl ChattyApp {
                 ui.label("Max score");
                 ui.text_edit_singleline(&mut self.draft_input.max_score);
             });
-            ui.label("Instructions");
+            ui.horizontal(|ui| {
+                ui.label("Instructions");
+                if ui
+                    .add_enabled(
+                        self.authoring_job.is_none(),
+                        egui::Button::new("Draft questions"),
+                    )
+                    .on_hover_text("Ask the authoring model for questions on this title, subject and year")
+                    .clicked()
+                {
+                    self.start_authoring();
+                }
+                if let Some(status) = &self.authoring_status {
+                    ui.label(status);
+                }
+            });
             ui.text_edit_multiline(&mut self.draft_input.instructions_md);
+            ui.label("Answer key / model answer (optional, checked against hints; only a fingerprint goes in the pack)");
+            ui.text_edit_multiline(&mut self.draft_input.answer_key);
 
             ui.horizontal(|ui| {
                 if ui.button("Add assignment to pack").clicked() {
@@ -933,10 +1215,16 @@ impl ChattyApp {
                             allow_games: self.draft_input.allow_games,
                             allow_ai_premark: self.draft_input.allow_ai_premark,
                             max_score,
+                            adapter: None,
+                            answer_fingerprint: hint_guard::fingerprint(
+                                &self.draft_input.answer_key,
+                                &self.draft_input.instructions_md,
+                            ),
                         };
                         self.draft_assignments.push(assignment);
                         self.draft_input.id =
                             format!("hw-{:03}", self.draft_assignments.len() + 1);
+                        self.draft_input.answer_key.clear();
                     }
                 }
 
@@ -1150,32 +1438,19 @@ impl ChattyApp {
                     self.submission_attachments.remove(idx);
                 }
             }
-            let disabled = self.selected_assignment.is_none();
+            let disabled = self.selected_assignment.is_none() || self.submission_job.is_some();
             let assign = self.selected_assignment.clone();
             if ui
                 .add_enabled(!disabled, egui::Button::new("Export submission file"))
                 .clicked()
             {
                 if let Some(id) = assign {
-                    match save_submission_with_answers(
-                        &self.base_path,
-                        &self.settings,
-                        &id,
-                        &self.submission_text,
-                        &self.submission_attachments,
-                    ) {
-                        Ok(path) => {
-                            let _ = ui.label(format!("Saved to {}", path.display()));
-                            self.submission_text.clear();
-                            self.submission_attachments.clear();
-                            self.resync_homework();
-                        }
-                        Err(e) => {
-                            let _ = ui.label(format!("Failed: {e}"));
-                        }
-                    }
+                    self.start_submission(id);
                 }
             }
+            if let Some(status) = &self.submission_status {
+                ui.label(status);
+            }
         } else {
             ui.label(
                 "No homework pack found. Drop a homework_pack*.json into homework/assigned/ and click Rescan.",
@@ -1211,9 +1486,333 @@ impl ChattyApp {
         });
     }
 
+    /// Export a chat or hint session to a file the student chooses. The dialog runs here; the
+    /// file is rendered and written on a worker thread and `poll_export` shows the outcome
+    /// next to the chat (`ModelTask::Chat`) or hint buttons.
+    fn export_session(
+        &mut self,
+        session: ChatSession,
+        task: ModelTask,
+        assignment: Option<HomeworkAssignment>,
+        name: &str,
+        format: ExportFormat,
+    ) {
+        if self.export_job.is_some() {
+            return;
+        }
+        // The student picks where the file goes, so it never lands in a folder other students
+        // on this computer can read.
+        let kind = match format {
+            ExportFormat::Markdown => "Markdown",
+            ExportFormat::Html => "HTML",
+        };
+        let status = match FileDialog::new()
+            .set_file_name(export::file_name(name, format))
+            .add_filter(kind, &[format.extension()])
+            .save_file()
+        {
+            Some(path) => {
+                let (tx, rx) = mpsc::channel();
+                let settings = self.settings.clone();
+                let policy = self.policy.clone();
+                let spawned = thread::Builder::new()
+                    .name("export".to_string())
+                    .spawn(move || {
+                        let status = panic::catch_unwind(|| {
+                            let model = if session.model.is_empty() {
+                                settings.model_for(task).name.as_str()
+                            } else {
+                                session.model.as_str()
+                            };
+                            let transcript = Transcript {
+                                kind: if task == ModelTask::Hint {
+                                    "Homework hints"
+                                } else {
+                                    "Chat"
+                                },
+                                model,
+                                started_at: &session.started_at,
+                                assignment: assignment.as_ref(),
+                                messages: &session.messages,
+                            };
+                            match export::render_transcript(&settings, &policy, &transcript, format)
+                                .and_then(|contents| fs::write(&path, contents))
+                            {
+                                Ok(()) => format!("Exported to {}", path.display()),
+                                Err(e) => format!("Could not export: {e}"),
+                            }
+                        });
+                        let _ = tx.send(status.unwrap_or_else(|_| {
+                            "Could not export: something went wrong.".to_string()
+                        }));
+                    });
+                match spawned {
+                    Ok(_) => {
+                        self.export_job = Some((task, rx));
+                        "Exporting...".to_string()
+                    }
+                    Err(e) => format!("Could not export: {e}"),
+                }
+            }
+            None => "Export cancelled.".to_string(),
+        };
+        self.set_export_status(task, status);
+    }
+
+    fn set_export_status(&mut self, task: ModelTask, status: String) {
+        if task == ModelTask::Hint {
+            self.homework_help_status = Some(status);
+        } else {
+            self.chat_export_status = Some(status);
+        }
+    }
+
+    fn poll_export(&mut self, ctx: &Context) {
+        let Some((task, rx)) = &self.export_job else {
+            return;
+        };
+        let task = *task;
+        let status = match rx.try_recv() {
+            Ok(status) => status,
+            Err(mpsc::TryRecvError::Empty) => {
+                ctx.request_repaint_after(std::time::Duration::from_millis(200));
+                return;
+            }
+            Err(mpsc::TryRecvError::Disconnected) => {
+                "Could not export: something went wrong.".to_string()
+            }
+        };
+        self.export_job = None;
+        self.set_export_status(task, status);
+    }
+
+    /// Markdown and HTML export buttons, disabled when the school's policy turns file export
+    /// off or an export is still being written. Returns the format clicked.
+    fn export_buttons(&self, ui: &mut egui::Ui, has_messages: bool) -> Option<ExportFormat> {
+        let allowed = self.policy.allow_file_export;
+        let mut clicked = None;
+        for (label, format) in [
+            ("Export Markdown", ExportFormat::Markdown),
+            ("Export HTML", ExportFormat::Html),
+        ] {
+            let button = ui.add_enabled(
+                allowed && has_messages && self.export_job.is_none(),
+                egui::Button::new(label),
+            );
+            let button = if allowed {
+                button
+            } else {
+                button.on_disabled_hover_text("File export is turned off by school policy.")
+            };
+            if button.clicked() {
+                clicked = Some(format);
+            }
+        }
+        clicked
+    }
+
+    fn new_chat(&mut self) {
+        self.chat_log.clear();
+        self.chat_session = ChatSession::new();
+        self.chat_export_status = None;
+    }
+
+    /// Chats are only saved once the student has opened their history with its PIN.
+    fn save_chat(&mut self) {
+        let Some(profile) = &self.history_profile else {
+            return;
+        };
+        if let Err(e) = history::save_chat(
+            &self.settings,
+            profile,
+            &mut self.chat_session,
+            &self.chat_log,
+        ) {
+            eprintln!("[history] Could not save chat: {e}");
+        }
+    }
+
+    fn open_history(&mut self) {
+        self.history_sessions = self
+            .history_profile
+            .as_ref()
+            .map(history::list_sessions)
+            .unwrap_or_default();
+        self.history_status = None;
+        self.history_open = true;
+    }
+
+    /// Close the open history when the student ID is edited, so the chat on screen is never
+    /// saved into (or shown beside) another profile's history.
+    fn check_history_profile(&mut self) {
+        if self
+            .history_profile
+            .as_ref()
+            .is_some_and(|p| !p.matches(&self.settings))
+        {
+            self.history_profile = None;
+            self.history_sessions.clear();
+            self.history_open = false;
+            self.history_status = None;
+            self.new_chat();
+        }
+    }
+
+    fn unlock_history(&mut self) {
+        match history::open_profile(&self.settings, &self.history_pin_input) {
+            Ok(profile) => {
+                history::purge_expired(&self.settings, &profile);
+                self.history_profile = Some(profile);
+                // The chat so far is kept too.
+                self.save_chat();
+                self.open_history();
+            }
+            Err(message) => self.history_status = Some(message),
+        }
+        self.history_pin_input.clear();
+    }
+
+    fn render_history_pin(&mut self, ui: &mut egui::Ui) {
+        let first_time = !history::has_pin(&self.settings);
+        ui.label(if first_time {
+            "Choose a PIN for your saved chats. Only someone with this PIN can open them."
+        } else {
+            "Enter your PIN to open your saved chats. Chats are only saved while they are open."
+        });
+        ui.horizontal(|ui| {
+            let input = ui.add(
+                egui::TextEdit::singleline(&mut self.history_pin_input)
+                    .password(true)
+                    .desired_width(100.0),
+            );
+            let submitted = input.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            let label = if first_time { "Set PIN" } else { "Open" };
+            if ui.button(label).clicked() || submitted {
+                self.unlock_history();
+            }
+        });
+        if let Some(status) = &self.history_status {
+            ui.label(status);
+        }
+    }
+
+    /// The student's saved chats. Not shown while the teacher is unlocked: history belongs to
+    /// the student profile, not the dashboard.
+    fn render_chat_history(&mut self, ui: &mut egui::Ui) {
+        let Some(profile) = self.history_profile.clone() else {
+            self.render_history_pin(ui);
+            return;
+        };
+        let config = &mut self.settings.chat_history;
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            changed |= ui
+                .checkbox(&mut config.enabled, "Save my chats on this computer")
+                .changed();
+            ui.label("Delete chats after");
+            changed |= ui
+                .add(egui::DragValue::new(&mut config.retention_days).clamp_range(0..=365))
+                .changed();
+            ui.label("days (0 = keep until I delete them)");
+        });
+        if changed {
+            if let Err(e) = save_settings(&self.settings, &self.base_path) {
+                eprintln!("[history] Could not save history settings: {e}");
+            }
+            if history::purge_expired(&self.settings, &profile) > 0 {
+                self.history_sessions = history::list_sessions(&profile);
+            }
+        }
+        ui.horizontal(|ui| {
+            if ui.button("Delete my history").clicked() {
+                self.history_status = Some(match history::delete_all(&profile) {
+                    Ok(count) => format!("Deleted {count} saved chats."),
+                    Err(e) => format!("Could not delete your history: {e}"),
+                });
+                self.history_sessions.clear();
+                self.new_chat();
+            }
+            if let Some(status) = &self.history_status {
+                ui.label(status);
+            }
+        });
+        ui.add_space(6.0);
+        if self.history_sessions.is_empty() {
+            ui.label("No saved chats.");
+            return;
+        }
+        let mut open = None;
+        let mut delete = None;
+        ScrollArea::vertical()
+            .auto_shrink([false; 2])
+            .show(ui, |ui| {
+                for (idx, session) in self.history_sessions.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        if ui.button("Open").clicked() {
+                            open = Some(idx);
+                        }
+                        if ui.small_button("Delete").clicked() {
+                            delete = Some(idx);
+                        }
+                        ui.label(
+                            RichText::new(session.when())
+                                .color(color_from_hex(&self.theme.muted_text)),
+                        );
+                        ui.label(session.title());
+                    });
+                }
+            });
+        if let Some(idx) = open {
+            let session = self.history_sessions[idx].clone();
+            self.chat_log = session.messages.clone();
+            self.chat_session = session;
+            self.history_open = false;
+        } else if let Some(idx) = delete {
+            let session = self.history_sessions.remove(idx);
+            if let Err(e) = history::delete_session(&profile, &session.id) {
+                self.history_status = Some(format!("Could not delete that chat: {e}"));
+            }
+            if session.id == self.chat_session.id {
+                self.new_chat();
+            }
+        }
+    }
+
     fn render_chat(&mut self, ui: &mut egui::Ui) {
         ui.heading("Chat");
+        ui.horizontal(|ui| {
+            if ui.button("New chat").clicked() {
+                self.new_chat();
+                self.history_open = false;
+            }
+            if !self.teacher_unlocked {
+                let label = if self.history_open {
+                    "Back to chat"
+                } else {
+                    "My chat history"
+                };
+                if ui.button(label).clicked() {
+                    if self.history_open {
+                        self.history_open = false;
+                    } else {
+                        self.open_history();
+                    }
+                }
+            }
+            if let Some(format) = self.export_buttons(ui, !self.chat_log.is_empty()) {
+                let mut session = self.chat_session.clone();
+                session.messages = self.chat_log.clone();
+                self.export_session(session, ModelTask::Chat, None, "chat", format);
+            }
+            if let Some(status) = &self.chat_export_status {
+                ui.label(status);
+            }
+        });
         ui.add_space(6.0);
+        if self.history_open && !self.teacher_unlocked {
+            self.render_chat_history(ui);
+            return;
+        }
         let log_height = ui.available_height();
         ScrollArea::vertical()
             .auto_shrink([false; 2])
@@ -1224,8 +1823,8 @@ impl ChattyApp {
                 ui.set_min_height(log_height);
                 let max_width = ui.available_width() * 0.96;
                 ui.set_max_width(max_width);
-                for (sender, msg) in &self.chat_log {
-                    let is_user = sender.eq_ignore_ascii_case("you");
+                for message in &self.chat_log {
+                    let is_user = message.is_student();
                     let bubble_fill = if is_user {
                         color_from_hex(&self.theme.accent_soft)
                     } else {
@@ -1261,17 +1860,28 @@ impl ChattyApp {
                             .show(ui, |ui| {
                                 ui.set_max_width(max_width * 0.9);
                                 ui.label(
-                                    RichText::new(sender.clone())
+                                    RichText::new(message.sender.clone())
                                         .strong()
                                         .color(name_color),
                                 );
                                 ui.add_space(4.0);
                                 ui.add(
                                     egui::Label::new(
-                                        RichText::new(msg).color(text_color),
+                                        RichText::new(&message.text).color(text_color),
                                     )
                                     .wrap(true),
                                 );
+                                if !message.sources.is_empty() {
+                                    ui.label(
+                                        RichText::new(format!(
+                                            "Sources: {}",
+                                            message.sources.join(", ")
+                                        ))
+                                        .small()
+                                        .italics()
+                                        .color(name_color),
+                                    );
+                                }
                             });
                     });
                 }
@@ -1306,30 +1916,619 @@ impl ChattyApp {
                 &mut self.settings.janet.enabled,
                 "Enable Janet safety filter",
             );
-            ui.checkbox(
-                &mut self.settings.janet.block_swears,
-                "Block swears and rude words",
-            );
-            ui.checkbox(
-                &mut self.settings.janet.block_mature_topics,
-                "Block mature topics",
-            );
+            self.render_janet_categories(ui);
+            self.render_janet_allowlist(ui);
             ui.separator();
-            ui.checkbox(
-                &mut self.settings.homework_hints_only,
-                "Homework help gives hints only (no full answers)",
-            );
+            self.render_wellbeing(ui);
             ui.separator();
-            ui.checkbox(&mut self.settings.game.enabled, "Enable games");
-            ui.checkbox(
-                &mut self.settings.game.games_in_class_allowed,
-                "Allow games in class",
-            );
-        }
-        if ui.button("Save settings").clicked() {
-            let _ = save_settings(&self.settings, &self.base_path);
-            ui.label("Saved");
+            self.render_redaction(ui);
+            ui.separator();
+            ui.label("Inference backend");
+            let mut backend_changed = false;
+            egui::ComboBox::from_id_source("model_backend")
+                .selected_text(self.settings.model.backend.label())
+                .show_ui(ui, |ui| {
+                    for backend in [
+                        ModelBackend::LlamaCpp,
+                        ModelBackend::Mock,
+                        ModelBackend::LocalHttp,
+                    ] {
+                        if ui
+                            .selectable_value(
+                                &mut self.settings.model.backend,
+                                backend,
+                                backend.label(),
+                            )
+                            .changed()
+                        {
+                            local_model::clear_cached_model();
+                            backend_changed = true;
+                        }
+                    }
+                });
+            if backend_changed {
+                self.refresh_model_routes();
+            }
+            if self.settings.model.backend == ModelBackend::LlamaCpp {
+                ui.horizontal(|ui| {
+                    ui.label("Unload model after idle (minutes, 0 = never)");
+                    let mut minutes = self.settings.model.idle_unload_secs / 60;
+                    if ui
+                        .add(egui::DragValue::new(&mut minutes).clamp_range(0..=240))
+                        .changed()
+                    {
+                        self.settings.model.idle_unload_secs = minutes * 60;
+                    }
+                });
+                ui.checkbox(
+                    &mut self.settings.model.allow_low_memory_load,
+                    "Load models even when free memory looks too low",
+                );
+            }
+            if self.settings.model.backend == ModelBackend::Mock {
+                ui.horizontal(|ui| {
+                    ui.label("Reply script (JSON, empty = built-in demo)");
+                    ui.text_edit_singleline(&mut self.settings.model.script);
+                });
+            }
+            if self.settings.model.backend == ModelBackend::LocalHttp {
+                ui.horizontal(|ui| {
+                    ui.label("Server endpoint");
+                    ui.text_edit_singleline(&mut self.settings.model.endpoint);
+                });
+                if !self.policy.allow_localhost_inference {
+                    ui.colored_label(
+                        self.warning_color(),
+                        "Blocked by policy: set allow_localhost_inference in config/policy.json.",
+                    );
+                }
+            }
+            ui.separator();
+            self.render_performance(ui);
+            ui.separator();
+            self.render_model_routing(ui);
+            ui.separator();
+            self.render_budget_settings(ui);
+            ui.separator();
+            self.render_cache_settings(ui);
+            ui.separator();
+            ui.checkbox(
+                &mut self.settings.homework_hints_only,
+                "Homework help gives hints only (no full answers)",
+            );
+            ui.separator();
+            ui.checkbox(&mut self.settings.game.enabled, "Enable games");
+            ui.checkbox(
+                &mut self.settings.game.games_in_class_allowed,
+                "Allow games in class",
+            );
+        }
+        if ui.button("Save settings").clicked() {
+            let _ = save_settings(&self.settings, &self.base_path);
+            ui.label("Saved");
+        }
+    }
+
+    fn render_janet_categories(&mut self, ui: &mut egui::Ui) {
+        let disabled = &mut self.settings.janet.disabled_categories;
+        for category in safety::categories() {
+            let mut on = !disabled.contains(&category.name);
+            let label = format!("{} ({})", category.display_name(), category.action.label());
+            if ui.checkbox(&mut on, label).changed() {
+                if on {
+                    disabled.retain(|c| c != &category.name);
+                } else {
+                    disabled.push(category.name.clone());
+                }
+            }
+        }
+        ui.horizontal(|ui| {
+            ui.label("Model safety check");
+            egui::ComboBox::from_id_source("janet_classifier")
+                .selected_text(self.settings.janet.classifier.label())
+                .show_ui(ui, |ui| {
+                    for mode in JanetClassifier::ALL {
+                        ui.selectable_value(
+                            &mut self.settings.janet.classifier,
+                            mode,
+                            mode.label(),
+                        );
+                    }
+                });
+        })
+        .response
+        .on_hover_text("Uses the Safety check model route. Word lists only is fastest.");
+        ui.checkbox(
+            &mut self.settings.janet.injection_model_check,
+            "Model check of hint questions for rule overrides",
+        )
+        .on_hover_text(
+            "Pattern rules always run. This also asks the Safety check model, for rewordings they miss.",
+        );
+        ui.horizontal_wrapped(|ui| {
+            ui.label("Also filter in:");
+            let locales = &mut self.settings.janet.locales;
+            for code in safety::available_locales() {
+                let mut on = locales.contains(&code);
+                if ui.checkbox(&mut on, code.as_str()).changed() {
+                    if on {
+                        locales.push(code);
+                    } else {
+                        locales.retain(|c| c != &code);
+                    }
+                }
+            }
+        });
+        if ui
+            .button("Reload Janet policies")
+            .on_hover_text("Re-read config/janet/*.json after editing them")
+            .clicked()
+        {
+            safety::load_policies(&self.base_path);
+        }
+    }
+
+    fn render_wellbeing(&mut self, ui: &mut egui::Ui) {
+        ui.label(RichText::new("Wellbeing support").strong());
+        ui.label("Shown with Janet's calm reply when a student writes about self-harm or abuse.");
+        let mut config = wellbeing::support_config();
+        let mut changed = false;
+        let mut remove = None;
+        egui::Grid::new("support_contacts")
+            .num_columns(3)
+            .show(ui, |ui| {
+                for (idx, contact) in config.contacts.iter().enumerate() {
+                    ui.label(&contact.name);
+                    ui.label(&contact.detail);
+                    if ui.small_button("Remove").clicked() {
+                        remove = Some(idx);
+                    }
+                    ui.end_row();
+                }
+            });
+        if let Some(idx) = remove {
+            config.contacts.remove(idx);
+            changed = true;
+        }
+        ui.horizontal(|ui| {
+            let (name, detail) = &mut self.support_input;
+            ui.add(egui::TextEdit::singleline(name).hint_text("Counsellor, helpline..."));
+            ui.add(egui::TextEdit::singleline(detail).hint_text("Room, hours or number"));
+            if ui.button("Add contact").clicked() && !name.trim().is_empty() {
+                config.contacts.push(SupportContact {
+                    name: name.trim().to_string(),
+                    detail: detail.trim().to_string(),
+                });
+                name.clear();
+                detail.clear();
+                changed = true;
+            }
+        });
+        if changed {
+            if let Err(e) = wellbeing::save_support(&self.base_path, &config) {
+                eprintln!("[wellbeing] Could not save support contacts: {e}");
+            }
+        }
+
+        ui.checkbox(
+            &mut self.settings.janet.wellbeing_counter,
+            "Count wellbeing replies per week (no names or messages are kept)",
+        );
+        let counts = wellbeing::weekly_counts(&self.base_path);
+        if !counts.is_empty() {
+            ui.horizontal_wrapped(|ui| {
+                for (week, count) in &counts {
+                    ui.label(format!("{week}: {count}"));
+                }
+            });
+            if ui.button("Clear counts").clicked() {
+                if let Err(e) = wellbeing::clear_counts(&self.base_path) {
+                    eprintln!("[wellbeing] Could not clear counts: {e}");
+                }
+            }
+        }
+    }
+
+    fn render_janet_allowlist(&mut self, ui: &mut egui::Ui) {
+        ui.label("Words Janet should allow (e.g. a local place name); click one to remove it");
+        let mut remove = None;
+        ui.horizontal_wrapped(|ui| {
+            for (idx, word) in self.settings.janet.allowlist.iter().enumerate() {
+                if ui.small_button(format!("{word} x")).clicked() {
+                    remove = Some(idx);
+                }
+            }
+        });
+        if let Some(idx) = remove {
+            self.settings.janet.allowlist.remove(idx);
+        }
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut self.janet_allow_input);
+            if ui.button("Allow word").clicked() {
+                let word = self.janet_allow_input.trim().to_string();
+                if !word.is_empty()
+                    && !self
+                        .settings
+                        .janet
+                        .allowlist
+                        .iter()
+                        .any(|w| w.eq_ignore_ascii_case(&word))
+                {
+                    self.settings.janet.allowlist.push(word);
+                }
+                self.janet_allow_input.clear();
+            }
+        });
+    }
+
+    fn render_redaction(&mut self, ui: &mut egui::Ui) {
+        ui.label(RichText::new("Privacy redaction").strong());
+        ui.label(
+            "Removed from student text before it is stored, logged or shared through the cache.",
+        );
+        let config = &mut self.settings.redaction;
+        ui.horizontal_wrapped(|ui| {
+            ui.checkbox(&mut config.emails, "Emails");
+            ui.checkbox(&mut config.phones, "Phone numbers");
+            ui.checkbox(&mut config.addresses, "Street addresses");
+            ui.checkbox(&mut config.roster_names, "Roster names");
+        });
+        ui.label(
+            "Roster names (the student's own name is always included); click one to remove it",
+        );
+        let mut remove = None;
+        ui.horizontal_wrapped(|ui| {
+            for (idx, name) in config.roster.iter().enumerate() {
+                if ui.small_button(format!("{name} x")).clicked() {
+                    remove = Some(idx);
+                }
+            }
+        });
+        if let Some(idx) = remove {
+            config.roster.remove(idx);
         }
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut self.roster_input);
+            if ui.button("Add name").clicked() {
+                let name = self.roster_input.trim().to_string();
+                if !name.is_empty() && !config.roster.iter().any(|n| n.eq_ignore_ascii_case(&name))
+                {
+                    config.roster.push(name);
+                }
+                self.roster_input.clear();
+            }
+        });
+    }
+
+    fn render_performance(&mut self, ui: &mut egui::Ui) {
+        ui.label(RichText::new("Performance").strong());
+        ui.horizontal(|ui| {
+            ui.label("CPU threads (0 = auto)");
+            ui.add(egui::DragValue::new(&mut self.settings.model.n_threads).clamp_range(0..=256));
+            ui.label("Batch size (0 = default)");
+            ui.add(
+                egui::DragValue::new(&mut self.settings.model.n_batch)
+                    .clamp_range(0..=4096)
+                    .speed(16),
+            );
+        });
+        if ui.button("Refresh timings").clicked() {
+            self.metrics_summary = None;
+        }
+        let summary = self
+            .metrics_summary
+            .get_or_insert_with(|| local_model::summarize_metrics(&self.base_path));
+        if summary.is_empty() {
+            ui.label("No requests recorded yet.");
+            return;
+        }
+        let ms = |v: Option<f64>| {
+            v.map(|v| format!("{v:.0} ms"))
+                .unwrap_or_else(|| "-".into())
+        };
+        let setting = |v: u32| {
+            if v == 0 {
+                "auto".to_string()
+            } else {
+                v.to_string()
+            }
+        };
+        egui::Grid::new("inference_metrics")
+            .num_columns(7)
+            .striped(true)
+            .show(ui, |ui| {
+                for heading in [
+                    "Model",
+                    "Threads/batch",
+                    "Requests",
+                    "Load",
+                    "Prompt",
+                    "Tokens/s",
+                    "Memory per request",
+                ] {
+                    let label = ui.label(RichText::new(heading).strong());
+                    if heading == "Memory per request" {
+                        label.on_hover_text(
+                            "Average change in the app's memory over a request (model loads included), and the highest the app's memory reached during one request.",
+                        );
+                    }
+                }
+                ui.end_row();
+                for row in summary.iter() {
+                    ui.label(format!("{} ({})", row.model, row.backend));
+                    ui.label(format!("{}/{}", setting(row.n_threads), setting(row.n_batch)));
+                    ui.label(if row.failures > 0 {
+                        format!("{} ({} failed)", row.requests, row.failures)
+                    } else {
+                        row.requests.to_string()
+                    });
+                    ui.label(ms(row.mean_load_ms));
+                    ui.label(ms(row.mean_prompt_eval_ms));
+                    ui.label(
+                        row.mean_tokens_per_sec
+                            .map(|v| format!("{v:.1}"))
+                            .unwrap_or_else(|| "-".into()),
+                    );
+                    ui.label(local_model::memory_per_request(row));
+                    ui.end_row();
+                }
+            });
+        ui.label("Averages of the latest requests in logs/inference_metrics.jsonl.");
+    }
+
+    fn render_model_routing(&mut self, ui: &mut egui::Ui) {
+        ui.label(RichText::new("Model profiles").strong());
+        ui.label("Keep several models and choose which one each task uses. The model picked in File -> Models is the default.");
+        let mut remove = None;
+        let mut routes_changed = false;
+        for (idx, profile) in self.settings.model_profiles.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                routes_changed |= ui
+                    .add(egui::TextEdit::singleline(&mut profile.name).desired_width(120.0))
+                    .changed();
+                let file = Path::new(&profile.path)
+                    .file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or_else(|| profile.path.clone());
+                ui.label(format!("{} | {}", profile.backend.label(), file));
+                ui.label("max tokens");
+                ui.add(egui::DragValue::new(&mut profile.max_tokens).clamp_range(16..=4096));
+                if !profile.is_available() {
+                    ui.colored_label(egui::Color32::YELLOW, "file missing");
+                }
+                if ui.button("Remove").clicked() {
+                    remove = Some(idx);
+                }
+            });
+        }
+        if let Some(idx) = remove {
+            let name = self.settings.model_profiles.remove(idx).name;
+            for route in self.settings.model_routes.values_mut() {
+                route.retain(|n| n != &name);
+            }
+            routes_changed = true;
+        }
+        if ui.button("Add current model as a profile").clicked() {
+            let mut profile = self.settings.model.clone();
+            let base_name = profile.name.clone();
+            let mut n = 2;
+            while self
+                .settings
+                .model_profiles
+                .iter()
+                .any(|p| p.name == profile.name)
+            {
+                profile.name = format!("{base_name}-{n}");
+                n += 1;
+            }
+            let detail = format!(
+                "{} ({}) by {}",
+                profile.name,
+                profile.path,
+                self.current_role()
+            );
+            if let Err(e) = audit::record(&self.base_path, "model_profile_added", &detail) {
+                eprintln!("[audit] Could not record new profile: {e}");
+            }
+            self.settings.model_profiles.push(profile);
+            routes_changed = true;
+        }
+
+        let names: Vec<String> = self
+            .settings
+            .model_profiles
+            .iter()
+            .map(|p| p.name.clone())
+            .collect();
+        egui::Grid::new("model_routes")
+            .num_columns(3)
+            .show(ui, |ui| {
+                for task in ModelTask::ALL {
+                    ui.label(task.label());
+                    let route = self.settings.model_routes.entry(task).or_default();
+                    for slot in 0..2 {
+                        if slot > route.len() {
+                            break;
+                        }
+                        let current = route.get(slot).cloned();
+                        let fallback_label = if slot == 0 {
+                            "Default model"
+                        } else {
+                            "(no fallback)"
+                        };
+                        let mut choice = current.clone();
+                        egui::ComboBox::from_id_source(("model_route", task.label(), slot))
+                            .selected_text(current.as_deref().unwrap_or(fallback_label))
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut choice, None, fallback_label);
+                                for name in &names {
+                                    ui.selectable_value(&mut choice, Some(name.clone()), name);
+                                }
+                            });
+                        if choice != current {
+                            routes_changed = true;
+                            match choice {
+                                Some(name) if slot < route.len() => route[slot] = name,
+                                Some(name) => route.push(name),
+                                None => route.truncate(slot),
+                            }
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+        self.settings
+            .model_routes
+            .retain(|_, route| !route.is_empty());
+        if routes_changed {
+            self.refresh_model_routes();
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Memory for loaded models (MB, 0 = automatic)");
+            if ui
+                .add(
+                    egui::DragValue::new(&mut self.settings.model_memory_budget_mb)
+                        .clamp_range(0..=262_144)
+                        .speed(64),
+                )
+                .changed()
+            {
+                local_model::set_memory_budget_mb(self.settings.model_memory_budget_mb);
+            }
+        });
+
+        ui.label(RichText::new("Subject adapters").strong());
+        ui.label("LoRA adapter files in models/ used for a subject's homework help. Switching adapters needs the local server backend (llama-server started with --lora).");
+        if let Some(err) = &self.adapter_error {
+            if !self.settings.subject_adapters.is_empty() {
+                ui.colored_label(
+                    self.warning_color(),
+                    format!("{err} Hints for these subjects will fail until then."),
+                );
+            }
+        }
+        let mut subjects: Vec<String> = self.settings.subject_adapters.keys().cloned().collect();
+        if let Some(pack) = &self.current_pack {
+            for assignment in &pack.assignments {
+                if !subjects
+                    .iter()
+                    .any(|s| s.eq_ignore_ascii_case(&assignment.subject))
+                {
+                    subjects.push(assignment.subject.clone());
+                }
+            }
+        }
+        egui::Grid::new("subject_adapters")
+            .num_columns(2)
+            .show(ui, |ui| {
+                for subject in subjects {
+                    ui.label(&subject);
+                    let mut file = self
+                        .settings
+                        .subject_adapters
+                        .get(&subject)
+                        .cloned()
+                        .unwrap_or_default();
+                    let edit = egui::TextEdit::singleline(&mut file)
+                        .hint_text("none")
+                        .desired_width(200.0);
+                    if ui.add(edit).changed() {
+                        if file.trim().is_empty() {
+                            self.settings.subject_adapters.remove(&subject);
+                        } else {
+                            self.settings
+                                .subject_adapters
+                                .insert(subject, file.trim().to_string());
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+    }
+
+    fn render_budget_settings(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(
+            &mut self.settings.budget.enabled,
+            "Limit model use per student in class mode",
+        );
+        if !self.settings.budget.enabled {
+            return;
+        }
+        ui.label("Default limits (0 = unlimited)");
+        budget_limit_fields(ui, "budget_default", &mut self.settings.budget.limits);
+
+        let student_id = self.settings.student.student_id.trim().to_string();
+        if student_id.is_empty() {
+            return;
+        }
+        let mut custom = self.settings.budget.per_student.contains_key(&student_id);
+        if ui
+            .checkbox(&mut custom, format!("Custom limits for {student_id}"))
+            .changed()
+        {
+            if custom {
+                let defaults = self.settings.budget.limits;
+                self.settings
+                    .budget
+                    .per_student
+                    .insert(student_id.clone(), defaults);
+            } else {
+                self.settings.budget.per_student.remove(&student_id);
+            }
+        }
+        if let Some(limits) = self.settings.budget.per_student.get_mut(&student_id) {
+            budget_limit_fields(ui, "budget_student", limits);
+        }
+        let session_student = self.settings.session_student_id.clone();
+        let (recent, tokens) = budget::usage_for(&session_student);
+        ui.horizontal(|ui| {
+            ui.label(format!(
+                "Used by {session_student}: {recent} requests in the last minute, {tokens} tokens this session"
+            ));
+            if ui.button("Reset usage").clicked() {
+                budget::reset(Some(&session_student));
+            }
+        });
+        ui.label(
+            RichText::new(
+                "Usage is charged to the student ID Chatty started with, so changing the ID does not reset it. It is kept in memory only and resets when Chatty restarts.",
+            )
+            .small(),
+        );
+    }
+
+    fn render_cache_settings(&mut self, ui: &mut egui::Ui) {
+        let cache = &mut self.settings.response_cache;
+        if ui
+            .checkbox(
+                &mut cache.enabled,
+                "Share answers to repeated questions (same assignment and model)",
+            )
+            .changed()
+            && !cache.enabled
+        {
+            response_cache::clear();
+        }
+        if !cache.enabled {
+            return;
+        }
+        ui.horizontal(|ui| {
+            ui.label("Keep answers for (s)");
+            ui.add(
+                egui::DragValue::new(&mut cache.ttl_secs)
+                    .clamp_range(10..=86_400)
+                    .speed(10),
+            );
+            ui.label("Max answers");
+            ui.add(egui::DragValue::new(&mut cache.max_entries).clamp_range(1..=10_000));
+        });
+        ui.horizontal(|ui| {
+            ui.label(format!("{} answers cached", response_cache::len()));
+            if ui.button("Clear").clicked() {
+                response_cache::clear();
+            }
+        });
     }
 
     fn sanitize_short(text: &str, max_lines: usize, max_len: usize) -> String {
@@ -1349,12 +2548,43 @@ impl ChattyApp {
         if out.is_empty() {
             out = text.trim().to_string();
         }
-        if out.len() > max_len {
-            out.truncate(max_len);
+        // Cut on a character boundary; truncating inside a multi-byte character panics.
+        if let Some((cut, _)) = out.char_indices().nth(max_len) {
+            out.truncate(cut);
         }
         out
     }
 
+    fn render_model_indicator(&self, ui: &mut egui::Ui) {
+        let uses_llama = std::iter::once(&self.settings.model)
+            .chain(&self.settings.model_profiles)
+            .any(|m| m.backend == ModelBackend::LlamaCpp);
+        if !uses_llama {
+            ui.label(
+                RichText::new(format!("Model: {}", self.settings.model.backend.label()))
+                    .color(color_from_hex(&self.theme.muted_text)),
+            );
+            return;
+        }
+        let status = local_model::model_status();
+        let response = match &status.loaded {
+            Some(name) => {
+                let idle_min = status.idle.map(|d| d.as_secs() / 60).unwrap_or(0);
+                ui.label(
+                    RichText::new(format!("Model loaded: {name} (idle {idle_min} min)"))
+                        .color(color_from_hex(&self.theme.accent)),
+                )
+            }
+            None => ui.label(
+                RichText::new("Model not loaded (loads on first question)")
+                    .color(color_from_hex(&self.theme.muted_text)),
+            ),
+        };
+        if let Some(warning) = &status.warning {
+            response.on_hover_text(warning);
+        }
+    }
+
     fn warning_color(&self) -> egui::Color32 {
         if self.theme.name.eq_ignore_ascii_case("classic_light") {
             color_from_hex(&self.theme.accent)
@@ -1374,6 +2604,18 @@ impl ChattyApp {
                         pack.class_id,
                         pack.assignments.len()
                     ));
+                    let caught: Vec<String> = pack
+                        .assignments
+                        .iter()
+                        .filter_map(|a| {
+                            let count = injection::detection_count(&self.base_path, &a.id);
+                            (count > 0).then(|| format!("{} ({count})", a.title))
+                        })
+                        .collect();
+                    if !caught.is_empty() {
+                        ui.label(format!("Hint rule overrides caught: {}", caught.join(", ")))
+                            .on_hover_text("Anonymous counts from each assignment's event chain.");
+                    }
                 } else {
                     ui.label("No pack loaded yet. Import a pack to see class metrics.");
                 }
@@ -1585,7 +2827,11 @@ impl ChattyApp {
             ModuleEntry::Markdown { path } => {
                 if cached_text.is_none() {
                     let full_path = module.folder.join(path);
-                    *cached_text = fs::read_to_string(&full_path).ok();
+                    // Module pages are shown to students, so they pass the same output check.
+                    *cached_text = fs::read_to_string(&full_path).ok().map(|text| {
+                        safety::check_output(&self.settings, &text)
+                            .unwrap_or_else(|message| message)
+                    });
                 }
                 if let Some(text) = cached_text {
                     render_markdown(ui, text);
@@ -1649,54 +2895,69 @@ impl ChattyApp {
                 )
                 .desired_width(f32::INFINITY),
         );
-        let get_hints = ui.add_enabled(assignment_opt.is_some(), egui::Button::new("Get hints"));
+        let get_hints = ui.add_enabled(self.hint_job.is_none(), egui::Button::new("Get hints"));
         if get_hints.clicked() {
             let question = self.homework_help_question.trim().to_string();
             if question.is_empty() {
                 self.homework_help_status = Some("Type a question first.".to_string());
             } else {
-                self.homework_help_status = Some("Generating hints...".to_string());
-                let prompt = format!(
-                    "{capsule}\nAssignment: {id} - {title}\nSubject: {subject}\nYear: {year}\nDue: {due}\nInstructions:\n{instr}\nStudent question: {q}\nRespond with one short hint (guiding question, steps, or reminder). Never provide the full answer.",
-                    capsule = HINT_CAPSULE,
-                    id = assignment.id,
-                    title = assignment.title,
-                    subject = assignment.subject,
-                    year = assignment.year_level,
-                    due = assignment.due_at.clone().unwrap_or_else(|| "not set".to_string()),
-                    instr = assignment.instructions_md,
-                    q = question
-                );
-                let result = panic::catch_unwind({
-                    let settings = self.settings.clone();
-                    move || {
-                        let raw = generate_answer(&settings, &prompt);
-                        raw
-                    }
-                });
-                match result {
-                    Ok(text) => {
-                        self.homework_help_response =
-                            Some(Self::sanitize_short(&text, 4, 400));
-                        self.homework_help_status = Some("Hints ready.".to_string());
-                    }
-                    Err(_) => {
-                        self.homework_help_response = None;
-                        self.homework_help_status =
-                            Some("Sorry, something went wrong while generating hints.".to_string());
-                    }
-                }
+                self.start_hint(assignment.clone(), question);
             }
         }
         if let Some(status) = &self.homework_help_status {
             ui.label(status);
         }
+        let has_hints = self.hint_assignment_id.as_deref() == Some(assignment.id.as_str())
+            && !self.hint_session.messages.is_empty();
+        ui.horizontal(|ui| {
+            ui.label("Keep these hints for revision:");
+            if let Some(format) = self.export_buttons(ui, has_hints) {
+                let name = format!("hints_{}", assignment.id);
+                self.export_session(
+                    self.hint_session.clone(),
+                    ModelTask::Hint,
+                    Some(assignment.clone()),
+                    &name,
+                    format,
+                );
+            }
+        });
         if let Some(resp) = &self.homework_help_response {
             ui.add_space(4.0);
             ui.label(RichText::new(resp).color(color_from_hex(&self.theme.text)));
+            if !self.homework_help_sources.is_empty() {
+                ui.label(
+                    RichText::new(format!(
+                        "Drew on: {}",
+                        self.homework_help_sources.join(", ")
+                    ))
+                    .small()
+                    .italics(),
+                );
+            }
         }
     }
 
+    fn record_hint(
+        &mut self,
+        assignment_id: &str,
+        question: &str,
+        reply: &str,
+        sources: &[String],
+    ) {
+        if self.hint_assignment_id.as_deref() != Some(assignment_id) {
+            self.hint_session = ChatSession::new();
+            self.hint_assignment_id = Some(assignment_id.to_string());
+        }
+        self.hint_session.model = self.settings.model_for(ModelTask::Hint).name.clone();
+        self.hint_session
+            .messages
+            .push(ChatMessage::new("You", question));
+        let mut answer = ChatMessage::new("Chatty", reply);
+        answer.sources = sources.to_vec();
+        self.hint_session.messages.push(answer);
+    }
+
     fn render_submission_area(&mut self, ui: &mut egui::Ui) {
         ui.label("Type your work and export a submission file to upload via the portal.");
         ui.add(
@@ -1732,34 +2993,140 @@ impl ChattyApp {
                 self.submission_attachments.remove(idx);
             }
         }
-        let disabled = self.selected_assignment.is_none();
+        let disabled = self.selected_assignment.is_none() || self.submission_job.is_some();
         let assign = self.selected_assignment.clone();
         if ui
             .add_enabled(!disabled, egui::Button::new("Export submission file"))
             .clicked()
         {
             if let Some(id) = assign {
-                match save_submission_with_answers(
-                    &self.base_path,
-                    &self.settings,
-                    &id,
-                    &self.submission_text,
-                    &self.submission_attachments,
-                ) {
-                    Ok(path) => {
-                        let _ = ui.label(format!("Saved to {}", path.display()));
-                        self.submission_text.clear();
-                        self.submission_attachments.clear();
-                        self.resync_homework();
-                    }
-                    Err(e) => {
-                        let _ = ui.label(format!("Failed: {e}"));
-                    }
+                self.start_submission(id);
+            }
+        }
+        if let Some(status) = &self.submission_status {
+            ui.label(status);
+        }
+    }
+
+    /// Draft questions for the pack builder on a worker thread; `poll_authoring` adds them to
+    /// the instructions.
+    fn start_authoring(&mut self) {
+        let (tx, rx) = mpsc::channel();
+        let settings = self.settings.clone();
+        let draft = self.draft_input.clone();
+        let spawned = thread::Builder::new()
+            .name("authoring".to_string())
+            .spawn(move || {
+                let drafted = panic::catch_unwind(|| {
+                    generate_questions(
+                        &settings,
+                        draft.title.trim(),
+                        draft.subject.trim(),
+                        draft.year_level.trim(),
+                        5,
+                    )
+                })
+                .unwrap_or_else(|_| Err("drafting failed".to_string()));
+                let _ = tx.send(drafted);
+            });
+        match spawned {
+            Ok(_) => {
+                self.authoring_job = Some(rx);
+                self.authoring_status = Some("Drafting...".to_string());
+            }
+            Err(e) => self.authoring_status = Some(format!("Failed: {e}")),
+        }
+    }
+
+    fn poll_authoring(&mut self, ctx: &Context) {
+        let Some(rx) = &self.authoring_job else {
+            return;
+        };
+        let drafted = match rx.try_recv() {
+            Ok(drafted) => drafted,
+            Err(mpsc::TryRecvError::Empty) => {
+                ctx.request_repaint_after(std::time::Duration::from_millis(200));
+                return;
+            }
+            Err(mpsc::TryRecvError::Disconnected) => {
+                Err("drafting stopped unexpectedly".to_string())
+            }
+        };
+        self.authoring_job = None;
+        self.authoring_status = Some(match drafted {
+            Ok(questions) => {
+                let instructions = &mut self.draft_input.instructions_md;
+                if instructions.trim().is_empty() || instructions.trim() == "Add instructions here."
+                {
+                    *instructions = questions;
+                } else {
+                    instructions.push_str("\n\n");
+                    instructions.push_str(&questions);
                 }
+                "Draft added; edit it before adding the assignment.".to_string()
             }
+            Err(e) => format!("Could not draft questions: {e}"),
+        });
+    }
+
+    /// Pre-marking runs the model, which can take a while; save on a worker thread so the
+    /// window stays responsive. The result arrives in `poll_submission`.
+    fn start_submission(&mut self, assignment_id: String) {
+        let (tx, rx) = mpsc::channel();
+        let base = self.base_path.clone();
+        let settings = self.settings.clone();
+        let text = self.submission_text.clone();
+        let attachments = self.submission_attachments.clone();
+        let spawned = thread::Builder::new()
+            .name("submission".to_string())
+            .spawn(move || {
+                let saved = panic::catch_unwind(|| {
+                    save_submission_with_answers(
+                        &base,
+                        &settings,
+                        &assignment_id,
+                        &text,
+                        &attachments,
+                    )
+                })
+                .unwrap_or_else(|_| Err(io::Error::other("pre-marking failed")));
+                let _ = tx.send(saved);
+            });
+        match spawned {
+            Ok(_) => {
+                self.submission_job = Some(rx);
+                self.submission_status = Some("Pre-marking and saving...".to_string());
+            }
+            Err(e) => self.submission_status = Some(format!("Failed: {e}")),
         }
     }
 
+    fn poll_submission(&mut self, ctx: &Context) {
+        let Some(rx) = &self.submission_job else {
+            return;
+        };
+        let saved = match rx.try_recv() {
+            Ok(saved) => saved,
+            Err(mpsc::TryRecvError::Empty) => {
+                ctx.request_repaint_after(std::time::Duration::from_millis(200));
+                return;
+            }
+            Err(mpsc::TryRecvError::Disconnected) => {
+                Err(io::Error::other("saving stopped unexpectedly"))
+            }
+        };
+        self.submission_job = None;
+        self.submission_status = Some(match saved {
+            Ok(path) => {
+                self.submission_text.clear();
+                self.submission_attachments.clear();
+                self.resync_homework();
+                format!("Saved to {}", path.display())
+            }
+            Err(e) => format!("Failed: {e}"),
+        });
+    }
+
     fn submission_rows(&self) -> Vec<SubmissionRow> {
         let mut rows = Vec::new();
         for s in &self.submissions {
@@ -1821,38 +3188,183 @@ impl ChattyApp {
             .collect()
     }
 
+    /// Start answering the chat input on a worker thread; `poll_chat` shows the reply.
     fn handle_chat_send(&mut self) {
-        if self.chat_input.trim().is_empty() {
+        if self.chat_input.trim().is_empty() || self.chat_job.is_some() {
             return;
         }
         let user_msg = self.chat_input.trim().to_string();
-        self.chat_log.push(("You".to_string(), user_msg.clone()));
-        // Show a placeholder before generation to avoid disappearing messages
+        self.history_open = false;
         self.chat_log
-            .push(("Chatty".to_string(), "...".to_string()));
+            .push(ChatMessage::new("You", user_msg.clone()));
+        // Show a placeholder before generation to avoid disappearing messages
+        self.chat_log.push(ChatMessage::new("Chatty", "..."));
+
+        let (tx, rx) = mpsc::channel();
+        let settings = self.settings.clone();
+        let base = self.base_path.clone();
+        let pack = self.current_pack.clone();
+        let spawned = thread::Builder::new()
+            .name("chat".to_string())
+            .spawn(move || {
+                let reply = panic::catch_unwind(|| {
+                    // Janet also screens inside generate_chat_reply; checking first skips
+                    // retrieval and keeps source labels off the fallback message.
+                    if let Err(message) = safety::check_input(&settings, &user_msg) {
+                        return StudentReply::screened(message);
+                    }
+                    let passages = passages_for_chat(&base, pack.as_ref(), &user_msg);
+                    StudentReply {
+                        answer: generate_chat_reply(&settings, &user_msg, &passages),
+                        sources: unique_citations(&passages),
+                        screened: false,
+                    }
+                });
+                let _ = tx.send(reply.ok());
+            });
+        self.chat_input.clear();
+        match spawned {
+            Ok(_) => self.chat_job = Some(rx),
+            Err(_) => {
+                if let Some(last) = self.chat_log.last_mut() {
+                    last.text = "Sorry, I ran into an error while answering.".to_string();
+                }
+                self.save_chat();
+            }
+        }
+    }
 
-        let result = panic::catch_unwind({
-            let settings = self.settings.clone();
-            let question = user_msg.clone();
+    /// Fill in the "..." placeholder once the chat worker answers.
+    fn poll_chat(&mut self, ctx: &Context) {
+        let Some(rx) = &self.chat_job else {
+            return;
+        };
+        let reply = match rx.try_recv() {
+            Ok(reply) => reply,
+            Err(mpsc::TryRecvError::Empty) => {
+                ctx.request_repaint_after(std::time::Duration::from_millis(200));
+                return;
+            }
+            Err(mpsc::TryRecvError::Disconnected) => None,
+        };
+        self.chat_job = None;
+        // The log may have been cleared or swapped for a saved chat meanwhile.
+        let Some(last) = self.chat_log.last_mut().filter(|m| m.text == "...") else {
+            return;
+        };
+        match reply {
+            Some(mut reply) => {
+                last.sources = std::mem::take(&mut reply.sources);
+                last.text = reply.display_text();
+            }
+            None => last.text = "Sorry, I ran into an error while answering.".to_string(),
+        }
+        self.save_chat();
+    }
+
+    /// Screen, retrieve and generate hints on a worker thread; `poll_hint` shows them.
+    fn start_hint(&mut self, assignment: HomeworkAssignment, question: String) {
+        let (tx, rx) = mpsc::channel();
+        let settings = self.settings.clone();
+        let base = self.base_path.clone();
+        let pack = self.current_pack.clone();
+        let spawned = thread::Builder::new().name("hints".to_string()).spawn({
+            let assignment = assignment.clone();
+            let question = question.clone();
             move || {
-                let prompt = format!(
-                    "{capsule}\nUser request: {q}\nRespond with one short, clear answer.",
-                    capsule = CHAT_CAPSULE,
-                    q = question
-                );
-                generate_answer(&settings, &prompt)
+                let reply = panic::catch_unwind(|| {
+                    if let Err(message) = safety::check_input(&settings, &question) {
+                        return StudentReply::screened(message);
+                    }
+                    let passages = passages_for_hint(&base, pack.as_ref(), &assignment, &question);
+                    StudentReply {
+                        answer: generate_hint(&settings, &assignment, &question, &passages),
+                        sources: unique_citations(&passages),
+                        screened: false,
+                    }
+                });
+                let _ = tx.send(reply.ok());
             }
         });
+        match spawned {
+            Ok(_) => {
+                self.hint_job = Some((assignment, question, rx));
+                self.homework_help_status = Some("Generating hints...".to_string());
+            }
+            Err(_) => {
+                self.homework_help_status =
+                    Some("Sorry, something went wrong while generating hints.".to_string());
+            }
+        }
+    }
 
-        if let Some(last) = self.chat_log.last_mut() {
-            last.1 = match result {
-                Ok(filtered) => Self::sanitize_short(&filtered, 4, 400),
-                Err(_) => "Sorry, I ran into an error while answering.".to_string(),
-            };
+    fn poll_hint(&mut self, ctx: &Context) {
+        let Some((_, _, rx)) = &self.hint_job else {
+            return;
+        };
+        let reply = match rx.try_recv() {
+            Ok(reply) => reply,
+            Err(mpsc::TryRecvError::Empty) => {
+                ctx.request_repaint_after(std::time::Duration::from_millis(200));
+                return;
+            }
+            Err(mpsc::TryRecvError::Disconnected) => None,
+        };
+        let Some((assignment, question, _)) = self.hint_job.take() else {
+            return;
+        };
+        match reply {
+            Some(reply) if reply.screened => {
+                self.homework_help_response = Some(reply.display_text());
+                self.homework_help_sources.clear();
+                self.homework_help_status = None;
+            }
+            Some(mut reply) => {
+                let sources = std::mem::take(&mut reply.sources);
+                let text = reply.display_text();
+                self.record_hint(&assignment.id, &question, &text, &sources);
+                self.homework_help_response = Some(text);
+                self.homework_help_sources = sources;
+                self.homework_help_status = Some("Hints ready.".to_string());
+            }
+            None => {
+                self.homework_help_response = None;
+                self.homework_help_sources.clear();
+                self.homework_help_status =
+                    Some("Sorry, something went wrong while generating hints.".to_string());
+            }
         }
-        self.chat_input.clear();
     }
 }
+fn budget_limit_fields(ui: &mut egui::Ui, id: &str, limits: &mut BudgetLimits) {
+    egui::Grid::new(id).num_columns(2).show(ui, |ui| {
+        ui.label("Requests per minute");
+        ui.add(egui::DragValue::new(&mut limits.requests_per_minute).clamp_range(0..=120));
+        ui.end_row();
+        ui.label("Tokens per session");
+        ui.add(
+            egui::DragValue::new(&mut limits.tokens_per_session)
+                .clamp_range(0..=200_000)
+                .speed(50),
+        );
+        ui.end_row();
+        ui.label("Max seconds per answer");
+        ui.add(egui::DragValue::new(&mut limits.max_generation_secs).clamp_range(0..=600));
+        ui.end_row();
+    });
+}
+
+/// "kind: file" labels for the passages a reply drew on, one per file.
+fn unique_citations(passages: &[Passage]) -> Vec<String> {
+    let mut out: Vec<String> = Vec::new();
+    for citation in passages.iter().map(Passage::citation) {
+        if !out.contains(&citation) {
+            out.push(citation);
+        }
+    }
+    out
+}
+
 fn render_markdown(ui: &mut egui::Ui, text: &str) {
     for line in text.lines() {
         let trimmed = line.trim();
@@ -1936,6 +3448,14 @@ fn color_from_hex(hex: &str) -> egui::Color32 {
 impl App for ChattyApp {
     fn update(&mut self, ctx: &Context, _frame: &mut eframe::Frame) {
         apply_theme(&self.theme, ctx);
+        self.check_history_profile();
+        self.poll_model_checks(ctx);
+        self.poll_model_switch(ctx);
+        self.poll_submission(ctx);
+        self.poll_authoring(ctx);
+        self.poll_chat(ctx);
+        self.poll_hint(ctx);
+        self.poll_export(ctx);
 
         TopBottomPanel::top("menu_bar").show(ctx, |ui| self.render_menu_bar(ctx, ui));
         TopBottomPanel::top("tabs").show(ctx, |ui| self.render_tab_bar(ui));
@@ -1961,11 +3481,19 @@ impl App for ChattyApp {
                 if input.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
                     self.handle_chat_send();
                 }
-                if ui.button("Send").clicked() {
+                if ui
+                    .add_enabled(self.chat_job.is_none(), egui::Button::new("Send"))
+                    .clicked()
+                {
                     self.handle_chat_send();
                 }
+                ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                    self.render_model_indicator(ui);
+                });
             });
         });
+        // Keep the model indicator current while idle unloading may happen in the background.
+        ctx.request_repaint_after(std::time::Duration::from_secs(5));
     }
 }
 
@@ -1991,4 +3519,3 @@ pub fn launch_gui(base_path: PathBuf, settings: Settings) -> eframe::Result<()>
         }),
     )
 }
-
@@ -0,0 +1,464 @@
+use crate::homework_pack::HomeworkAssignment;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Extra generations tried after a hint gives too much away, before it is blocked.
+pub const RETRIES: usize = 1;
+
+/// Shown instead of a hint that still gave the answer away after the retries.
+pub const BLOCKED_HINT: &str = "I can't give that hint without giving away the answer. \
+Ask me about the one step you're stuck on and I'll point you in the right direction.";
+
+/// Words a hint may copy in a row from the answer key or the instructions.
+const MAX_COPIED_RUN: usize = 6;
+/// Share of the answer key's three-word phrases a hint may repeat.
+const MAX_KEY_OVERLAP: f64 = 0.4;
+/// Hex digits kept from each salted hash in a `KeyFingerprint`.
+const HASH_LEN: usize = 16;
+
+/// What the guard needs from an answer key, as salted hashes. Built when the teacher adds the
+/// assignment, so student packs can check hints against the key without carrying it. A short
+/// numeric answer could still be found by trying values, but the key can't be read off the pack.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct KeyFingerprint {
+    pub salt: String,
+    /// The key's answers as numbers, where the instructions don't give them.
+    #[serde(default)]
+    pub numbers: Vec<String>,
+    /// A one- or two-word answer ("Paris", "Henry VIII") the instructions don't name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub short_answer: Option<String>,
+    /// Every run of `MAX_COPIED_RUN + 1` words.
+    #[serde(default)]
+    pub runs: Vec<String>,
+    /// Three-word phrases the instructions don't use, one entry per occurrence.
+    #[serde(default)]
+    pub phrases: Vec<String>,
+}
+
+impl KeyFingerprint {
+    fn hash(&self, item: &str) -> String {
+        salted_hash(&self.salt, item)
+    }
+}
+
+/// Why a hint was held back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Leak {
+    /// Repeats the teacher's answer key or model answer.
+    AnswerKey,
+    /// Contains a number from the answer key that the instructions don't give.
+    KeyNumber,
+    /// Works the assignment's own numbers through to a result ("3/4 + 1/8 = 7/8").
+    WorkedNumbers,
+    /// Copies the assignment's wording, filling it in.
+    TaskWording,
+}
+
+impl Leak {
+    pub fn label(self) -> &'static str {
+        match self {
+            Leak::AnswerKey => "repeats the answer key",
+            Leak::KeyNumber => "contains a number from the answer key",
+            Leak::WorkedNumbers => "works the assignment's numbers to a result",
+            Leak::TaskWording => "copies the assignment wording",
+        }
+    }
+
+    /// Added to the hint request before it is generated again.
+    pub fn retry_note(self) -> String {
+        let what = match self {
+            Leak::AnswerKey | Leak::KeyNumber => "the answer",
+            Leak::WorkedNumbers => "a worked solution using the assignment's numbers",
+            Leak::TaskWording => "the assignment text filled in",
+        };
+        format!(
+            "\nYour previous hint gave away {what}. Give a different hint: a guiding question or a first step, with no final answer and no results worked from the assignment's numbers."
+        )
+    }
+}
+
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect()
+}
+
+/// Numbers in `text` as values, so "7/8" and "0.875" are the same answer. Thousands
+/// separators are dropped; list markers at the start of a line ("1." or "2)") are skipped.
+fn numbers(text: &str) -> Vec<f64> {
+    let mut out = Vec::new();
+    for line in text.lines() {
+        let chars: Vec<char> = line.chars().collect();
+        let mut i = 0;
+        while i < chars.len() {
+            if !chars[i].is_ascii_digit() {
+                i += 1;
+                continue;
+            }
+            let start = i;
+            while i < chars.len()
+                && (chars[i].is_ascii_digit()
+                    || (matches!(chars[i], '.' | ',' | '/')
+                        && chars.get(i + 1).is_some_and(|c| c.is_ascii_digit())))
+            {
+                i += 1;
+            }
+            let token: String = chars[start..i].iter().filter(|c| **c != ',').collect();
+            let list_marker = chars[..start].iter().all(|c| c.is_whitespace())
+                && matches!(chars.get(i), Some('.') | Some(')'));
+            if list_marker {
+                continue;
+            }
+            if let Some(value) = number_value(&token) {
+                out.push(value);
+            }
+        }
+    }
+    out
+}
+
+/// The numbers a key gives as answers, one per item ("1) 7/8  2) 5/6" has two): the first
+/// after the item's last `=`, otherwise its last number. Working such as "2 steps" or
+/// "multiply by 3" is left out.
+fn answer_numbers(key: &str) -> Vec<f64> {
+    let mut out = Vec::new();
+    for line in key.lines() {
+        for item in split_items(line) {
+            let answer = match item.rsplit_once('=') {
+                Some((_, right)) => numbers(right).first().copied(),
+                None => numbers(item).last().copied(),
+            };
+            out.extend(answer);
+        }
+    }
+    out
+}
+
+/// `line` cut before each "2)"-style marker that follows a space, markers included.
+fn split_items(line: &str) -> Vec<&str> {
+    let bytes = line.as_bytes();
+    let mut cuts = vec![0];
+    for (i, b) in bytes.iter().enumerate() {
+        if !b.is_ascii_digit() || (i > 0 && !bytes[i - 1].is_ascii_whitespace()) {
+            continue;
+        }
+        let end = i + bytes[i..].iter().take_while(|b| b.is_ascii_digit()).count();
+        if i > 0 && bytes.get(end) == Some(&b')') {
+            cuts.push(i);
+        }
+    }
+    cuts.push(line.len());
+    cuts.windows(2).map(|w| &line[w[0]..w[1]]).collect()
+}
+
+fn number_value(token: &str) -> Option<f64> {
+    match token.split_once('/') {
+        Some((num, den)) => {
+            let den: f64 = den.parse().ok()?;
+            (den != 0.0).then_some(num.parse::<f64>().ok()? / den)
+        }
+        None => token.parse().ok(),
+    }
+}
+
+fn contains_value(values: &[f64], value: f64) -> bool {
+    values.iter().any(|v| (v - value).abs() < 1e-6)
+}
+
+/// Numbers as fingerprint entries, rounded like `contains_value` compares them.
+fn number_text(value: f64) -> String {
+    format!("{value:.6}")
+}
+
+fn salted_hash(salt: &str, item: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(salt.as_bytes());
+    hasher.update([0u8]);
+    hasher.update(item.as_bytes());
+    format!("{:x}", hasher.finalize())[..HASH_LEN].to_string()
+}
+
+fn new_salt() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    salted_hash(&format!("{nanos}:{}", std::process::id()), "salt")
+}
+
+/// Fingerprint of `key` for an assignment with these instructions, or None for an empty key.
+/// Numbers and phrases the instructions already give are left out, since hints may use them.
+pub fn fingerprint(key: &str, instructions: &str) -> Option<KeyFingerprint> {
+    if key.trim().is_empty() {
+        return None;
+    }
+    let salt = new_salt();
+    let hash = |item: &str| salted_hash(&salt, item);
+    let task = words(instructions);
+    let task_numbers = numbers(instructions);
+    let key_words = words(key);
+    let numbers = answer_numbers(key)
+        .into_iter()
+        .filter(|n| !contains_value(&task_numbers, *n))
+        .map(|n| hash(&number_text(n)))
+        .collect();
+    // A one- or two-word answer leaks when the hint names it and the instructions don't.
+    let short = key_words.len() < 3 && key_words.iter().any(|w| w.chars().any(char::is_alphabetic));
+    let short_answer = short
+        .then(|| key_words.join(" "))
+        .filter(|phrase| !format!(" {} ", task.join(" ")).contains(&format!(" {phrase} ")))
+        .map(|phrase| hash(&phrase));
+    let runs = key_words
+        .windows(MAX_COPIED_RUN + 1)
+        .map(|run| hash(&run.join(" ")))
+        .collect();
+    // Phrases the instructions also use are the question's wording, not the answer's.
+    let task_phrases: Vec<&[String]> = task.windows(3).collect();
+    let phrases = key_words
+        .windows(3)
+        .filter(|p| !task_phrases.contains(p))
+        .map(|p| hash(&p.join(" ")))
+        .collect();
+    Some(KeyFingerprint {
+        salt: salt.clone(),
+        numbers,
+        short_answer,
+        runs,
+        phrases,
+    })
+}
+
+/// Longest run of consecutive words `text` shares with `source`.
+fn longest_copied_run(text: &[String], source: &[String]) -> usize {
+    let mut best = 0;
+    let mut prev = vec![0usize; source.len() + 1];
+    for word in text {
+        let mut row = vec![0usize; source.len() + 1];
+        for (j, other) in source.iter().enumerate() {
+            if word == other {
+                row[j + 1] = prev[j] + 1;
+                best = best.max(row[j + 1]);
+            }
+        }
+        prev = row;
+    }
+    best
+}
+
+fn key_leak(hint: &[String], hint_numbers: &[f64], key: &KeyFingerprint) -> Option<Leak> {
+    if hint_numbers
+        .iter()
+        .any(|n| key.numbers.contains(&key.hash(&number_text(*n))))
+    {
+        return Some(Leak::KeyNumber);
+    }
+    if let Some(short) = &key.short_answer {
+        let named = (1..=2).any(|n| hint.windows(n).any(|w| key.hash(&w.join(" ")) == *short));
+        return named.then_some(Leak::AnswerKey);
+    }
+    let hashed =
+        |n: usize| -> HashSet<String> { hint.windows(n).map(|w| key.hash(&w.join(" "))).collect() };
+    let hint_runs = hashed(MAX_COPIED_RUN + 1);
+    if key.runs.iter().any(|r| hint_runs.contains(r)) {
+        return Some(Leak::AnswerKey);
+    }
+    if key.phrases.is_empty() {
+        return None;
+    }
+    let hint_phrases = hashed(3);
+    let repeated = key
+        .phrases
+        .iter()
+        .filter(|p| hint_phrases.contains(*p))
+        .count();
+    (repeated as f64 / key.phrases.len() as f64 >= MAX_KEY_OVERLAP).then_some(Leak::AnswerKey)
+}
+
+/// A line of the hint with `=` where the left side uses the assignment's numbers and the
+/// right side is a number the assignment doesn't give: the sum worked out for the student.
+fn works_task_numbers(hint: &str, task_numbers: &[f64]) -> bool {
+    !task_numbers.is_empty()
+        && hint.lines().any(|line| {
+            line.split_once('=').is_some_and(|(left, right)| {
+                let used = numbers(left)
+                    .iter()
+                    .any(|n| contains_value(task_numbers, *n));
+                let result = numbers(right)
+                    .first()
+                    .is_some_and(|n| !contains_value(task_numbers, *n));
+                used && result
+            })
+        })
+}
+
+/// Whether a hint for `assignment` gives too much away: compared with the answer key's
+/// fingerprint when the pack has one, and with the assignment's own numbers and wording.
+pub fn find_leak(assignment: &HomeworkAssignment, hint: &str) -> Option<Leak> {
+    let hint_words = words(hint);
+    let hint_numbers = numbers(hint);
+    let task_words = words(&assignment.instructions_md);
+    let task_numbers = numbers(&assignment.instructions_md);
+    if let Some(key) = &assignment.answer_fingerprint {
+        if let Some(leak) = key_leak(&hint_words, &hint_numbers, key) {
+            return Some(leak);
+        }
+    }
+    if works_task_numbers(hint, &task_numbers) {
+        return Some(Leak::WorkedNumbers);
+    }
+    (longest_copied_run(&hint_words, &task_words) > MAX_COPIED_RUN + 2).then_some(Leak::TaskWording)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assignment(instructions: &str, key: &str) -> HomeworkAssignment {
+        HomeworkAssignment {
+            id: "hw-1".to_string(),
+            title: "Homework".to_string(),
+            subject: "Math".to_string(),
+            year_level: "7".to_string(),
+            due_at: None,
+            instructions_md: instructions.to_string(),
+            attachments: vec![],
+            allow_games: false,
+            allow_ai_premark: false,
+            max_score: None,
+            adapter: None,
+            answer_fingerprint: fingerprint(key, instructions),
+        }
+    }
+
+    #[test]
+    fn key_answers_leak_in_any_form() {
+        let fractions = assignment("Add 3/4 and 1/8.", "3/4 + 1/8 = 7/8");
+        assert_eq!(
+            find_leak(&fractions, "You should get 0.875."),
+            Some(Leak::KeyNumber)
+        );
+        assert_eq!(find_leak(&fractions, "It is 7/8."), Some(Leak::KeyNumber));
+
+        let listed = assignment("1) 3/4 + 1/8\n2) 1/2 + 1/3", "1) 7/8  2) 5/6");
+        assert_eq!(
+            find_leak(&listed, "Question 2 comes to 5/6."),
+            Some(Leak::KeyNumber)
+        );
+    }
+
+    #[test]
+    fn working_numbers_in_the_key_are_not_answers() {
+        assert_eq!(
+            answer_numbers("Subtract 4 then divide by 3, 2 steps: x = 14"),
+            vec![14.0]
+        );
+        let equation = assignment(
+            "Solve for x: 3x + 4 = 46",
+            "Subtract 4 then divide by 3, 2 steps: x = 14",
+        );
+        assert_eq!(
+            find_leak(&equation, "This takes 2 steps. What do you undo first?"),
+            None
+        );
+    }
+
+    #[test]
+    fn short_answers_leak_only_when_named() {
+        let capital = assignment("What is the capital of France?", "Paris");
+        assert_eq!(find_leak(&capital, "It's Paris!"), Some(Leak::AnswerKey));
+        assert_eq!(find_leak(&capital, "Which city is on the Seine?"), None);
+
+        let king = assignment("Which king founded the Church of England?", "Henry VIII");
+        assert_eq!(
+            find_leak(&king, "Think of Henry VIII and his marriages."),
+            Some(Leak::AnswerKey)
+        );
+        assert_eq!(find_leak(&king, "Think about which Henry it was."), None);
+    }
+
+    #[test]
+    fn copying_the_model_answer_leaks() {
+        let essay = assignment(
+            "Explain why the Roman Empire split in two.",
+            "The empire was too large to govern from one city, so Diocletian divided the administration between east and west.",
+        );
+        assert_eq!(
+            find_leak(
+                &essay,
+                "Remember it was too large to govern from one city, so think about that."
+            ),
+            Some(Leak::AnswerKey)
+        );
+        assert_eq!(
+            find_leak(
+                &essay,
+                "Between east and west, Diocletian divided the administration, as it was too large to govern."
+            ),
+            Some(Leak::AnswerKey)
+        );
+        assert_eq!(
+            find_leak(
+                &essay,
+                "How easy is it to run a very big empire from one place?"
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn worked_task_numbers_and_copied_wording_leak() {
+        let sums = assignment("Add 3/4 and 1/8.", "");
+        assert_eq!(
+            find_leak(&sums, "3/4 + 1/8 = 7/8"),
+            Some(Leak::WorkedNumbers)
+        );
+        assert_eq!(
+            find_leak(&sums, "Find a common denominator for 3/4 and 1/8 first."),
+            None
+        );
+
+        let wording = assignment(
+            "Describe how the water cycle moves water from the ocean to the clouds and back to the land.",
+            "",
+        );
+        assert_eq!(
+            find_leak(
+                &wording,
+                "The water cycle moves water from the ocean to the clouds and back to the land by evaporation and rain."
+            ),
+            Some(Leak::TaskWording)
+        );
+    }
+
+    #[test]
+    fn safe_hints_pass() {
+        let fractions = assignment("Add 3/4 and 1/8.", "3/4 + 1/8 = 7/8");
+        for hint in [
+            "What denominator could both fractions share?",
+            "Try rewriting 3/4 with 8 as the denominator.",
+            "1. Find a common denominator.\n2. Add the numerators.",
+        ] {
+            assert_eq!(find_leak(&fractions, hint), None, "{hint}");
+        }
+    }
+
+    #[test]
+    fn packs_carry_a_salted_fingerprint_not_the_key() {
+        let capital = assignment(
+            "What is the capital of France?",
+            "Paris, on the Seine, population 2100000",
+        );
+        let json = serde_json::to_string(&capital).unwrap();
+        for secret in ["Paris", "paris", "Seine", "2100000"] {
+            assert!(!json.contains(secret), "{json}");
+        }
+        let again = fingerprint("Paris", "What is the capital of France?").unwrap();
+        let other = fingerprint("Paris", "What is the capital of France?").unwrap();
+        assert_ne!(again.short_answer, other.short_answer);
+        assert!(fingerprint("  ", "anything").is_none());
+    }
+}
@@ -0,0 +1,486 @@
+use crate::redact;
+use crate::settings::{ModelTask, Settings};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// One line of a saved chat, as shown in the Chat tab.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChatMessage {
+    pub sender: String,
+    pub text: String,
+    /// When the message was sent (RFC 3339); empty in chats saved before it was recorded.
+    #[serde(default)]
+    pub at: String,
+    /// Class material the reply drew on, shown under it rather than mixed into the text.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<String>,
+}
+
+impl ChatMessage {
+    pub fn new(sender: &str, text: impl Into<String>) -> Self {
+        Self {
+            sender: sender.to_string(),
+            text: text.into(),
+            at: chrono::Utc::now().to_rfc3339(),
+            sources: Vec::new(),
+        }
+    }
+
+    pub fn is_student(&self) -> bool {
+        self.sender.eq_ignore_ascii_case("you")
+    }
+}
+
+/// Local date and time of an RFC 3339 timestamp, as shown to the student.
+pub fn local_time(at: &str) -> String {
+    chrono::DateTime::parse_from_rfc3339(at)
+        .map(|t| {
+            t.with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+        })
+        .unwrap_or_else(|_| at.to_string())
+}
+
+/// A chat session saved under `history/<student_id>/session_<id>.json`. Text is redacted
+/// before it is written.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChatSession {
+    pub id: String,
+    pub started_at: String,
+    pub updated_at: String,
+    /// Chat model when the session was last saved.
+    #[serde(default)]
+    pub model: String,
+    #[serde(default)]
+    pub messages: Vec<ChatMessage>,
+}
+
+impl ChatSession {
+    pub fn new() -> Self {
+        let now = chrono::Utc::now();
+        Self {
+            id: now.format("%Y%m%d_%H%M%S_%3f").to_string(),
+            started_at: now.to_rfc3339(),
+            updated_at: now.to_rfc3339(),
+            model: String::new(),
+            messages: Vec::new(),
+        }
+    }
+
+    /// The first thing the student asked, shortened, for the history list.
+    pub fn title(&self) -> String {
+        let first = self
+            .messages
+            .iter()
+            .find(|m| m.is_student())
+            .map(|m| m.text.trim())
+            .unwrap_or("(empty chat)");
+        let mut title: String = first.chars().take(60).collect();
+        if first.chars().count() > 60 {
+            title.push_str("...");
+        }
+        title
+    }
+
+    /// Local date and time of the last message.
+    pub fn when(&self) -> String {
+        local_time(&self.updated_at)
+    }
+}
+
+impl Default for ChatSession {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Folder name for a student ID: anything but letters, digits, `-` and `_` dropped, so an ID
+/// cannot point outside `history/`.
+fn profile_key(student_id: &str) -> String {
+    let key: String = student_id
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+        .collect();
+    if key.is_empty() {
+        "student".to_string()
+    } else {
+        key
+    }
+}
+
+pub fn history_root(base: &Path) -> PathBuf {
+    base.join("history")
+}
+
+fn profile_dir(settings: &Settings) -> PathBuf {
+    history_root(Path::new(&settings.base_path)).join(profile_key(&settings.student.student_id))
+}
+
+/// Shortest PIN accepted for a history profile.
+pub const MIN_PIN_LEN: usize = 4;
+
+/// Salted PIN hash kept in `history/<student_id>/profile.json`.
+#[derive(Serialize, Deserialize)]
+struct ProfileLock {
+    salt: String,
+    pin_sha256: String,
+}
+
+fn pin_hash(salt: &str, pin: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(salt.as_bytes());
+    hasher.update([0u8]);
+    hasher.update(pin.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+fn new_salt(dir: &Path) -> String {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    let mut hasher = Sha256::new();
+    hasher.update(nanos.to_le_bytes());
+    hasher.update(std::process::id().to_le_bytes());
+    hasher.update(dir.to_string_lossy().as_bytes());
+    format!("{:x}", hasher.finalize())[..32].to_string()
+}
+
+/// A student profile whose history PIN has been entered. Saved chats are only listed, saved
+/// or deleted through one, so typing a classmate's ID on the Home tab (or into Settings after
+/// the teacher view is locked again) does not open their history.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    student_id: String,
+    dir: PathBuf,
+}
+
+impl Profile {
+    /// False once the ID in settings has been changed away from this profile's.
+    pub fn matches(&self, settings: &Settings) -> bool {
+        self.student_id == settings.student.student_id
+    }
+
+    fn session_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("session_{id}.json"))
+    }
+
+    /// The student's chat and hint exports, kept owner-only inside the profile folder.
+    pub fn exports_dir(&self) -> io::Result<PathBuf> {
+        let dir = self.dir.join("exports");
+        make_private_dir(&dir)?;
+        Ok(dir)
+    }
+}
+
+fn lock_path(settings: &Settings) -> PathBuf {
+    profile_dir(settings).join("profile.json")
+}
+
+/// Whether the current student ID already has a history PIN.
+pub fn has_pin(settings: &Settings) -> bool {
+    lock_path(settings).exists()
+}
+
+/// Open the current student's history with their PIN. The first time an ID is used the PIN
+/// is set instead; after that the same PIN is needed. Errors are student-facing.
+pub fn open_profile(settings: &Settings, pin: &str) -> Result<Profile, String> {
+    let pin = pin.trim();
+    if pin.chars().count() < MIN_PIN_LEN {
+        return Err(format!("Your PIN needs at least {MIN_PIN_LEN} characters."));
+    }
+    let dir = profile_dir(settings);
+    let profile = Profile {
+        student_id: settings.student.student_id.clone(),
+        dir: dir.clone(),
+    };
+    let lock_path = lock_path(settings);
+    if let Ok(contents) = fs::read_to_string(&lock_path) {
+        let lock: ProfileLock = serde_json::from_str(&contents)
+            .map_err(|e| format!("Your history profile could not be read ({e})."))?;
+        return if pin_hash(&lock.salt, pin) == lock.pin_sha256 {
+            Ok(profile)
+        } else {
+            Err("That PIN does not match this student ID.".to_string())
+        };
+    }
+
+    let salt = new_salt(&dir);
+    let lock = ProfileLock {
+        pin_sha256: pin_hash(&salt, pin),
+        salt,
+    };
+    let saved = make_private_dir(&history_root(Path::new(&settings.base_path)))
+        .and_then(|_| make_private_dir(&dir))
+        .and_then(|_| {
+            let json = serde_json::to_string_pretty(&lock)
+                .map_err(|e| io::Error::other(format!("JSON encode error: {e}")))?;
+            write_private(&lock_path, &json)
+        });
+    saved.map_err(|e| format!("Could not set up your history PIN ({e})."))?;
+    Ok(profile)
+}
+
+/// Session IDs are generated as `YYYYmmdd_HHMMSS_fff`; anything else did not come from here.
+fn valid_session_id(id: &str) -> bool {
+    !id.is_empty() && id.chars().all(|c| c.is_ascii_digit() || c == '_')
+}
+
+/// Other accounts on a shared computer cannot list or read history (owner-only folders and
+/// files). Elsewhere the folder inherits the data directory's permissions.
+#[cfg(unix)]
+fn make_private_dir(dir: &Path) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::create_dir_all(dir)?;
+    fs::set_permissions(dir, fs::Permissions::from_mode(0o700))
+}
+
+#[cfg(not(unix))]
+fn make_private_dir(dir: &Path) -> io::Result<()> {
+    fs::create_dir_all(dir)
+}
+
+#[cfg(unix)]
+fn write_private(path: &Path, contents: &str) -> io::Result<()> {
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    // An existing file keeps its old mode when opened; tighten it too.
+    file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    file.write_all(contents.as_bytes())
+}
+
+#[cfg(not(unix))]
+fn write_private(path: &Path, contents: &str) -> io::Result<()> {
+    fs::File::create(path)?.write_all(contents.as_bytes())
+}
+
+/// Save the open chat from the Chat tab or CLI log, redacting each message. Does nothing
+/// when history is off or the chat is empty; expired chats are cleared at the same time.
+pub fn save_chat(
+    settings: &Settings,
+    profile: &Profile,
+    session: &mut ChatSession,
+    log: &[ChatMessage],
+) -> io::Result<()> {
+    if !settings.chat_history.enabled || log.is_empty() {
+        return Ok(());
+    }
+    session.messages = log
+        .iter()
+        .map(|m| ChatMessage {
+            text: redact::redact(settings, &m.text),
+            ..m.clone()
+        })
+        .collect();
+    session.updated_at = chrono::Utc::now().to_rfc3339();
+    session.model = settings.model_for(ModelTask::Chat).name.clone();
+
+    make_private_dir(&profile.dir)?;
+    let json = serde_json::to_string_pretty(session)
+        .map_err(|e| io::Error::other(format!("JSON encode error: {e}")))?;
+    write_private(&profile.session_path(&session.id), &json)?;
+    purge_expired(settings, profile);
+    Ok(())
+}
+
+/// The profile's saved chats, newest first. A file whose `id` does not match its own name is
+/// skipped, so a hand-edited session cannot steer a later delete elsewhere.
+pub fn list_sessions(profile: &Profile) -> Vec<ChatSession> {
+    let Ok(entries) = fs::read_dir(&profile.dir) else {
+        return Vec::new();
+    };
+    let mut sessions: Vec<ChatSession> = entries
+        .flatten()
+        .filter_map(|e| {
+            let name = e.file_name().to_string_lossy().to_string();
+            name.starts_with("session_").then_some((name, e.path()))
+        })
+        .filter_map(|(name, path)| {
+            let session: ChatSession =
+                serde_json::from_str(&fs::read_to_string(path).ok()?).ok()?;
+            (valid_session_id(&session.id) && name == format!("session_{}.json", session.id))
+                .then_some(session)
+        })
+        .collect();
+    sessions.sort_by(|a, b| b.updated_at.cmp(&a.updated_at));
+    sessions
+}
+
+pub fn delete_session(profile: &Profile, id: &str) -> io::Result<()> {
+    if !valid_session_id(id) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("not a saved chat: {id}"),
+        ));
+    }
+    match fs::remove_file(profile.session_path(id)) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Delete every saved chat for the profile. Returns how many were removed.
+pub fn delete_all(profile: &Profile) -> io::Result<usize> {
+    let sessions = list_sessions(profile);
+    for session in &sessions {
+        delete_session(profile, &session.id)?;
+    }
+    Ok(sessions.len())
+}
+
+/// Delete the profile's chats whose last message is older than the retention period (0 keeps
+/// them until the student deletes them). Returns how many were removed.
+pub fn purge_expired(settings: &Settings, profile: &Profile) -> usize {
+    if settings.chat_history.retention_days == 0 {
+        return 0;
+    }
+    let cutoff =
+        chrono::Utc::now() - chrono::Duration::days(settings.chat_history.retention_days as i64);
+    let mut removed = 0;
+    for session in list_sessions(profile) {
+        let expired = chrono::DateTime::parse_from_rfc3339(&session.updated_at)
+            .map(|t| t < cutoff)
+            .unwrap_or(false);
+        if !expired {
+            continue;
+        }
+        match delete_session(profile, &session.id) {
+            Ok(()) => removed += 1,
+            Err(e) => eprintln!("[history] Could not delete old chat {}: {e}", session.id),
+        }
+    }
+    removed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::default_settings;
+
+    fn temp_settings(name: &str, student_id: &str) -> Settings {
+        let dir =
+            std::env::temp_dir().join(format!("chatty_history_{name}_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let mut settings = default_settings(&dir);
+        settings.student.student_id = student_id.to_string();
+        settings
+    }
+
+    fn chat(question: &str) -> Vec<ChatMessage> {
+        vec![
+            ChatMessage::new("You", question),
+            ChatMessage::new("Chatty", "Try splitting it into smaller steps."),
+        ]
+    }
+
+    #[test]
+    fn saved_chats_round_trip_with_redaction() {
+        let mut settings = temp_settings("round_trip", "s-100");
+        settings.redaction.roster.push("Priya".to_string());
+        let profile = open_profile(&settings, "4821").unwrap();
+        let mut session = ChatSession::new();
+        save_chat(
+            &settings,
+            &profile,
+            &mut session,
+            &chat("Priya said 7 x 8 is 54?"),
+        )
+        .unwrap();
+
+        let saved = list_sessions(&profile);
+        assert_eq!(saved.len(), 1);
+        assert_eq!(saved[0].id, session.id);
+        assert_eq!(saved[0].messages.len(), 2);
+        assert_eq!(saved[0].messages[0].text, "[name] said 7 x 8 is 54?");
+        assert_eq!(saved[0].title(), "[name] said 7 x 8 is 54?");
+
+        assert_eq!(delete_all(&profile).unwrap(), 1);
+        assert!(list_sessions(&profile).is_empty());
+    }
+
+    #[test]
+    fn expired_chats_are_pruned() {
+        let mut settings = temp_settings("retention", "s-200");
+        let profile = open_profile(&settings, "4821").unwrap();
+        let mut old = ChatSession::new();
+        old.id = "20200101_090000_000".to_string();
+        save_chat(&settings, &profile, &mut old, &chat("old question")).unwrap();
+        let mut recent = ChatSession::new();
+        save_chat(&settings, &profile, &mut recent, &chat("new question")).unwrap();
+
+        // Backdate the first chat past the retention period.
+        old.updated_at = (chrono::Utc::now() - chrono::Duration::days(40)).to_rfc3339();
+        let json = serde_json::to_string(&old).unwrap();
+        fs::write(profile.session_path(&old.id), json).unwrap();
+
+        settings.chat_history.retention_days = 0;
+        assert_eq!(purge_expired(&settings, &profile), 0);
+        settings.chat_history.retention_days = 30;
+        assert_eq!(purge_expired(&settings, &profile), 1);
+        let left = list_sessions(&profile);
+        assert_eq!(left.len(), 1);
+        assert_eq!(left[0].id, recent.id);
+    }
+
+    #[test]
+    fn another_students_history_needs_their_pin() {
+        let mut settings = temp_settings("isolation", "s-300");
+        let own = open_profile(&settings, "1111").unwrap();
+        save_chat(
+            &settings,
+            &own,
+            &mut ChatSession::new(),
+            &chat("my question"),
+        )
+        .unwrap();
+
+        // A classmate types the same ID: the PIN is required and a guess is refused.
+        assert!(has_pin(&settings));
+        assert!(open_profile(&settings, "2222").is_err());
+        assert!(open_profile(&settings, "12").is_err());
+
+        // Their own ID is a separate folder with nothing of the first student's in it.
+        settings.student.student_id = "s-301".to_string();
+        assert!(!own.matches(&settings));
+        assert!(!has_pin(&settings));
+        let other = open_profile(&settings, "2222").unwrap();
+        assert!(list_sessions(&other).is_empty());
+        let exports = other.exports_dir().unwrap();
+        assert!(exports.starts_with(&other.dir));
+        assert_ne!(exports, own.exports_dir().unwrap());
+
+        settings.student.student_id = "s-300".to_string();
+        let again = open_profile(&settings, "1111").unwrap();
+        assert_eq!(list_sessions(&again).len(), 1);
+    }
+
+    #[test]
+    fn crafted_session_ids_cannot_leave_the_profile() {
+        let settings = temp_settings("crafted", "s-400");
+        let profile = open_profile(&settings, "4821").unwrap();
+        let outside = Path::new(&settings.base_path)
+            .join("config")
+            .join("keep.json");
+        fs::create_dir_all(outside.parent().unwrap()).unwrap();
+        fs::write(&outside, "{}").unwrap();
+
+        let mut crafted = ChatSession::new();
+        crafted.id = "../../../config/keep".to_string();
+        let json = serde_json::to_string(&crafted).unwrap();
+        fs::write(profile.dir.join("session_crafted.json"), json).unwrap();
+
+        assert!(list_sessions(&profile).is_empty());
+        assert!(delete_session(&profile, &crafted.id).is_err());
+        assert_eq!(delete_all(&profile).unwrap(), 0);
+        assert!(outside.exists());
+    }
+}
@@ -6,6 +6,8 @@ use std::io;
 use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
 
+use crate::chat::generate_premark;
+use crate::hint_guard::KeyFingerprint;
 use crate::settings::Settings;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -23,6 +25,14 @@ pub struct HomeworkAssignment {
     #[serde(default)]
     pub allow_ai_premark: bool,
     pub max_score: Option<i32>,
+    /// LoRA adapter for this assignment (file in `models/`), overriding the subject's adapter.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub adapter: Option<String>,
+    /// Salted fingerprint of the teacher's model answer or answer key (see `hint_guard`).
+    /// Hints are compared against it so they can't give the answer away; the key itself is
+    /// never written to the pack or sent to the model.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub answer_fingerprint: Option<KeyFingerprint>,
 }
 
 fn default_allow_games() -> bool {
@@ -104,6 +114,8 @@ pub fn export_pack_template(base: &Path, school_id: &str, class_id: &str) -> io:
             allow_games: false,
             allow_ai_premark: true,
             max_score: Some(100),
+            adapter: None,
+            answer_fingerprint: None,
         }],
     };
 
@@ -228,7 +240,7 @@ pub fn save_submission_with_answers(
         settings.student.class_id.clone()
     };
 
-    let premark = simple_premark(answers_text);
+    let premark = premark_answers(base, settings, assignment_id, answers_text);
     let now_ms = unix_ms_now();
     let mut events = Vec::new();
     let start_event = build_event("", now_ms, "start", None, Some("session_start"));
@@ -368,6 +380,66 @@ fn build_event(
     }
 }
 
+pub fn assignment_events_path(base: &Path, assignment_id: &str) -> PathBuf {
+    let file: String = assignment_id
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    base.join("homework")
+        .join("events")
+        .join(format!("{file}.json"))
+}
+
+/// Events recorded against an assignment itself rather than one student's submission.
+pub fn load_assignment_events(base: &Path, assignment_id: &str) -> Vec<SubmissionEvent> {
+    fs::read_to_string(assignment_events_path(base, assignment_id))
+        .ok()
+        .and_then(|c| serde_json::from_str(&c).ok())
+        .unwrap_or_default()
+}
+
+/// How many `event_type` events an assignment's chain holds.
+pub fn assignment_event_count(base: &Path, assignment_id: &str, event_type: &str) -> usize {
+    load_assignment_events(base, assignment_id)
+        .iter()
+        .filter(|e| e.event_type == event_type)
+        .count()
+}
+
+/// Append an anonymous event to the assignment's chain: the payload is the running count of
+/// `event_type`, and the time is the day only, so nothing points back at a student.
+pub fn record_assignment_event(
+    base: &Path,
+    assignment_id: &str,
+    event_type: &str,
+) -> io::Result<SubmissionEvent> {
+    let path = assignment_events_path(base, assignment_id);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut events = load_assignment_events(base, assignment_id);
+    let count = events.iter().filter(|e| e.event_type == event_type).count() + 1;
+    const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+    let prev = events.last().map(|e| e.hash.as_str()).unwrap_or("");
+    let event = build_event(
+        prev,
+        unix_ms_now() / DAY_MS * DAY_MS,
+        event_type,
+        None,
+        Some(&count.to_string()),
+    );
+    events.push(event.clone());
+    let json = serde_json::to_string_pretty(&events)?;
+    fs::write(&path, json)?;
+    Ok(event)
+}
+
 fn unix_ms_now() -> i64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -454,6 +526,24 @@ fn system_time_millis(t: SystemTime) -> i128 {
         .unwrap_or(0)
 }
 
+/// Model pre-mark when the assignment allows it, otherwise (or if the model cannot give a
+/// valid mark) the length heuristic.
+fn premark_answers(
+    base: &Path,
+    settings: &Settings,
+    assignment_id: &str,
+    answers_text: &str,
+) -> AiPremark {
+    let assignment = find_latest_pack(base).ok().flatten().and_then(|(_, pack)| {
+        pack.assignments
+            .into_iter()
+            .find(|a| a.id == assignment_id && a.allow_ai_premark)
+    });
+    assignment
+        .and_then(|a| generate_premark(settings, &a, answers_text))
+        .unwrap_or_else(|| simple_premark(answers_text))
+}
+
 fn simple_premark(text: &str) -> AiPremark {
     let len = text.trim().len();
     let score = if len > 400 {
@@ -0,0 +1,275 @@
+use crate::safety::{self, Direction, JanetAction};
+use crate::settings::{JanetClassifier, Settings};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// One line of a labelled corpus: real student (or model) text and the category Janet should
+/// act on, or none when it should pass untouched.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LabelledText {
+    pub text: String,
+    /// Category name (policy file name, e.g. "profanity"); absent, null or "none" = allowed.
+    #[serde(default)]
+    pub category: Option<String>,
+    #[serde(default)]
+    pub direction: Direction,
+}
+
+impl LabelledText {
+    fn expected(&self) -> Option<&str> {
+        self.category
+            .as_deref()
+            .map(str::trim)
+            .filter(|c| !c.is_empty() && *c != "none")
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JanetCaseResult {
+    pub text: String,
+    pub direction: Direction,
+    pub expected: Option<String>,
+    /// Category Janet acted on, if any.
+    pub got: Option<String>,
+    pub action: Option<JanetAction>,
+}
+
+impl JanetCaseResult {
+    fn correct(&self) -> bool {
+        self.expected == self.got
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CategoryScore {
+    pub true_positives: usize,
+    pub false_positives: usize,
+    pub false_negatives: usize,
+    /// Of the texts Janet put in this category, the share labelled with it. None if it
+    /// caught nothing.
+    pub precision: Option<f64>,
+    /// Of the texts labelled with this category, the share Janet put in it. None if the
+    /// corpus has none.
+    pub recall: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JanetEvalReport {
+    pub corpus: String,
+    pub started_at: String,
+    pub janet_enabled: bool,
+    pub classifier: JanetClassifier,
+    pub locales: Vec<String>,
+    pub disabled_categories: Vec<String>,
+    pub total: usize,
+    pub correct: usize,
+    pub categories: BTreeMap<String, CategoryScore>,
+    /// Labelled allowed, but Janet acted on them.
+    pub wrongly_blocked: Vec<JanetCaseResult>,
+    /// Labelled with a category, but Janet let them through.
+    pub let_through: Vec<JanetCaseResult>,
+    /// Caught, but under a different category than labelled.
+    pub wrong_category: Vec<JanetCaseResult>,
+}
+
+pub fn load_corpus(path: &Path) -> io::Result<Vec<LabelledText>> {
+    let contents = fs::read_to_string(path)?;
+    let mut cases = Vec::new();
+    for (idx, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let case: LabelledText = serde_json::from_str(line).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("corpus line {}: {e}", idx + 1),
+            )
+        })?;
+        cases.push(case);
+    }
+    Ok(cases)
+}
+
+/// Default report location: `logs/janet_eval_<timestamp>.json`.
+pub fn default_report_path(base: &Path) -> PathBuf {
+    let stamp = chrono::Utc::now().format("%Y%m%d_%H%M%S");
+    base.join("logs").join(format!("janet_eval_{stamp}.json"))
+}
+
+fn ratio(hits: usize, total: usize) -> Option<f64> {
+    (total > 0).then(|| hits as f64 / total as f64)
+}
+
+fn score(results: &[JanetCaseResult]) -> BTreeMap<String, CategoryScore> {
+    let mut scores: BTreeMap<String, CategoryScore> = BTreeMap::new();
+    for result in results {
+        match (&result.expected, &result.got) {
+            (Some(expected), Some(got)) if expected == got => {
+                scores.entry(got.clone()).or_default().true_positives += 1;
+            }
+            (expected, got) => {
+                if let Some(expected) = expected {
+                    scores.entry(expected.clone()).or_default().false_negatives += 1;
+                }
+                if let Some(got) = got {
+                    scores.entry(got.clone()).or_default().false_positives += 1;
+                }
+            }
+        }
+    }
+    for score in scores.values_mut() {
+        score.precision = ratio(
+            score.true_positives,
+            score.true_positives + score.false_positives,
+        );
+        score.recall = ratio(
+            score.true_positives,
+            score.true_positives + score.false_negatives,
+        );
+    }
+    scores
+}
+
+/// Run every text in `corpus_path` through Janet as configured in `settings` (policy files,
+/// locales, allowlist, model check) and write a JSON report. Nothing is counted towards the
+/// wellbeing counter.
+pub fn run_corpus(
+    settings: &Settings,
+    corpus_path: &Path,
+    report_path: &Path,
+) -> io::Result<JanetEvalReport> {
+    let cases = load_corpus(corpus_path)?;
+    let started_at = chrono::Utc::now().to_rfc3339();
+    let results: Vec<JanetCaseResult> = cases
+        .iter()
+        .map(|case| {
+            let decision = safety::decision(settings, &case.text, case.direction);
+            JanetCaseResult {
+                text: case.text.clone(),
+                direction: case.direction,
+                expected: case.expected().map(str::to_string),
+                got: decision.as_ref().map(|(name, _)| name.clone()),
+                action: decision.map(|(_, action)| action),
+            }
+        })
+        .collect();
+
+    let pick = |keep: fn(&JanetCaseResult) -> bool| -> Vec<JanetCaseResult> {
+        results.iter().filter(|r| keep(r)).cloned().collect()
+    };
+    let report = JanetEvalReport {
+        corpus: corpus_path.display().to_string(),
+        started_at,
+        janet_enabled: settings.janet.enabled,
+        classifier: settings.janet.classifier,
+        locales: settings.janet.locales.clone(),
+        disabled_categories: settings.janet.disabled_categories.clone(),
+        total: results.len(),
+        correct: results.iter().filter(|r| r.correct()).count(),
+        categories: score(&results),
+        wrongly_blocked: pick(|r| r.expected.is_none() && r.got.is_some()),
+        let_through: pick(|r| r.expected.is_some() && r.got.is_none()),
+        wrong_category: pick(|r| r.expected.is_some() && r.got.is_some() && !r.correct()),
+    };
+
+    if let Some(parent) = report_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let json = serde_json::to_string_pretty(&report)?;
+    fs::write(report_path, json)?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::default_settings;
+
+    const FIXTURE: &str = r#"# comment lines and blank lines are skipped
+
+{"text": "I need help with my class assignment"}
+{"text": "this homework is shit", "category": "profanity"}
+{"text": "I want to kill myself", "category": "wellbeing"}
+{"text": "Why do owls kill mice?", "category": "none"}
+{"text": "You are a lovely person", "category": "profanity"}
+{"text": "this homework is shit", "category": "violence"}
+"#;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("chatty_janet_eval_{name}_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn counts_passes_and_failures_per_category() {
+        let dir = temp_dir("counts");
+        let corpus = dir.join("corpus.jsonl");
+        fs::write(&corpus, FIXTURE).unwrap();
+        let report_path = dir.join("logs").join("report.json");
+        let settings = default_settings(&dir);
+
+        let report = run_corpus(&settings, &corpus, &report_path).unwrap();
+        assert_eq!(report.total, 6);
+        assert_eq!(report.correct, 3);
+        assert_eq!(report.wrongly_blocked.len(), 1);
+        assert_eq!(report.let_through.len(), 1);
+        assert_eq!(report.wrong_category.len(), 1);
+
+        let profanity = &report.categories["profanity"];
+        assert_eq!(
+            (
+                profanity.true_positives,
+                profanity.false_positives,
+                profanity.false_negatives
+            ),
+            (1, 1, 1)
+        );
+        assert_eq!(profanity.precision, Some(0.5));
+        assert_eq!(profanity.recall, Some(0.5));
+        let wellbeing = &report.categories["wellbeing"];
+        assert_eq!(
+            (wellbeing.precision, wellbeing.recall),
+            (Some(1.0), Some(1.0))
+        );
+        let violence = &report.categories["violence"];
+        assert_eq!(violence.true_positives, 0);
+        assert_eq!(violence.recall, Some(0.0));
+
+        let written: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&report_path).unwrap()).unwrap();
+        assert_eq!(written["correct"], 3);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn with_janet_off_only_allowed_texts_count_as_correct() {
+        let dir = temp_dir("off");
+        let corpus = dir.join("corpus.jsonl");
+        fs::write(&corpus, FIXTURE).unwrap();
+        let mut settings = default_settings(&dir);
+        settings.janet.enabled = false;
+
+        let report = run_corpus(&settings, &corpus, &dir.join("report.json")).unwrap();
+        assert!(!report.janet_enabled);
+        assert_eq!((report.total, report.correct), (6, 2));
+        assert_eq!(report.let_through.len(), 4);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn bad_corpus_lines_are_reported_by_number() {
+        let dir = temp_dir("bad");
+        let corpus = dir.join("corpus.jsonl");
+        fs::write(&corpus, "{\"text\": \"fine\"}\nnot json\n").unwrap();
+        let err = load_corpus(&corpus).unwrap_err();
+        assert!(err.to_string().contains("corpus line 2"), "{err}");
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,366 @@
+use super::memory::{check_model_fits, MemoryVerdict};
+use super::{Completion, CompletionRequest, InferenceBackend, ModelStatus, Timings};
+use crate::settings::ModelConfig;
+use llama_cpp::{
+    grammar::LlamaGrammar,
+    standard_sampler::{SamplerStage, StandardSampler},
+    LlamaModel, LlamaParams, LlamaSession, SessionParams,
+};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use sha2::{Digest, Sha256};
+use std::collections::VecDeque;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::runtime::{Builder, Runtime};
+
+struct LoadedModel {
+    path: PathBuf,
+    model: Arc<LlamaModel>,
+    size_bytes: u64,
+    last_used: Instant,
+}
+
+static TOKIO_RUNTIME: Lazy<parking_lot::Mutex<Runtime>> = Lazy::new(|| {
+    parking_lot::Mutex::new(
+        Builder::new_current_thread()
+            .build()
+            .expect("Failed to build Tokio runtime"),
+    )
+});
+
+/// How many evaluated prompt prefixes to keep. Each holds a full KV cache, so this stays small:
+/// usually the chat capsule plus the assignment currently open in the hint helper.
+const MAX_PREFIX_SESSIONS: usize = 2;
+
+/// Context window for every session; also what the memory check budgets KV cache for.
+const CONTEXT_TOKENS: u32 = 2048;
+
+/// A session that has already evaluated a stable prefix (capsule + assignment context).
+/// Requests with the same prefix start from a copy of it instead of re-reading the capsule.
+struct PrefixSession {
+    key: String,
+    model_path: PathBuf,
+    session: LlamaSession,
+}
+
+/// In-process llama.cpp backend. Keeps recently used GGUFs loaded between requests, so tasks
+/// routed to different models do not reload on every switch. Least recently used models are
+/// dropped when the memory budget (or free RAM) runs out.
+#[derive(Default)]
+pub struct LlamaCppBackend {
+    loaded: Mutex<Vec<LoadedModel>>,
+    prefixes: Mutex<VecDeque<PrefixSession>>,
+    memory_warning: Mutex<Option<String>>,
+    /// Bytes of model weights the cache may hold; 0 leaves it to the free-memory check.
+    memory_budget: AtomicU64,
+}
+
+impl LlamaCppBackend {
+    pub fn set_memory_budget(&self, bytes: u64) {
+        self.memory_budget.store(bytes, Ordering::Relaxed);
+    }
+
+    /// Drop the least recently used model (and its evaluated prefixes). False if none loaded.
+    fn evict_lru(&self) -> bool {
+        let evicted = {
+            let mut loaded = self.loaded.lock();
+            let Some(idx) = loaded
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, m)| m.last_used)
+                .map(|(idx, _)| idx)
+            else {
+                return false;
+            };
+            loaded.remove(idx)
+        };
+        self.prefixes
+            .lock()
+            .retain(|p| p.model_path != evicted.path);
+        eprintln!("[models] Unloaded {} to make room", evicted.path.display());
+        true
+    }
+
+    fn load_model(&self, cfg: &ModelConfig, path: &Path) -> Result<LoadedModel, String> {
+        if !path.exists() {
+            return Err(format!("Model file not found: {}", path.display()));
+        }
+        let size_bytes = fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+
+        let budget = self.memory_budget.load(Ordering::Relaxed);
+        if budget > 0 {
+            while self.loaded.lock().iter().map(|m| m.size_bytes).sum::<u64>() + size_bytes > budget
+                && self.evict_lru()
+            {}
+        }
+
+        // Free RAM already excludes the models we hold, so make room before giving up.
+        let warning = loop {
+            match check_model_fits(path, CONTEXT_TOKENS as u64) {
+                MemoryVerdict::TooLarge(_) if self.evict_lru() => continue,
+                MemoryVerdict::TooLarge(msg) if !cfg.allow_low_memory_load => return Err(msg),
+                MemoryVerdict::TooLarge(msg) | MemoryVerdict::Tight(msg) => {
+                    eprintln!("[models] {msg}");
+                    break Some(msg);
+                }
+                MemoryVerdict::Fits | MemoryVerdict::Unknown => break None,
+            }
+        };
+        *self.memory_warning.lock() = warning;
+
+        let mut params = LlamaParams::default();
+        params.n_gpu_layers = 0; // CPU only for school devices
+        params.use_mmap = true;
+        params.use_mlock = false;
+
+        let model = LlamaModel::load_from_file(path, params)
+            .map_err(|e| format!("Failed to load model {}: {e}", path.display()))?;
+
+        Ok(LoadedModel {
+            path: path.to_path_buf(),
+            model: Arc::new(model),
+            size_bytes,
+            last_used: Instant::now(),
+        })
+    }
+
+    /// The cached model for `cfg`, loading it if needed. The duration is set when it was loaded.
+    fn get_or_load_model(
+        &self,
+        cfg: &ModelConfig,
+    ) -> Result<(Arc<LlamaModel>, Option<Duration>), String> {
+        let wanted_path = PathBuf::from(&cfg.path);
+
+        {
+            let mut loaded = self.loaded.lock();
+            if let Some(current) = loaded.iter_mut().find(|m| m.path == wanted_path) {
+                current.last_used = Instant::now();
+                return Ok((current.model.clone(), None));
+            }
+        }
+
+        // Loading takes seconds; do it without holding the lock.
+        let started = Instant::now();
+        let entry = self.load_model(cfg, &wanted_path)?;
+        let load_time = started.elapsed();
+        let model = entry.model.clone();
+        let mut loaded = self.loaded.lock();
+        loaded.retain(|m| m.path != wanted_path);
+        loaded.push(entry);
+        Ok((model, Some(load_time)))
+    }
+
+    fn touch(&self, path: &Path) {
+        if let Some(m) = self.loaded.lock().iter_mut().find(|m| m.path == path) {
+            m.last_used = Instant::now();
+        }
+    }
+
+    /// Copy of the cached session for `key`, if that prefix has already been evaluated.
+    fn checkout_prefix(&self, key: &str) -> Result<Option<LlamaSession>, String> {
+        let mut prefixes = self.prefixes.lock();
+        let Some(idx) = prefixes.iter().position(|p| p.key == key) else {
+            return Ok(None);
+        };
+        // Move to the back so the least recently used prefix is evicted first.
+        let entry = prefixes.remove(idx).expect("index from position");
+        let copy = entry
+            .session
+            .deep_copy()
+            .map_err(|e| format!("Could not copy model session: {e}"))?;
+        prefixes.push_back(entry);
+        Ok(Some(copy))
+    }
+
+    /// Drop every model nothing has used for `timeout`. Returns true when any were unloaded.
+    pub fn unload_if_idle(&self, timeout: Duration) -> bool {
+        let idle: Vec<PathBuf> = {
+            let mut loaded = self.loaded.lock();
+            let (idle, keep): (Vec<_>, Vec<_>) = loaded
+                .drain(..)
+                .partition(|m| m.last_used.elapsed() >= timeout);
+            *loaded = keep;
+            idle.into_iter().map(|m| m.path).collect()
+        };
+        if idle.is_empty() {
+            return false;
+        }
+        self.prefixes
+            .lock()
+            .retain(|p| !idle.contains(&p.model_path));
+        if self.loaded.lock().is_empty() {
+            *self.memory_warning.lock() = None;
+        }
+        true
+    }
+
+    pub fn status(&self) -> ModelStatus {
+        let loaded = self.loaded.lock();
+        let names: Vec<String> = loaded
+            .iter()
+            .map(|m| {
+                m.path
+                    .file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or_else(|| m.path.display().to_string())
+            })
+            .collect();
+        ModelStatus {
+            loaded: (!names.is_empty()).then(|| names.join(", ")),
+            idle: loaded.iter().map(|m| m.last_used.elapsed()).min(),
+            warning: self.memory_warning.lock().clone(),
+        }
+    }
+
+    fn store_prefix(&self, key: String, model_path: PathBuf, session: LlamaSession) {
+        let mut prefixes = self.prefixes.lock();
+        while prefixes.len() >= MAX_PREFIX_SESSIONS {
+            prefixes.pop_front();
+        }
+        prefixes.push_back(PrefixSession {
+            key,
+            model_path,
+            session,
+        });
+    }
+}
+
+fn session_params(cfg: &ModelConfig) -> SessionParams {
+    let mut session_params = SessionParams::default();
+    // Keep context modest for low-end machines while allowing a reasonable history window.
+    session_params.n_ctx = session_params.n_ctx.max(CONTEXT_TOKENS);
+    session_params.n_batch = session_params.n_batch.max(256);
+    session_params.n_ubatch = session_params.n_ubatch.max(128);
+    session_params.n_threads = session_params.n_threads.max(1);
+    session_params.n_threads_batch = session_params.n_threads_batch.max(1);
+    // IT overrides, tuned per hardware model from logs/inference_metrics.jsonl.
+    if cfg.n_threads > 0 {
+        session_params.n_threads = cfg.n_threads;
+        session_params.n_threads_batch = cfg.n_threads;
+    }
+    if cfg.n_batch > 0 {
+        session_params.n_batch = cfg.n_batch;
+        session_params.n_ubatch = session_params.n_ubatch.min(cfg.n_batch);
+    }
+    session_params
+}
+
+/// Cache key for an evaluated prefix. Changing the model file, the thread/batch settings or any
+/// byte of the capsule or assignment context produces a different key, which is what
+/// invalidates stale entries.
+fn prefix_key(cfg: &ModelConfig, prefix: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(cfg.path.as_bytes());
+    hasher.update([0u8]);
+    hasher.update(format!("{}/{}", cfg.n_threads, cfg.n_batch).as_bytes());
+    hasher.update([0u8]);
+    hasher.update(prefix.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+impl InferenceBackend for LlamaCppBackend {
+    fn name(&self) -> &'static str {
+        "llama.cpp"
+    }
+
+    fn complete(
+        &self,
+        cfg: &ModelConfig,
+        request: &CompletionRequest,
+    ) -> Result<Completion, String> {
+        // The llama_cpp bindings do not expose llama.cpp's LoRA API. Answering with the base
+        // model instead would quietly drop the subject tuning the teacher set up.
+        if let Some(adapter) = &request.adapter {
+            return Err(format!(
+                "Cannot apply adapter {}: the in-process backend has no LoRA support. Use the local server backend (llama-server started with --lora).",
+                adapter.display()
+            ));
+        }
+        let (model, load_time) = self.get_or_load_model(cfg)?;
+
+        let prompt_started = Instant::now();
+        let prefix = format!("{}\n\n", request.system);
+        let key = prefix_key(cfg, &prefix);
+        let mut session = match self.checkout_prefix(&key)? {
+            Some(session) => session,
+            None => {
+                let mut base = model
+                    .create_session(session_params(cfg))
+                    .map_err(|e| format!("Failed to create model session: {e}"))?;
+                base.advance_context(prefix.as_bytes())
+                    .map_err(|e| format!("Could not feed prompt into model: {e}"))?;
+                let session = base
+                    .deep_copy()
+                    .map_err(|e| format!("Could not copy model session: {e}"))?;
+                self.store_prefix(key, PathBuf::from(&cfg.path), base);
+                session
+            }
+        };
+
+        let turn = format!("User: {}\nAssistant:", request.user);
+        session
+            .advance_context(turn.as_bytes())
+            .map_err(|e| format!("Could not feed prompt into model: {e}"))?;
+        let prompt_eval = prompt_started.elapsed();
+
+        let sampler = match &request.grammar {
+            Some(gbnf) => {
+                let grammar = LlamaGrammar::from_str(gbnf)
+                    .map_err(|e| format!("Invalid output grammar: {e}"))?;
+                // Low temperature: structured output should be the model's best guess.
+                StandardSampler::new_softmax(vec![SamplerStage::Temperature(0.2)], 1, Some(grammar))
+            }
+            None => StandardSampler::default(),
+        };
+        let max_predictions = request.max_tokens.max(16) as usize;
+        let generation_started = Instant::now();
+        let handle = session
+            .start_completing_with(sampler, max_predictions)
+            .map_err(|e| format!("Model could not start completion: {e}"))?;
+
+        let output = match request.time_limit {
+            Some(limit) => {
+                let deadline = Instant::now() + limit;
+                let mut output = String::new();
+                for piece in handle.into_strings() {
+                    output.push_str(&piece);
+                    if Instant::now() >= deadline {
+                        eprintln!(
+                            "[llama] Generation stopped at the {}s limit",
+                            limit.as_secs()
+                        );
+                        break;
+                    }
+                }
+                output
+            }
+            None => TOKIO_RUNTIME.lock().block_on(handle.into_string_async()),
+        };
+        let generation = generation_started.elapsed();
+        self.touch(Path::new(&cfg.path));
+        let tokens = model
+            .tokenize_bytes(output.as_bytes(), false, false)
+            .ok()
+            .map(|t| t.len());
+        Ok(Completion {
+            text: output,
+            tokens,
+            timings: Timings {
+                load: load_time,
+                prompt_eval: Some(prompt_eval),
+                generation: Some(generation),
+            },
+        })
+    }
+
+    fn unload(&self) {
+        self.prefixes.lock().clear();
+        self.loaded.lock().clear();
+        *self.memory_warning.lock() = None;
+    }
+}
@@ -0,0 +1,304 @@
+use super::{Completion, CompletionRequest, InferenceBackend, Timings};
+use crate::settings::ModelConfig;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const IO_TIMEOUT: Duration = Duration::from_secs(300);
+/// How long a server's adapter list is reused. llama-server fixes its adapters at startup, so
+/// this only has to notice a restart with different `--lora` flags.
+const ADAPTER_LIST_TTL: Duration = Duration::from_secs(60);
+
+type AdapterList = Result<Vec<Value>, String>;
+
+/// `GET /lora-adapters` results by server, so requests do not pay an extra round trip each.
+/// Backends are built per request, hence a static rather than a field.
+static ADAPTER_LISTS: Lazy<Mutex<HashMap<String, (Instant, AdapterList)>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// OpenAI-compatible client for a llama-server running on the same machine.
+/// Only loopback endpoints are accepted; anything else is refused at construction.
+pub struct LocalHttpBackend {
+    host: String,
+    addrs: Vec<SocketAddr>,
+    path_prefix: String,
+}
+
+impl LocalHttpBackend {
+    pub fn new(endpoint: &str) -> Result<Self, String> {
+        let rest = endpoint
+            .trim()
+            .strip_prefix("http://")
+            .ok_or_else(|| format!("Local server endpoint must start with http:// ({endpoint})"))?;
+        let (authority, path) = match rest.find('/') {
+            Some(idx) => (&rest[..idx], rest[idx..].trim_end_matches('/')),
+            None => (rest, ""),
+        };
+        let (host, port) = split_host_port(authority)?;
+        if !matches!(host.as_str(), "localhost" | "127.0.0.1" | "::1") {
+            return Err(format!(
+                "Local server endpoint must be on this machine (localhost), got {host}"
+            ));
+        }
+
+        let addrs: Vec<SocketAddr> = (host.as_str(), port)
+            .to_socket_addrs()
+            .map_err(|e| format!("Could not resolve {host}: {e}"))?
+            .filter(|a| a.ip().is_loopback())
+            .collect();
+        if addrs.is_empty() {
+            return Err(format!("{host} did not resolve to a loopback address"));
+        }
+
+        Ok(Self {
+            host: if host.contains(':') {
+                format!("[{host}]:{port}")
+            } else {
+                format!("{host}:{port}")
+            },
+            addrs,
+            path_prefix: path.to_string(),
+        })
+    }
+
+    fn post_json(&self, path: &str, body: &Value, timeout: Duration) -> Result<Value, String> {
+        self.send_json("POST", path, Some(body), timeout)
+    }
+
+    fn get_json(&self, path: &str) -> Result<Value, String> {
+        self.send_json("GET", path, None, CONNECT_TIMEOUT)
+    }
+
+    fn send_json(
+        &self,
+        method: &str,
+        path: &str,
+        body: Option<&Value>,
+        timeout: Duration,
+    ) -> Result<Value, String> {
+        let payload = body.map(Value::to_string).unwrap_or_default();
+        let mut stream = self
+            .addrs
+            .iter()
+            .find_map(|addr| TcpStream::connect_timeout(addr, CONNECT_TIMEOUT).ok())
+            .ok_or_else(|| format!("Could not connect to local model server at {}", self.host))?;
+        stream.set_read_timeout(Some(timeout)).ok();
+        stream.set_write_timeout(Some(timeout)).ok();
+
+        let request = format!(
+            "{method} {prefix}{path} HTTP/1.1\r\nHost: {host}\r\nContent-Type: application/json\r\nAccept: application/json\r\nContent-Length: {len}\r\nConnection: close\r\n\r\n{payload}",
+            prefix = self.path_prefix,
+            host = self.host,
+            len = payload.len(),
+        );
+        stream
+            .write_all(request.as_bytes())
+            .map_err(|e| format!("Could not send request to local model server: {e}"))?;
+
+        let mut raw = Vec::new();
+        stream
+            .read_to_end(&mut raw)
+            .map_err(|e| format!("Could not read local model server response: {e}"))?;
+
+        let (status, body) = parse_http_response(&raw)?;
+        if !(200..300).contains(&status) {
+            let snippet: String = String::from_utf8_lossy(&body).chars().take(200).collect();
+            return Err(format!(
+                "Local model server returned HTTP {status}: {snippet}"
+            ));
+        }
+        serde_json::from_slice(&body)
+            .map_err(|e| format!("Local model server sent invalid JSON: {e}"))
+    }
+
+    /// Per-request adapter scales for llama-server: `adapter` (matched by file name against
+    /// the adapters the server was started with via `--lora`) at full strength, every other
+    /// adapter off. The server keeps the base weights loaded, so switching costs nothing.
+    /// An adapter the server cannot apply is an error rather than a base-model answer.
+    fn lora_scales(&self, adapter: Option<&Path>) -> Result<Option<Value>, String> {
+        let loaded = match (self.server_adapters(), adapter) {
+            (Ok(list), _) => list,
+            (Err(_), None) => return Ok(None),
+            (Err(e), Some(adapter)) => {
+                return Err(format!(
+                    "Cannot apply adapter {}: could not list the local server's adapters ({e}).",
+                    adapter.display()
+                ))
+            }
+        };
+        let wanted = adapter.and_then(Path::file_name);
+        let mut matched = false;
+        let scales: Vec<Value> = loaded
+            .iter()
+            .filter_map(|entry| {
+                let id = entry["id"].as_u64()?;
+                let on = wanted.is_some()
+                    && entry["path"]
+                        .as_str()
+                        .map(Path::new)
+                        .and_then(Path::file_name)
+                        == wanted;
+                matched |= on;
+                Some(json!({ "id": id, "scale": if on { 1.0 } else { 0.0 } }))
+            })
+            .collect();
+        if let (Some(adapter), false) = (adapter, matched) {
+            return Err(format!(
+                "Cannot apply adapter {}: the local model server was not started with it (add --lora).",
+                adapter.display()
+            ));
+        }
+        Ok((!scales.is_empty()).then_some(Value::Array(scales)))
+    }
+
+    /// Adapters the server was started with, from the cache when it is fresh. Servers without
+    /// the endpoint (not llama-server) report an empty list.
+    fn server_adapters(&self) -> AdapterList {
+        let key = format!("{}{}", self.host, self.path_prefix);
+        if let Some((fetched, list)) = ADAPTER_LISTS.lock().get(&key) {
+            if fetched.elapsed() < ADAPTER_LIST_TTL {
+                return list.clone();
+            }
+        }
+        let list = match self.get_json("/lora-adapters") {
+            Ok(Value::Array(list)) => Ok(list),
+            Ok(_) => Ok(Vec::new()),
+            Err(e) => Err(e),
+        };
+        ADAPTER_LISTS
+            .lock()
+            .insert(key, (Instant::now(), list.clone()));
+        list
+    }
+}
+
+impl InferenceBackend for LocalHttpBackend {
+    fn name(&self) -> &'static str {
+        "local-http"
+    }
+
+    fn complete(
+        &self,
+        cfg: &ModelConfig,
+        request: &CompletionRequest,
+    ) -> Result<Completion, String> {
+        let mut body = json!({
+            "model": cfg.name,
+            "messages": [
+                { "role": "system", "content": request.system },
+                { "role": "user", "content": request.user },
+            ],
+            "max_tokens": request.max_tokens.max(16),
+            "stream": false,
+        });
+        if let Some(grammar) = &request.grammar {
+            // llama-server extension to the OpenAI API.
+            body["grammar"] = Value::String(grammar.clone());
+        }
+        if let Some(lora) = self.lora_scales(request.adapter.as_deref())? {
+            body["lora"] = lora;
+        }
+        let timeout = request.time_limit.unwrap_or(IO_TIMEOUT);
+        let response = self.post_json("/v1/chat/completions", &body, timeout)?;
+        let text = response["choices"][0]["message"]["content"]
+            .as_str()
+            .map(|s| s.to_string())
+            .ok_or_else(|| "Local model server response had no message content".to_string())?;
+        // llama-server reports its own stage timings; other servers leave them empty.
+        let stage = |key: &str| {
+            response["timings"][key]
+                .as_f64()
+                .map(|ms| Duration::from_secs_f64(ms / 1000.0))
+        };
+        Ok(Completion {
+            text,
+            tokens: response["usage"]["completion_tokens"]
+                .as_u64()
+                .map(|n| n as usize),
+            timings: Timings {
+                load: None,
+                prompt_eval: stage("prompt_ms"),
+                generation: stage("predicted_ms"),
+            },
+        })
+    }
+}
+
+fn split_host_port(authority: &str) -> Result<(String, u16), String> {
+    // Bracketed IPv6: [::1]:8080
+    if let Some(rest) = authority.strip_prefix('[') {
+        let (host, tail) = rest
+            .split_once(']')
+            .ok_or_else(|| format!("Malformed endpoint host: {authority}"))?;
+        let port = match tail.strip_prefix(':') {
+            Some(p) => p
+                .parse()
+                .map_err(|_| format!("Invalid port in {authority}"))?,
+            None => 80,
+        };
+        return Ok((host.to_string(), port));
+    }
+    match authority.rsplit_once(':') {
+        Some((host, port)) => Ok((
+            host.to_lowercase(),
+            port.parse()
+                .map_err(|_| format!("Invalid port in {authority}"))?,
+        )),
+        None => Ok((authority.to_lowercase(), 80)),
+    }
+}
+
+fn parse_http_response(raw: &[u8]) -> Result<(u16, Vec<u8>), String> {
+    let header_end = raw
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or_else(|| "Local model server sent an incomplete response".to_string())?;
+    let head = String::from_utf8_lossy(&raw[..header_end]);
+    let body = &raw[header_end + 4..];
+
+    let mut lines = head.lines();
+    let status = lines
+        .next()
+        .and_then(|l| l.split_whitespace().nth(1))
+        .and_then(|s| s.parse::<u16>().ok())
+        .ok_or_else(|| "Local model server sent a malformed status line".to_string())?;
+    let chunked = lines.any(|l| {
+        let lower = l.to_ascii_lowercase();
+        lower.starts_with("transfer-encoding:") && lower.contains("chunked")
+    });
+
+    if chunked {
+        Ok((status, decode_chunked(body)?))
+    } else {
+        Ok((status, body.to_vec()))
+    }
+}
+
+fn decode_chunked(mut body: &[u8]) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    loop {
+        let line_end = body
+            .windows(2)
+            .position(|w| w == b"\r\n")
+            .ok_or_else(|| "Truncated chunked response".to_string())?;
+        let size_str = String::from_utf8_lossy(&body[..line_end]);
+        let size_hex = size_str.split(';').next().unwrap_or("").trim();
+        let size = usize::from_str_radix(size_hex, 16)
+            .map_err(|_| format!("Bad chunk size: {size_hex}"))?;
+        body = &body[line_end + 2..];
+        if size == 0 {
+            return Ok(out);
+        }
+        if body.len() < size {
+            return Err("Truncated chunked response".to_string());
+        }
+        out.extend_from_slice(&body[..size]);
+        body = body.get(size + 2..).unwrap_or(&[]);
+    }
+}
@@ -0,0 +1,189 @@
+use crate::model_catalog::{format_bytes, read_gguf_info};
+use std::fs;
+use std::path::Path;
+
+/// Headroom for llama.cpp compute buffers, the app itself and the OS on top of weights + KV.
+const OVERHEAD_BYTES: u64 = 384 * 1024 * 1024;
+/// Warn once a load would use more than this share of the memory that is currently free.
+const WARN_RATIO: f64 = 0.8;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MemoryVerdict {
+    Fits,
+    Tight(String),
+    TooLarge(String),
+    /// The platform gave no reading, so the load goes ahead unchecked.
+    Unknown,
+}
+
+/// Estimate what loading `path` with an `n_ctx` context needs and compare it with free RAM.
+pub fn check_model_fits(path: &Path, n_ctx: u64) -> MemoryVerdict {
+    let Some(available) = available_memory_bytes() else {
+        return MemoryVerdict::Unknown;
+    };
+    let file_size = fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+    let kv = read_gguf_info(path)
+        .ok()
+        .and_then(|info| info.kv_cache_bytes(n_ctx))
+        .unwrap_or(0);
+    let required = file_size + kv + OVERHEAD_BYTES;
+
+    if required > available {
+        MemoryVerdict::TooLarge(format!(
+            "This model needs about {} of memory but only {} is free. Close other programs or choose a smaller model.",
+            format_bytes(required),
+            format_bytes(available)
+        ))
+    } else if required as f64 > available as f64 * WARN_RATIO {
+        MemoryVerdict::Tight(format!(
+            "This model needs about {} of the {} currently free; the computer may slow down.",
+            format_bytes(required),
+            format_bytes(available)
+        ))
+    } else {
+        MemoryVerdict::Fits
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub fn available_memory_bytes() -> Option<u64> {
+    let meminfo = fs::read_to_string("/proc/meminfo").ok()?;
+    meminfo
+        .lines()
+        .find(|l| l.starts_with("MemAvailable:"))
+        .and_then(|l| l.split_whitespace().nth(1))
+        .and_then(|kb| kb.parse::<u64>().ok())
+        .map(|kb| kb * 1024)
+}
+
+#[cfg(windows)]
+pub fn available_memory_bytes() -> Option<u64> {
+    #[repr(C)]
+    struct MemoryStatusEx {
+        length: u32,
+        memory_load: u32,
+        total_phys: u64,
+        avail_phys: u64,
+        total_page_file: u64,
+        avail_page_file: u64,
+        total_virtual: u64,
+        avail_virtual: u64,
+        avail_extended_virtual: u64,
+    }
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GlobalMemoryStatusEx(buffer: *mut MemoryStatusEx) -> i32;
+    }
+
+    let mut status = MemoryStatusEx {
+        length: std::mem::size_of::<MemoryStatusEx>() as u32,
+        memory_load: 0,
+        total_phys: 0,
+        avail_phys: 0,
+        total_page_file: 0,
+        avail_page_file: 0,
+        total_virtual: 0,
+        avail_virtual: 0,
+        avail_extended_virtual: 0,
+    };
+    // SAFETY: `status` is a correctly sized MEMORYSTATUSEX with `length` set, as the API requires.
+    let ok = unsafe { GlobalMemoryStatusEx(&mut status) };
+    (ok != 0).then_some(status.avail_phys)
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+pub fn available_memory_bytes() -> Option<u64> {
+    None
+}
+
+/// Resident memory of this process right now and the most it has used since the peak was last
+/// reset (or since it started), in bytes.
+#[derive(Debug, Clone, Copy)]
+pub struct ProcessMemory {
+    pub resident: u64,
+    pub peak: u64,
+}
+
+#[cfg(target_os = "linux")]
+pub fn process_memory() -> Option<ProcessMemory> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let field = |name: &str| {
+        status
+            .lines()
+            .find(|l| l.starts_with(name))
+            .and_then(|l| l.split_whitespace().nth(1))
+            .and_then(|kb| kb.parse::<u64>().ok())
+            .map(|kb| kb * 1024)
+    };
+    Some(ProcessMemory {
+        resident: field("VmRSS:")?,
+        peak: field("VmHWM:")?,
+    })
+}
+
+/// Start a new peak at the current resident size, so the next `process_memory` reports the
+/// most used since now. Writing 5 to `clear_refs` resets VmHWM. False where the peak cannot be
+/// reset (it then stays the all-time peak).
+#[cfg(target_os = "linux")]
+pub fn reset_peak_memory() -> bool {
+    fs::write("/proc/self/clear_refs", "5").is_ok()
+}
+
+#[cfg(windows)]
+pub fn process_memory() -> Option<ProcessMemory> {
+    #[repr(C)]
+    struct ProcessMemoryCounters {
+        cb: u32,
+        page_fault_count: u32,
+        peak_working_set_size: usize,
+        working_set_size: usize,
+        quota_peak_paged_pool_usage: usize,
+        quota_paged_pool_usage: usize,
+        quota_peak_non_paged_pool_usage: usize,
+        quota_non_paged_pool_usage: usize,
+        pagefile_usage: usize,
+        peak_pagefile_usage: usize,
+    }
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetCurrentProcess() -> isize;
+        fn K32GetProcessMemoryInfo(
+            process: isize,
+            counters: *mut ProcessMemoryCounters,
+            cb: u32,
+        ) -> i32;
+    }
+
+    let size = std::mem::size_of::<ProcessMemoryCounters>() as u32;
+    let mut counters = ProcessMemoryCounters {
+        cb: size,
+        page_fault_count: 0,
+        peak_working_set_size: 0,
+        working_set_size: 0,
+        quota_peak_paged_pool_usage: 0,
+        quota_paged_pool_usage: 0,
+        quota_peak_non_paged_pool_usage: 0,
+        quota_non_paged_pool_usage: 0,
+        pagefile_usage: 0,
+        peak_pagefile_usage: 0,
+    };
+    // SAFETY: the pseudo-handle from GetCurrentProcess is always valid and `counters` is a
+    // correctly sized PROCESS_MEMORY_COUNTERS with `cb` set.
+    let ok = unsafe { K32GetProcessMemoryInfo(GetCurrentProcess(), &mut counters, size) };
+    (ok != 0).then_some(ProcessMemory {
+        resident: counters.working_set_size as u64,
+        peak: counters.peak_working_set_size as u64,
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn reset_peak_memory() -> bool {
+    false
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+pub fn process_memory() -> Option<ProcessMemory> {
+    None
+}
@@ -0,0 +1,222 @@
+use super::{Completion, CompletionRequest, InferenceBackend};
+use crate::settings::ModelConfig;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// One canned reply: fires when the user text contains `when_contains` (case-insensitive).
+/// With `adapter` set it only fires for requests using an adapter with that file name, so
+/// scripts can check which adapter a subject was routed to.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ScriptRule {
+    pub when_contains: String,
+    pub reply: String,
+    #[serde(default)]
+    pub adapter: Option<String>,
+}
+
+/// Deterministic backend for tests and classroom demos. Rules are checked in order and the
+/// first match wins; anything unmatched gets `default_reply`. No model file is needed.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ScriptedBackend {
+    #[serde(default)]
+    pub rules: Vec<ScriptRule>,
+    #[serde(default = "default_reply")]
+    pub default_reply: String,
+}
+
+fn default_reply() -> String {
+    "This is a scripted demo reply. Ask your teacher to select a real model for full answers."
+        .to_string()
+}
+
+impl Default for ScriptedBackend {
+    fn default() -> Self {
+        Self {
+            rules: Vec::new(),
+            default_reply: default_reply(),
+        }
+    }
+}
+
+impl ScriptedBackend {
+    #[allow(dead_code)]
+    pub fn new(rules: Vec<ScriptRule>, default_reply: &str) -> Self {
+        Self {
+            rules,
+            default_reply: default_reply.to_string(),
+        }
+    }
+
+    /// Load a script from a JSON file (`{"rules": [...], "default_reply": "..."}`).
+    /// An empty path yields the built-in demo script so the mock always answers.
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        if path.as_os_str().is_empty() {
+            return Ok(Self::default());
+        }
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read mock script {}: {e}", path.display()))?;
+        serde_json::from_str(&contents)
+            .map_err(|e| format!("Invalid mock script {}: {e}", path.display()))
+    }
+}
+
+impl InferenceBackend for ScriptedBackend {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn complete(
+        &self,
+        _cfg: &ModelConfig,
+        request: &CompletionRequest,
+    ) -> Result<Completion, String> {
+        let haystack = request.user.to_lowercase();
+        let adapter = request
+            .adapter
+            .as_deref()
+            .and_then(Path::file_name)
+            .map(|n| n.to_string_lossy().to_string());
+        let reply = self
+            .rules
+            .iter()
+            .filter(|r| r.adapter.is_none() || r.adapter == adapter)
+            .find(|r| haystack.contains(&r.when_contains.to_lowercase()))
+            .map(|r| r.reply.clone())
+            .unwrap_or_else(|| self.default_reply.clone());
+        Ok(Completion {
+            tokens: Some(reply.split_whitespace().count()),
+            text: reply,
+            ..Completion::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::ModelBackend;
+    use std::path::PathBuf;
+
+    pub(crate) fn mock_config(script: &str) -> ModelConfig {
+        ModelConfig {
+            name: "mock".to_string(),
+            path: "unused.gguf".to_string(),
+            max_tokens: 64,
+            backend: ModelBackend::Mock,
+            endpoint: String::new(),
+            idle_unload_secs: 0,
+            allow_low_memory_load: false,
+            n_threads: 0,
+            n_batch: 0,
+            script: script.to_string(),
+        }
+    }
+
+    fn request(user: &str, adapter: Option<&str>) -> CompletionRequest {
+        CompletionRequest {
+            system: String::new(),
+            user: user.to_string(),
+            max_tokens: 64,
+            grammar: None,
+            time_limit: None,
+            adapter: adapter.map(PathBuf::from),
+        }
+    }
+
+    fn rule(when: &str, reply: &str, adapter: Option<&str>) -> ScriptRule {
+        ScriptRule {
+            when_contains: when.to_string(),
+            reply: reply.to_string(),
+            adapter: adapter.map(str::to_string),
+        }
+    }
+
+    fn reply(backend: &ScriptedBackend, user: &str, adapter: Option<&str>) -> String {
+        backend
+            .complete(&mock_config(""), &request(user, adapter))
+            .expect("mock always answers")
+            .text
+    }
+
+    #[test]
+    fn first_matching_rule_wins_case_insensitively() {
+        let backend = ScriptedBackend::new(
+            vec![
+                rule("photosynthesis", "Plants use light.", None),
+                rule("plant", "Plants grow.", None),
+            ],
+            "fallback",
+        );
+        assert_eq!(
+            reply(&backend, "What is PHOTOSYNTHESIS in a plant?", None),
+            "Plants use light."
+        );
+        assert_eq!(reply(&backend, "Do plants sleep?", None), "Plants grow.");
+        assert_eq!(reply(&backend, "What is a volcano?", None), "fallback");
+    }
+
+    #[test]
+    fn adapter_rules_only_fire_for_that_adapter() {
+        let backend = ScriptedBackend::new(
+            vec![
+                rule("hint", "maths hint", Some("maths.gguf")),
+                rule("hint", "base hint", None),
+            ],
+            "fallback",
+        );
+        assert_eq!(
+            reply(&backend, "a hint please", Some("models/maths.gguf")),
+            "maths hint"
+        );
+        assert_eq!(
+            reply(&backend, "a hint please", Some("models/science.gguf")),
+            "base hint"
+        );
+        assert_eq!(reply(&backend, "a hint please", None), "base hint");
+    }
+
+    #[test]
+    fn counts_reply_words_as_tokens() {
+        let backend = ScriptedBackend::new(Vec::new(), "three little words");
+        let completion = backend
+            .complete(&mock_config(""), &request("anything", None))
+            .unwrap();
+        assert_eq!(completion.tokens, Some(3));
+    }
+
+    #[test]
+    fn empty_script_path_uses_demo_script() {
+        let backend = ScriptedBackend::from_file(Path::new("")).unwrap();
+        assert_eq!(backend.default_reply, default_reply());
+        assert!(backend.rules.is_empty());
+    }
+
+    #[test]
+    fn script_files_load_and_errors_are_reported() {
+        let dir = std::env::temp_dir().join(format!("chatty_mock_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let good = dir.join("script.json");
+        fs::write(
+            &good,
+            r#"{"rules": [{"when_contains": "owl", "reply": "Owls hunt at night."}]}"#,
+        )
+        .unwrap();
+        let backend = ScriptedBackend::from_file(&good).unwrap();
+        assert_eq!(
+            reply(&backend, "tell me about owls", None),
+            "Owls hunt at night."
+        );
+        assert_eq!(backend.default_reply, default_reply());
+
+        let bad = dir.join("bad.json");
+        fs::write(&bad, "{ not json").unwrap();
+        assert!(ScriptedBackend::from_file(&bad)
+            .unwrap_err()
+            .contains("Invalid mock script"));
+        assert!(ScriptedBackend::from_file(&dir.join("missing.json"))
+            .unwrap_err()
+            .contains("Could not read"));
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,300 @@
+mod llama;
+mod local_http;
+mod memory;
+mod mock;
+mod structured;
+mod telemetry;
+
+pub use llama::LlamaCppBackend;
+pub use local_http::LocalHttpBackend;
+#[allow(unused_imports)]
+pub use mock::{ScriptRule, ScriptedBackend};
+#[allow(unused_imports)]
+pub use structured::{complete_json, schema_to_gbnf, validate};
+pub use telemetry::{
+    memory_per_request, metrics_log_path, set_metrics_log, summarize_metrics, MetricsSummary,
+};
+
+use crate::policy::PolicyConfig;
+use crate::settings::{ModelBackend, ModelConfig};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Once};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Minimal system prompt to keep answers friendly and concise for students.
+pub const DEFAULT_SYSTEM_PROMPT: &str =
+    "You are Chatty-EDU, an offline school AI helper. Answer plainly, safely, and briefly.";
+
+#[derive(Debug, Clone)]
+pub struct CompletionRequest {
+    pub system: String,
+    pub user: String,
+    pub max_tokens: u32,
+    /// GBNF grammar that sampling must follow. Backends that cannot constrain sampling
+    /// ignore it, so callers still validate the output (see `complete_json`).
+    pub grammar: Option<String>,
+    /// Stop generating after this long and return what has been produced so far.
+    pub time_limit: Option<Duration>,
+    /// LoRA adapter to apply on top of the base model for this request (None = base only).
+    pub adapter: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Completion {
+    pub text: String,
+    /// Generated tokens, when the backend can count them.
+    pub tokens: Option<usize>,
+    pub timings: Timings,
+}
+
+/// Where the time in one request went, as far as the backend can tell.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Timings {
+    /// Loading the model file, when this request had to load it.
+    pub load: Option<Duration>,
+    /// Reading the prompt (capsule, context and question) into the model.
+    pub prompt_eval: Option<Duration>,
+    pub generation: Option<Duration>,
+}
+
+/// Anything that can turn a prompt into text. Implementations must be safe to share
+/// between the GUI thread and worker threads.
+pub trait InferenceBackend: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn complete(
+        &self,
+        cfg: &ModelConfig,
+        request: &CompletionRequest,
+    ) -> Result<Completion, String>;
+    /// Drop any cached weights or sessions. Backends without state can ignore this.
+    fn unload(&self) {}
+}
+
+/// What the status indicator shows for the in-process model.
+#[derive(Debug, Clone, Default)]
+pub struct ModelStatus {
+    /// File name of the loaded GGUF, if any.
+    pub loaded: Option<String>,
+    pub idle: Option<Duration>,
+    /// Memory warning raised when the model was loaded.
+    pub warning: Option<String>,
+}
+
+/// How often the idle watchdog looks at the loaded model.
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(15);
+
+static IDLE_UNLOAD_SECS: AtomicU64 = AtomicU64::new(0);
+static IDLE_WATCHDOG: Once = Once::new();
+
+static LLAMA: Lazy<Arc<LlamaCppBackend>> = Lazy::new(|| Arc::new(LlamaCppBackend::default()));
+static POLICY: Lazy<RwLock<PolicyConfig>> = Lazy::new(|| RwLock::new(PolicyConfig::default()));
+static INSTALLED: Lazy<RwLock<Option<Arc<dyn InferenceBackend>>>> = Lazy::new(|| RwLock::new(None));
+
+/// Record the school policy that gates which backends may be used.
+pub fn set_policy(policy: PolicyConfig) {
+    *POLICY.write() = policy;
+}
+
+/// Route every request to `backend` regardless of settings (tests and demos).
+/// Pass `None` to go back to the configured backend.
+#[allow(dead_code)]
+pub fn install_backend(backend: Option<Arc<dyn InferenceBackend>>) {
+    *INSTALLED.write() = backend;
+}
+
+/// Cap on memory for models held by the in-process cache, in MB (0 = decide from free RAM).
+pub fn set_memory_budget_mb(mb: u64) {
+    LLAMA.set_memory_budget(mb * 1024 * 1024);
+}
+
+pub fn clear_cached_model() {
+    LLAMA.unload();
+}
+
+pub fn model_status() -> ModelStatus {
+    LLAMA.status()
+}
+
+/// Start (once) a background thread that unloads the in-process model after
+/// `idle_unload_secs` without requests, so a laptop gets its memory back between lessons.
+fn ensure_idle_watchdog(cfg: &ModelConfig) {
+    IDLE_UNLOAD_SECS.store(cfg.idle_unload_secs, Ordering::Relaxed);
+    IDLE_WATCHDOG.call_once(|| {
+        let spawned = thread::Builder::new()
+            .name("model-idle-watchdog".to_string())
+            .spawn(|| loop {
+                thread::sleep(IDLE_CHECK_INTERVAL);
+                let secs = IDLE_UNLOAD_SECS.load(Ordering::Relaxed);
+                if secs > 0 && LLAMA.unload_if_idle(Duration::from_secs(secs)) {
+                    eprintln!("[models] Unloaded model after {secs}s idle");
+                }
+            });
+        if let Err(e) = spawned {
+            eprintln!("[models] Could not start idle watchdog: {e}");
+        }
+    });
+}
+
+fn backend_for(cfg: &ModelConfig) -> Result<Arc<dyn InferenceBackend>, String> {
+    if let Some(backend) = INSTALLED.read().clone() {
+        return Ok(backend);
+    }
+
+    match cfg.backend {
+        ModelBackend::LlamaCpp => {
+            ensure_idle_watchdog(cfg);
+            Ok(LLAMA.clone())
+        }
+        ModelBackend::Mock => Ok(Arc::new(ScriptedBackend::from_file(Path::new(
+            &cfg.script,
+        ))?)),
+        ModelBackend::LocalHttp => {
+            if !POLICY.read().allow_localhost_inference {
+                return Err(
+                    "The local server backend is disabled by school policy (allow_localhost_inference)."
+                        .to_string(),
+                );
+            }
+            Ok(Arc::new(LocalHttpBackend::new(&cfg.endpoint)?))
+        }
+    }
+}
+
+/// Why `cfg` cannot apply LoRA adapters, or None if it can. Only llama-server (the local server
+/// backend) switches adapters per request; the mock backend matches them by name.
+pub fn adapter_support_error(cfg: &ModelConfig) -> Option<String> {
+    (cfg.backend == ModelBackend::LlamaCpp).then(|| {
+        format!(
+            "{} uses the in-process backend, which cannot apply LoRA adapters. Use the local server backend (llama-server started with --lora) or remove the adapter.",
+            cfg.name
+        )
+    })
+}
+
+pub fn complete(cfg: &ModelConfig, request: &CompletionRequest) -> Result<Completion, String> {
+    let backend = backend_for(cfg)?;
+    let memory = telemetry::MemorySample::start();
+    let started = Instant::now();
+    let result = backend.complete(cfg, request);
+    telemetry::record(
+        cfg,
+        backend.name(),
+        result.as_ref().ok(),
+        started.elapsed(),
+        memory,
+    );
+    let output = result.map_err(|e| format!("[{}] {e}", backend.name()))?;
+
+    let cleaned = output.text.trim().to_string();
+    if cleaned.is_empty() {
+        Err("Model returned an empty response".to_string())
+    } else {
+        Ok(Completion {
+            text: cleaned,
+            ..output
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::ModelBackend;
+    use parking_lot::Mutex;
+
+    /// Tests here swap the process-wide backend, so they take turns.
+    static BACKEND_LOCK: Mutex<()> = Mutex::new(());
+
+    fn config(backend: ModelBackend, script: &str) -> ModelConfig {
+        ModelConfig {
+            name: "test".to_string(),
+            path: "missing.gguf".to_string(),
+            max_tokens: 64,
+            backend,
+            endpoint: "http://127.0.0.1:9".to_string(),
+            idle_unload_secs: 0,
+            allow_low_memory_load: false,
+            n_threads: 0,
+            n_batch: 0,
+            script: script.to_string(),
+        }
+    }
+
+    fn request(user: &str) -> CompletionRequest {
+        CompletionRequest {
+            system: "system".to_string(),
+            user: user.to_string(),
+            max_tokens: 64,
+            grammar: None,
+            time_limit: None,
+            adapter: None,
+        }
+    }
+
+    #[test]
+    fn installed_backend_overrides_settings_and_replies_are_trimmed() {
+        let _guard = BACKEND_LOCK.lock();
+        let scripted = ScriptedBackend::new(
+            vec![ScriptRule {
+                when_contains: "moon".to_string(),
+                reply: "  The moon orbits the Earth.\n".to_string(),
+                adapter: None,
+            }],
+            "   ",
+        );
+        install_backend(Some(Arc::new(scripted)));
+        let cfg = config(ModelBackend::LlamaCpp, "");
+        let reply = complete(&cfg, &request("Why does the moon shine?"));
+        let empty = complete(&cfg, &request("Something else"));
+        install_backend(None);
+
+        assert_eq!(reply.unwrap().text, "The moon orbits the Earth.");
+        assert_eq!(empty.unwrap_err(), "Model returned an empty response");
+    }
+
+    #[test]
+    fn mock_backend_reads_its_own_script_not_the_model_path() {
+        let _guard = BACKEND_LOCK.lock();
+        let reply = complete(&config(ModelBackend::Mock, ""), &request("hello")).unwrap();
+        assert!(reply.text.contains("scripted demo reply"), "{}", reply.text);
+
+        let missing = config(ModelBackend::Mock, "no/such/script.json");
+        assert!(complete(&missing, &request("hello"))
+            .unwrap_err()
+            .contains("Could not read mock script"));
+    }
+
+    #[test]
+    fn in_process_backend_refuses_adapters() {
+        let backend = LlamaCppBackend::default();
+        let mut req = request("hint please");
+        req.adapter = Some(PathBuf::from("maths.lora.gguf"));
+        // Refused before the base model is even looked for.
+        let err = backend
+            .complete(&config(ModelBackend::LlamaCpp, ""), &req)
+            .unwrap_err();
+        assert!(
+            err.contains("Cannot apply adapter maths.lora.gguf"),
+            "{err}"
+        );
+        assert!(backend.status().warning.is_none());
+    }
+
+    #[test]
+    fn local_server_backend_needs_policy_and_loopback() {
+        let _guard = BACKEND_LOCK.lock();
+        set_policy(PolicyConfig::default());
+        let cfg = config(ModelBackend::LocalHttp, "");
+        assert!(complete(&cfg, &request("hi"))
+            .unwrap_err()
+            .contains("disabled by school policy"));
+
+        assert!(LocalHttpBackend::new("http://example.com:8080").is_err());
+        assert!(LocalHttpBackend::new("https://127.0.0.1:8080").is_err());
+        assert!(LocalHttpBackend::new("http://127.0.0.1:8080/v1").is_ok());
+    }
+}
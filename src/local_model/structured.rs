@@ -0,0 +1,373 @@
+use super::{complete, CompletionRequest};
+use crate::settings::ModelConfig;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// Attempts before giving up; callers are expected to have a non-model fallback.
+const STRUCTURED_ATTEMPTS: usize = 3;
+
+/// Generic JSON grammar (after llama.cpp's `grammars/json.gbnf`), used for schema nodes that
+/// have no `type`.
+const JSON_VALUE_RULES: &str = r#"value ::= object | array | string | number | ("true" | "false" | "null") ws
+object ::= "{" ws ( string ":" ws value ("," ws string ":" ws value)* )? "}" ws
+array ::= "[" ws ( value ("," ws value)* )? "]" ws
+number ::= "-"? ([0-9] | [1-9] [0-9]*) ("." [0-9]+)? ([eE] [-+]? [0-9]+)? ws
+"#;
+
+const STRING_RULE: &str = r#"string ::= "\"" ( [^"\\\x7F\x00-\x1F] | "\\" (["\\/bfnrt] | "u" [0-9a-fA-F] [0-9a-fA-F] [0-9a-fA-F] [0-9a-fA-F]) )* "\"" ws"#;
+const WS_RULE: &str = "ws ::= ([ \\t\\n] ws)?";
+
+/// Ask for JSON matching `schema` and deserialize it into `T`.
+///
+/// Sampling is constrained with a grammar built from the schema (unless the request already
+/// carries a hand-written GBNF grammar). Backends that cannot constrain sampling still get the
+/// schema in the prompt, and every reply is parsed and checked against the schema. Invalid
+/// replies are retried with the error fed back; after that the error is returned so the caller
+/// can fall back to its non-model path.
+///
+/// Supported schema subset: `object` (`properties`, `required`), `array` (`items`), `string`
+/// (`enum`), `integer`/`number` (`minimum`, `maximum`), `boolean`, `null`.
+pub fn complete_json<T: DeserializeOwned>(
+    cfg: &ModelConfig,
+    request: &CompletionRequest,
+    schema: &Value,
+) -> Result<T, String> {
+    let mut request = request.clone();
+    if request.grammar.is_none() {
+        request.grammar = Some(schema_to_gbnf(schema)?);
+    }
+    request.user = format!(
+        "{}\nReply with JSON only, matching this schema:\n{}",
+        request.user, schema
+    );
+
+    let base_user = request.user.clone();
+    let mut last_error = String::new();
+    for attempt in 1..=STRUCTURED_ATTEMPTS {
+        let output = complete(cfg, &request)?;
+        match parse_structured(&output.text, schema) {
+            Ok(value) => {
+                return serde_json::from_value(value)
+                    .map_err(|e| format!("Structured reply did not fit the expected type: {e}"))
+            }
+            Err(e) => {
+                eprintln!("[structured] Attempt {attempt}/{STRUCTURED_ATTEMPTS} rejected: {e}");
+                request.user = format!(
+                    "{base_user}\nYour previous reply was rejected ({e}). Reply with valid JSON only."
+                );
+                last_error = e;
+            }
+        }
+    }
+    Err(format!(
+        "Model did not produce valid structured output after {STRUCTURED_ATTEMPTS} attempts: {last_error}"
+    ))
+}
+
+fn parse_structured(text: &str, schema: &Value) -> Result<Value, String> {
+    let json = extract_json(text).ok_or_else(|| "no JSON found in reply".to_string())?;
+    let value: Value = serde_json::from_str(json).map_err(|e| format!("invalid JSON: {e}"))?;
+    validate(&value, schema, "$")?;
+    Ok(value)
+}
+
+/// Unconstrained backends often wrap JSON in prose or code fences; take the outermost
+/// object or array.
+fn extract_json(text: &str) -> Option<&str> {
+    let start = text.find(['{', '['])?;
+    let close = if text[start..].starts_with('{') {
+        '}'
+    } else {
+        ']'
+    };
+    let end = text.rfind(close)?;
+    (end > start).then(|| &text[start..=end])
+}
+
+fn type_of(schema: &Value) -> Option<&str> {
+    schema.get("type").and_then(Value::as_str)
+}
+
+/// Check `value` against the supported schema subset. `path` is used in error messages.
+pub fn validate(value: &Value, schema: &Value, path: &str) -> Result<(), String> {
+    if let Some(options) = schema.get("enum").and_then(Value::as_array) {
+        if !options.contains(value) {
+            return Err(format!(
+                "{path} must be one of {}",
+                Value::Array(options.clone())
+            ));
+        }
+    }
+
+    match type_of(schema) {
+        Some("object") => {
+            let obj = value
+                .as_object()
+                .ok_or_else(|| format!("{path} must be an object"))?;
+            if let Some(required) = schema.get("required").and_then(Value::as_array) {
+                for key in required.iter().filter_map(Value::as_str) {
+                    if !obj.contains_key(key) {
+                        return Err(format!("{path}.{key} is missing"));
+                    }
+                }
+            }
+            if let Some(props) = schema.get("properties").and_then(Value::as_object) {
+                for (key, prop_schema) in props {
+                    if let Some(v) = obj.get(key) {
+                        validate(v, prop_schema, &format!("{path}.{key}"))?;
+                    }
+                }
+            }
+        }
+        Some("array") => {
+            let items = value
+                .as_array()
+                .ok_or_else(|| format!("{path} must be an array"))?;
+            if let Some(item_schema) = schema.get("items") {
+                for (idx, item) in items.iter().enumerate() {
+                    validate(item, item_schema, &format!("{path}[{idx}]"))?;
+                }
+            }
+        }
+        Some("string") if !value.is_string() => return Err(format!("{path} must be a string")),
+        Some("boolean") if !value.is_boolean() => {
+            return Err(format!("{path} must be true or false"))
+        }
+        Some("null") if !value.is_null() => return Err(format!("{path} must be null")),
+        Some("integer") if !(value.is_i64() || value.is_u64()) => {
+            return Err(format!("{path} must be a whole number"))
+        }
+        Some("number") if !value.is_number() => return Err(format!("{path} must be a number")),
+        _ => {}
+    }
+
+    if let Some(n) = value.as_f64() {
+        if let Some(min) = schema.get("minimum").and_then(Value::as_f64) {
+            if n < min {
+                return Err(format!("{path} must be at least {min}"));
+            }
+        }
+        if let Some(max) = schema.get("maximum").and_then(Value::as_f64) {
+            if n > max {
+                return Err(format!("{path} must be at most {max}"));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// GBNF string literal for `text` (quotes and backslashes escaped).
+fn gbnf_literal(text: &str) -> String {
+    let mut out = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            _ => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// GBNF literal matching the JSON encoding of `value`, e.g. `"\"hint\""` for the string hint.
+fn json_literal(value: &Value) -> String {
+    gbnf_literal(&value.to_string())
+}
+
+fn rule_name(parent: &str, key: &str) -> String {
+    let key: String = key
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    format!("{parent}-{key}")
+}
+
+/// Convert the supported JSON-schema subset into a GBNF grammar whose root is `root`.
+/// Object properties are emitted in key order (serde_json's map is sorted, not kept in schema
+/// order) and all of them are generated, so optional fields always appear; that keeps the
+/// grammar small and is harmless for `serde` targets.
+pub fn schema_to_gbnf(schema: &Value) -> Result<String, String> {
+    let mut rules = BTreeMap::new();
+    let root = schema_rule(schema, "root", &mut rules)?;
+    let mut out = format!("root ::= {root}\n");
+    for (name, body) in &rules {
+        out.push_str(&format!("{name} ::= {body}\n"));
+    }
+    out.push_str(JSON_VALUE_RULES);
+    out.push_str(STRING_RULE);
+    out.push('\n');
+    out.push_str(WS_RULE);
+    out.push('\n');
+    Ok(out)
+}
+
+/// Grammar expression for `schema`; nested objects and arrays get their own named rules.
+fn schema_rule(
+    schema: &Value,
+    name: &str,
+    rules: &mut BTreeMap<String, String>,
+) -> Result<String, String> {
+    if let Some(options) = schema.get("enum").and_then(Value::as_array) {
+        if options.is_empty() {
+            return Err(format!("{name}: enum has no values"));
+        }
+        let alts: Vec<String> = options.iter().map(json_literal).collect();
+        return Ok(format!("({}) ws", alts.join(" | ")));
+    }
+
+    let expr = match type_of(schema) {
+        Some("object") => {
+            let props = schema
+                .get("properties")
+                .and_then(Value::as_object)
+                .filter(|p| !p.is_empty());
+            let Some(props) = props else {
+                return Ok("object".to_string());
+            };
+            let mut parts = Vec::new();
+            for (key, prop_schema) in props {
+                let value_expr = schema_rule(prop_schema, &rule_name(name, key), rules)?;
+                parts.push(format!(
+                    "{} ws \":\" ws {value_expr}",
+                    json_literal(&Value::String(key.clone()))
+                ));
+            }
+            let body = format!("\"{{\" ws {} \"}}\" ws", parts.join(" \",\" ws "));
+            let rule = format!("{name}-obj");
+            rules.insert(rule.clone(), body);
+            rule
+        }
+        Some("array") => {
+            let item = match schema.get("items") {
+                Some(items) => schema_rule(items, &format!("{name}-item"), rules)?,
+                None => "value".to_string(),
+            };
+            let body = format!("\"[\" ws ( {item} ( \",\" ws {item} )* )? \"]\" ws");
+            let rule = format!("{name}-arr");
+            rules.insert(rule.clone(), body);
+            rule
+        }
+        Some("string") => "string".to_string(),
+        Some("integer") => {
+            let non_negative = schema
+                .get("minimum")
+                .and_then(Value::as_f64)
+                .map(|m| m >= 0.0)
+                .unwrap_or(false);
+            let sign = if non_negative { "" } else { "\"-\"? " };
+            format!("{sign}(\"0\" | [1-9] [0-9]*) ws")
+        }
+        Some("number") => "number".to_string(),
+        Some("boolean") => "(\"true\" | \"false\") ws".to_string(),
+        Some("null") => "\"null\" ws".to_string(),
+        Some(other) => return Err(format!("{name}: unsupported schema type {other}")),
+        None => "value".to_string(),
+    };
+    Ok(expr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn premark_schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "score": {"type": "integer", "minimum": 0, "maximum": 100},
+                "feedback": {"type": "string"},
+                "level": {"enum": ["low", "high"]},
+                "tags": {"type": "array", "items": {"type": "string"}}
+            },
+            "required": ["score", "feedback"]
+        })
+    }
+
+    #[test]
+    fn grammar_lists_properties_in_key_order() {
+        let grammar = schema_to_gbnf(&premark_schema()).unwrap();
+        assert!(grammar.starts_with("root ::= root-obj\n"), "{grammar}");
+        let obj = grammar
+            .lines()
+            .find(|l| l.starts_with("root-obj ::="))
+            .unwrap();
+        let positions: Vec<usize> = ["feedback", "level", "score", "tags"]
+            .iter()
+            .map(|k| obj.find(&format!(r#"\"{k}\""#)).unwrap())
+            .collect();
+        assert!(positions.windows(2).all(|w| w[0] < w[1]), "{obj}");
+        assert!(grammar.contains("root-tags-arr ::= \"[\" ws ( string"));
+        assert!(grammar.contains(r#"("\"low\"" | "\"high\"") ws"#));
+        // A minimum of 0 rules out the minus sign.
+        assert!(obj.contains("(\"0\" | [1-9] [0-9]*) ws"));
+        assert!(grammar.contains("\nws ::= "));
+    }
+
+    #[test]
+    fn grammar_rejects_unsupported_schemas() {
+        assert!(schema_to_gbnf(&json!({"type": "tuple"})).is_err());
+        assert!(schema_to_gbnf(&json!({"enum": []})).is_err());
+        assert!(schema_to_gbnf(&json!({"type": "object"}))
+            .unwrap()
+            .starts_with("root ::= object\n"));
+    }
+
+    #[test]
+    fn validate_checks_types_ranges_and_required_keys() {
+        let schema = premark_schema();
+        let ok = json!({"score": 70, "feedback": "Good", "level": "high", "tags": ["a"]});
+        assert!(validate(&ok, &schema, "$").is_ok());
+
+        let cases = [
+            (json!({"feedback": "Good"}), "$.score is missing"),
+            (
+                json!({"score": 170, "feedback": "Good"}),
+                "$.score must be at most 100",
+            ),
+            (
+                json!({"score": 7.5, "feedback": "Good"}),
+                "$.score must be a whole number",
+            ),
+            (
+                json!({"score": 7, "feedback": 3}),
+                "$.feedback must be a string",
+            ),
+            (
+                json!({"score": 7, "feedback": "Good", "level": "mid"}),
+                "$.level must be one of",
+            ),
+            (
+                json!({"score": 7, "feedback": "Good", "tags": [1]}),
+                "$.tags[0] must be a string",
+            ),
+            (json!([1]), "$ must be an object"),
+        ];
+        for (value, expected) in cases {
+            let err = validate(&value, &schema, "$").unwrap_err();
+            assert!(err.starts_with(expected), "{value}: {err}");
+        }
+    }
+
+    #[test]
+    fn structured_replies_are_dug_out_of_prose() {
+        let schema = json!({"type": "object", "properties": {"score": {"type": "integer"}}});
+        let value = parse_structured(
+            "Sure! ```json\n{\"score\": 4}\n``` Hope that helps.",
+            &schema,
+        )
+        .unwrap();
+        assert_eq!(value, json!({"score": 4}));
+        assert!(parse_structured("no json here", &schema).is_err());
+        assert!(parse_structured("{\"score\": \"four\"}", &schema).is_err());
+    }
+}
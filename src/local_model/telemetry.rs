@@ -0,0 +1,333 @@
+use super::memory::{process_memory, reset_peak_memory, ProcessMemory};
+use super::Completion;
+use crate::settings::ModelConfig;
+use once_cell::sync::Lazy;
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Only the most recent records feed the Settings summary, so a long-running log stays cheap
+/// to read and reflects the current configuration.
+const SUMMARY_WINDOW: usize = 500;
+
+static METRICS_LOG: Lazy<RwLock<Option<PathBuf>>> = Lazy::new(|| RwLock::new(None));
+/// Serialises appends from the GUI and worker threads.
+static WRITE_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+/// One line of `logs/inference_metrics.jsonl`. Timings and settings only, never prompt or
+/// answer text.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InferenceRecord {
+    pub t: i64,
+    pub backend: String,
+    pub model: String,
+    /// Configured values (0 = backend default).
+    pub n_threads: u32,
+    pub n_batch: u32,
+    /// Logical CPUs on this machine, to compare hardware models.
+    pub cpu_threads: usize,
+    pub ok: bool,
+    pub load_ms: Option<u64>,
+    pub prompt_eval_ms: Option<u64>,
+    pub generation_ms: Option<u64>,
+    pub total_ms: u64,
+    pub tokens: Option<usize>,
+    pub tokens_per_sec: Option<f64>,
+    /// Change in the app's resident memory over this request, in MB (negative when memory was
+    /// freed). Loading a model shows up here.
+    pub memory_delta_mb: Option<i64>,
+    /// Most resident memory the app used during this request, in MB, where the OS lets the peak
+    /// be reset before each call (Linux). Includes memory already in use before the request.
+    pub request_peak_memory_mb: Option<u64>,
+}
+
+/// Memory reading taken just before a request, so `record` can report what the request used.
+pub struct MemorySample {
+    resident_before: Option<u64>,
+    peak_reset: bool,
+}
+
+impl MemorySample {
+    /// Note the resident size and start a fresh peak. Overlapping requests on worker threads
+    /// share the process, so their peaks cover each other.
+    pub fn start() -> Self {
+        let peak_reset = reset_peak_memory();
+        Self {
+            resident_before: process_memory().map(|m| m.resident),
+            peak_reset,
+        }
+    }
+}
+
+const MB: u64 = 1024 * 1024;
+
+/// Averages per model and thread/batch setting, for the Settings tab.
+#[derive(Debug, Clone)]
+pub struct MetricsSummary {
+    pub backend: String,
+    pub model: String,
+    pub n_threads: u32,
+    pub n_batch: u32,
+    pub requests: usize,
+    pub failures: usize,
+    pub mean_load_ms: Option<f64>,
+    pub mean_prompt_eval_ms: Option<f64>,
+    pub mean_tokens_per_sec: Option<f64>,
+    pub mean_memory_delta_mb: Option<f64>,
+    /// Largest per-request peak (see `InferenceRecord`).
+    pub request_peak_memory_mb: Option<u64>,
+}
+
+pub fn metrics_log_path(base: &Path) -> PathBuf {
+    base.join("logs").join("inference_metrics.jsonl")
+}
+
+/// Start recording to `<base>/logs/inference_metrics.jsonl`.
+pub fn set_metrics_log(base: &Path) {
+    *METRICS_LOG.write() = Some(metrics_log_path(base));
+}
+
+fn millis(d: Duration) -> u64 {
+    d.as_millis() as u64
+}
+
+/// Append a record for one request to the metrics log, if one is set. `completion` is None
+/// when the backend failed; `memory` was taken just before the backend call.
+pub fn record(
+    cfg: &ModelConfig,
+    backend: &str,
+    completion: Option<&Completion>,
+    total: Duration,
+    memory: MemorySample,
+) {
+    let Some(path) = METRICS_LOG.read().clone() else {
+        return;
+    };
+    let entry = inference_record(cfg, backend, completion, total, &memory, process_memory());
+    append_record(&path, &entry);
+}
+
+/// The log line for one request, from the memory readings taken before and after it.
+fn inference_record(
+    cfg: &ModelConfig,
+    backend: &str,
+    completion: Option<&Completion>,
+    total: Duration,
+    before: &MemorySample,
+    after: Option<ProcessMemory>,
+) -> InferenceRecord {
+    let timings = completion.map(|c| c.timings).unwrap_or_default();
+    let tokens = completion.and_then(|c| c.tokens);
+    let generation = timings.generation.unwrap_or(total);
+    InferenceRecord {
+        t: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as i64,
+        backend: backend.to_string(),
+        model: cfg.name.clone(),
+        n_threads: cfg.n_threads,
+        n_batch: cfg.n_batch,
+        cpu_threads: thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(0),
+        ok: completion.is_some(),
+        load_ms: timings.load.map(millis),
+        prompt_eval_ms: timings.prompt_eval.map(millis),
+        generation_ms: timings.generation.map(millis),
+        total_ms: millis(total),
+        tokens,
+        tokens_per_sec: tokens
+            .filter(|_| generation.as_secs_f64() > 0.0)
+            .map(|n| n as f64 / generation.as_secs_f64()),
+        memory_delta_mb: after
+            .zip(before.resident_before)
+            .map(|(after, before)| (after.resident as i64 - before as i64) / MB as i64),
+        request_peak_memory_mb: after
+            .filter(|_| before.peak_reset)
+            .map(|after| after.peak / MB),
+    }
+}
+
+fn append_record(path: &Path, entry: &InferenceRecord) {
+    let _guard = WRITE_LOCK.lock();
+    let written = path
+        .parent()
+        .map(fs::create_dir_all)
+        .unwrap_or(Ok(()))
+        .and_then(|_| OpenOptions::new().create(true).append(true).open(path))
+        .and_then(|mut file| {
+            let line = serde_json::to_string(entry)?;
+            writeln!(file, "{line}")
+        });
+    if let Err(e) = written {
+        eprintln!("[metrics] Could not write {}: {e}", path.display());
+    }
+}
+
+/// "+120 MB avg, peak 2410 MB" for the summary tables; "-" when nothing was measured.
+pub fn memory_per_request(row: &MetricsSummary) -> String {
+    let delta = row
+        .mean_memory_delta_mb
+        .map(|mb| format!("{mb:+.0} MB avg"));
+    let peak = row.request_peak_memory_mb.map(|mb| format!("peak {mb} MB"));
+    match (delta, peak) {
+        (Some(d), Some(p)) => format!("{d}, {p}"),
+        (Some(v), None) | (None, Some(v)) => v,
+        (None, None) => "-".to_string(),
+    }
+}
+
+fn mean(values: &[f64]) -> Option<f64> {
+    (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+}
+
+/// Summaries of the latest records in `base`'s metrics log, grouped by backend, model and
+/// thread/batch setting.
+pub fn summarize_metrics(base: &Path) -> Vec<MetricsSummary> {
+    let Ok(contents) = fs::read_to_string(metrics_log_path(base)) else {
+        return Vec::new();
+    };
+    let lines: Vec<&str> = contents.lines().filter(|l| !l.trim().is_empty()).collect();
+    let recent = &lines[lines.len().saturating_sub(SUMMARY_WINDOW)..];
+
+    let mut groups: BTreeMap<(String, String, u32, u32), Vec<InferenceRecord>> = BTreeMap::new();
+    for entry in recent
+        .iter()
+        .filter_map(|l| serde_json::from_str::<InferenceRecord>(l).ok())
+    {
+        groups
+            .entry((
+                entry.backend.clone(),
+                entry.model.clone(),
+                entry.n_threads,
+                entry.n_batch,
+            ))
+            .or_default()
+            .push(entry);
+    }
+
+    groups
+        .into_iter()
+        .map(|((backend, model, n_threads, n_batch), entries)| {
+            let collect = |f: fn(&InferenceRecord) -> Option<f64>| -> Vec<f64> {
+                entries.iter().filter_map(f).collect()
+            };
+            MetricsSummary {
+                backend,
+                model,
+                n_threads,
+                n_batch,
+                requests: entries.len(),
+                failures: entries.iter().filter(|e| !e.ok).count(),
+                mean_load_ms: mean(&collect(|e| e.load_ms.map(|v| v as f64))),
+                mean_prompt_eval_ms: mean(&collect(|e| e.prompt_eval_ms.map(|v| v as f64))),
+                mean_tokens_per_sec: mean(&collect(|e| e.tokens_per_sec)),
+                mean_memory_delta_mb: mean(&collect(|e| e.memory_delta_mb.map(|v| v as f64))),
+                request_peak_memory_mb: entries
+                    .iter()
+                    .filter_map(|e| e.request_peak_memory_mb)
+                    .max(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::default_settings;
+
+    fn sample(resident_before: Option<u64>, peak_reset: bool) -> MemorySample {
+        MemorySample {
+            resident_before: resident_before.map(|mb| mb * MB),
+            peak_reset,
+        }
+    }
+
+    fn reading(resident_mb: u64, peak_mb: u64) -> Option<ProcessMemory> {
+        Some(ProcessMemory {
+            resident: resident_mb * MB,
+            peak: peak_mb * MB,
+        })
+    }
+
+    #[test]
+    fn memory_figures_come_from_the_readings_around_the_request() {
+        let cfg = default_settings(Path::new("/tmp/chatty-metrics-missing")).model;
+        let total = Duration::from_millis(5);
+
+        let load = inference_record(
+            &cfg,
+            "test",
+            None,
+            total,
+            &sample(Some(900), true),
+            reading(2948, 3100),
+        );
+        assert_eq!(load.memory_delta_mb, Some(2048));
+        assert_eq!(load.request_peak_memory_mb, Some(3100));
+
+        let freed = inference_record(
+            &cfg,
+            "test",
+            None,
+            total,
+            &sample(Some(2948), true),
+            reading(900, 2948),
+        );
+        assert_eq!(freed.memory_delta_mb, Some(-2048));
+
+        // Without a peak reset the OS peak covers the whole run, so it is not reported.
+        let no_reset = inference_record(
+            &cfg,
+            "test",
+            None,
+            total,
+            &sample(Some(900), false),
+            reading(1000, 4000),
+        );
+        assert_eq!(no_reset.memory_delta_mb, Some(100));
+        assert_eq!(no_reset.request_peak_memory_mb, None);
+
+        let unknown = inference_record(&cfg, "test", None, total, &sample(None, true), None);
+        assert_eq!(unknown.memory_delta_mb, None);
+        assert_eq!(unknown.request_peak_memory_mb, None);
+    }
+
+    #[test]
+    fn summaries_average_the_memory_each_request_added() {
+        let dir = std::env::temp_dir().join(format!("chatty_metrics_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let mut cfg = default_settings(&dir).model;
+        cfg.name = "memory-test".to_string();
+        let total = Duration::from_millis(5);
+        let path = metrics_log_path(&dir);
+        for (before, after) in [(1000, reading(1300, 1400)), (1300, reading(1400, 1800))] {
+            let entry = inference_record(
+                &cfg,
+                "test",
+                None,
+                total,
+                &sample(Some(before), true),
+                after,
+            );
+            append_record(&path, &entry);
+        }
+
+        let row = summarize_metrics(&dir)
+            .into_iter()
+            .find(|r| r.model == "memory-test")
+            .unwrap();
+        assert_eq!(row.requests, 2);
+        assert_eq!(row.mean_memory_delta_mb, Some(200.0));
+        assert_eq!(row.request_peak_memory_mb, Some(1800));
+        assert_eq!(memory_per_request(&row), "+200 MB avg, peak 1800 MB");
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -1,24 +1,41 @@
 use clap::{Parser, ValueEnum};
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
+mod audit;
+mod budget;
 mod chat;
+mod eval;
+mod export;
 mod gui;
+mod hint_guard;
+mod history;
 mod homework;
 mod homework_pack;
+mod injection;
+mod janet_eval;
 mod local_model;
+mod model_catalog;
 mod modules;
+mod policy;
+mod redact;
+mod response_cache;
+mod retrieval;
+mod safety;
 mod settings;
 mod theme;
+mod wellbeing;
 
-use chat::{generate_answer, janet_filter};
+use chat::generate_answer;
 use homework_pack::{
     apply_pack_policy, create_pack, create_pack_multi, export_pack_template, find_latest_pack,
     load_pack_from_file, load_submission_summaries, save_submission_with_answers,
     HomeworkAssignment,
 };
 use settings::{
-    default_base_path, ensure_base_folders, load_or_init_settings, save_settings, Settings,
+    default_base_path, ensure_base_folders, load_or_init_settings, save_settings, BudgetLimits,
+    JanetClassifier, ModelBackend, ModelTask, Settings,
 };
 
 #[derive(Parser, Debug)]
@@ -34,15 +51,29 @@ struct CliArgs {
     /// Override data base path (defaults to ./data next to the exe)
     #[arg(long)]
     base_path: Option<PathBuf>,
+    /// Eval mode: JSONL suite of prompts to run. Janet-eval mode: JSONL of labelled texts
+    #[arg(long)]
+    suite: Option<PathBuf>,
+    /// Eval modes: where to write the JSON report (defaults to logs/eval_<model>_<time>.json
+    /// or logs/janet_eval_<time>.json)
+    #[arg(long)]
+    report: Option<PathBuf>,
+    /// Eval mode: model file to test instead of the one selected in settings
+    #[arg(long)]
+    model: Option<PathBuf>,
 }
 
 #[derive(Copy, Clone, Debug, ValueEnum)]
 enum RunMode {
     Gui,
     Cli,
+    /// Run an evaluation suite against the configured model and exit
+    Eval,
+    /// Run a labelled corpus through Janet, report precision/recall per category and exit
+    JanetEval,
 }
 
-fn main() {
+fn main() -> ExitCode {
     let args = CliArgs::parse();
     let base_path = args.base_path.unwrap_or_else(default_base_path);
 
@@ -52,18 +83,23 @@ fn main() {
             base_path.display(),
             e
         );
-        return;
+        return ExitCode::FAILURE;
     }
 
     let mut settings = match load_or_init_settings(&base_path) {
         Ok(s) => s,
         Err(e) => {
             eprintln!("Failed to load settings: {}", e);
-            return;
+            return ExitCode::FAILURE;
         }
     };
 
     println!("Using data path: {}", base_path.display());
+    local_model::set_policy(policy::load_policy(&base_path));
+    local_model::set_memory_budget_mb(settings.model_memory_budget_mb);
+    local_model::set_metrics_log(&base_path);
+    safety::load_policies(&base_path);
+    wellbeing::load_support(&base_path);
 
     // Apply latest homework pack policy (e.g., games allowed/blocked) if present.
     if let Ok(Some((_pack_path, pack))) = find_latest_pack(&base_path) {
@@ -71,37 +107,205 @@ fn main() {
     }
 
     settings.base_path = base_path.to_string_lossy().to_string();
-    settings.mode = match args.mode {
-        RunMode::Gui => "gui".to_string(),
-        RunMode::Cli => "cli".to_string(),
-    };
-
     match args.mode {
         RunMode::Gui => {
+            settings.mode = "gui".to_string();
             if let Err(e) = gui::launch_gui(base_path.clone(), settings.clone()) {
                 eprintln!("Failed to start GUI: {}", e);
             }
         }
         RunMode::Cli => {
+            settings.mode = "cli".to_string();
             run_cli(&mut settings, &base_path);
         }
+        RunMode::Eval => {
+            return run_eval(&settings, &base_path, args.suite, args.report, args.model)
+        }
+        RunMode::JanetEval => {
+            return run_janet_eval(&settings, &base_path, args.suite, args.report)
+        }
     }
 
     if let Err(e) = save_settings(&settings, &base_path) {
         eprintln!("Could not save settings: {}", e);
     }
+    ExitCode::SUCCESS
+}
+
+/// Offline model evaluation for IT staff. Never saves settings, so a `--model` override
+/// does not change what students get. Exits non-zero when any case fails or errors, so scripts
+/// can gate a model rollout on it.
+fn run_eval(
+    settings: &Settings,
+    base_path: &Path,
+    suite: Option<PathBuf>,
+    report: Option<PathBuf>,
+    model: Option<PathBuf>,
+) -> ExitCode {
+    let Some(suite) = suite else {
+        eprintln!("Eval mode needs --suite <file.jsonl> (see resources/eval_suite_example.jsonl)");
+        return ExitCode::FAILURE;
+    };
+    let mut settings = settings.clone();
+    if let Some(model) = model {
+        settings.model.name = model
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        settings.model.path = model.to_string_lossy().to_string();
+        // Test only this file, whatever the task routes say.
+        settings.model_routes.clear();
+        settings.refresh_model_routes();
+    }
+    let report_path = report.unwrap_or_else(|| eval::default_report_path(base_path, &settings));
+
+    println!(
+        "Evaluating {} ({}) with {}",
+        settings.model.name,
+        settings.model.path,
+        suite.display()
+    );
+    match eval::run_suite(base_path, &settings, &suite, &report_path) {
+        Ok(report) => {
+            let s = &report.summary;
+            println!(
+                "\n{} cases: {} passed, {} failed, {} errors. Mean latency {:.0} ms{}",
+                s.total,
+                s.passed,
+                s.failed,
+                s.errors,
+                s.mean_latency_ms,
+                s.mean_tokens_per_sec
+                    .map(|r| format!(", {r:.1} tok/s"))
+                    .unwrap_or_default()
+            );
+            println!("Report written to {}", report_path.display());
+            if s.failed == 0 && s.errors == 0 {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            }
+        }
+        Err(e) => {
+            eprintln!("Evaluation failed: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn percent(value: Option<f64>) -> String {
+    value
+        .map(|v| format!("{:.0}%", v * 100.0))
+        .unwrap_or_else(|| "-".to_string())
+}
+
+/// Janet policy check for school IT: the installed policy files and Janet settings against a
+/// labelled corpus, so lists can be tuned before a rollout. Never saves settings. Exits non-zero
+/// when any text is not handled as labelled.
+fn run_janet_eval(
+    settings: &Settings,
+    base_path: &Path,
+    corpus: Option<PathBuf>,
+    report: Option<PathBuf>,
+) -> ExitCode {
+    let Some(corpus) = corpus else {
+        eprintln!(
+            "Janet-eval mode needs --suite <file.jsonl> (see resources/janet_eval_example.jsonl)"
+        );
+        return ExitCode::FAILURE;
+    };
+    let report_path = report.unwrap_or_else(|| janet_eval::default_report_path(base_path));
+    println!(
+        "Checking Janet (model safety check: {}, extra languages: {}) with {}",
+        settings.janet.classifier.label(),
+        if settings.janet.locales.is_empty() {
+            "none".to_string()
+        } else {
+            settings.janet.locales.join(", ")
+        },
+        corpus.display()
+    );
+    let report = match janet_eval::run_corpus(settings, &corpus, &report_path) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("Janet evaluation failed: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    if !report.janet_enabled {
+        println!("Janet is switched off in settings; every text passes.");
+    }
+    let known: Vec<String> = safety::categories().into_iter().map(|c| c.name).collect();
+    println!();
+    println!(
+        "{:<14} {:>4} {:>4} {:>4} {:>10} {:>7}",
+        "category", "tp", "fp", "fn", "precision", "recall"
+    );
+    for (name, score) in &report.categories {
+        let note = if known.contains(name) {
+            ""
+        } else {
+            "  (no such policy file)"
+        };
+        println!(
+            "{:<14} {:>4} {:>4} {:>4} {:>10} {:>7}{note}",
+            name,
+            score.true_positives,
+            score.false_positives,
+            score.false_negatives,
+            percent(score.precision),
+            percent(score.recall)
+        );
+    }
+    let list = |title: &str, cases: &[janet_eval::JanetCaseResult]| {
+        if cases.is_empty() {
+            return;
+        }
+        println!("\n{title} ({}):", cases.len());
+        for case in cases {
+            println!(
+                "  [{} -> {}] {}",
+                case.expected.as_deref().unwrap_or("none"),
+                case.got.as_deref().unwrap_or("none"),
+                case.text
+            );
+        }
+    };
+    list("Wrongly blocked", &report.wrongly_blocked);
+    list("Let through", &report.let_through);
+    list("Wrong category", &report.wrong_category);
+    println!(
+        "\n{} of {} texts handled as labelled. Report written to {}",
+        report.correct,
+        report.total,
+        report_path.display()
+    );
+    if report.correct == report.total {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
 }
 
 fn run_cli(settings: &mut Settings, base_path: &Path) {
     println!("Chatty-EDU v0.2 CLI starting up");
     println!("Base path: {}", base_path.display());
     println!("Mode: {}", settings.mode);
-    println!("Type 'exit' to quit, 'teacher' for teacher console, 'play' to try game mode.\n");
+    println!("Type 'exit' to quit, 'teacher' for teacher console, 'play' to try game mode, 'history' for your saved chats.\n");
+
+    let mut session = history::ChatSession::new();
+    let mut chat_log: Vec<history::ChatMessage> = Vec::new();
+    // Saved chats open with the student's PIN the first time they type 'history'.
+    let mut profile: Option<history::Profile> = None;
 
     loop {
+        let model_state = match local_model::model_status().loaded {
+            Some(name) => format!("loaded ({name})"),
+            None => "not loaded".to_string(),
+        };
         println!(
-            "[Mode: {} | TeacherMode: {}]",
-            settings.mode, settings.teacher_mode
+            "[Mode: {} | TeacherMode: {} | Model: {}]",
+            settings.mode, settings.teacher_mode, model_state
         );
         print!("You (or command): ");
         io::stdout().flush().unwrap();
@@ -120,6 +324,12 @@ fn run_cli(settings: &mut Settings, base_path: &Path) {
 
         if input.eq_ignore_ascii_case("teacher") {
             teacher_console(settings, base_path);
+            if profile.as_ref().is_some_and(|p| !p.matches(settings)) {
+                println!("Student ID changed; type 'history' to open that student's saved chats.");
+                profile = None;
+                session = history::ChatSession::new();
+                chat_log.clear();
+            }
             continue;
         }
 
@@ -150,6 +360,27 @@ fn run_cli(settings: &mut Settings, base_path: &Path) {
             continue;
         }
 
+        if let Some(rest) = input.strip_prefix("export ") {
+            match export::ExportFormat::parse(rest) {
+                Some(format) => export_chat(
+                    settings,
+                    base_path,
+                    profile.as_ref(),
+                    &session,
+                    &chat_log,
+                    format,
+                ),
+                None => println!("Usage: export md|html"),
+            }
+            continue;
+        }
+
+        if input.eq_ignore_ascii_case("history") || input.starts_with("history ") {
+            let args = input.trim_start_matches("history").trim();
+            handle_history_command(settings, args, &mut profile, &mut session, &mut chat_log);
+            continue;
+        }
+
         if input.to_lowercase().starts_with("play") {
             handle_play_request(settings);
             continue;
@@ -159,13 +390,732 @@ fn run_cli(settings: &mut Settings, base_path: &Path) {
             continue;
         }
 
-        let raw_answer = generate_answer(settings, input);
-        let safe_answer = janet_filter(&settings.janet, &raw_answer, input);
+        let answer = generate_answer(settings, input).text;
+        println!("Chatty: {answer}\n");
+        chat_log.push(history::ChatMessage::new("You", input));
+        chat_log.push(history::ChatMessage::new("Chatty", answer));
+        if let Some(profile) = &profile {
+            if let Err(e) = history::save_chat(settings, profile, &mut session, &chat_log) {
+                eprintln!("[history] Could not save chat: {e}");
+            }
+        }
+    }
+}
+
+/// Save a chat as Markdown or HTML in the student's own exports folder, if the school's policy
+/// allows file export. Needs the history opened with the student's PIN.
+fn export_chat(
+    settings: &Settings,
+    base_path: &Path,
+    profile: Option<&history::Profile>,
+    session: &history::ChatSession,
+    messages: &[history::ChatMessage],
+    format: export::ExportFormat,
+) {
+    let model = if session.model.is_empty() {
+        settings.model_for(ModelTask::Chat).name.as_str()
+    } else {
+        session.model.as_str()
+    };
+    let transcript = export::Transcript {
+        kind: "Chat",
+        model,
+        started_at: &session.started_at,
+        assignment: None,
+        messages,
+    };
+    let Some(profile) = profile else {
+        println!("Type 'history' and enter your PIN first; exports are saved with your chats.");
+        return;
+    };
+    let policy = policy::load_policy(base_path);
+    let exported = profile.exports_dir().and_then(|dir| {
+        export::export_transcript(settings, &policy, &transcript, &dir, "chat", format)
+    });
+    match exported {
+        Ok(path) => println!("Exported to {}", path.display()),
+        Err(e) => println!("Could not export: {e}"),
+    }
+}
 
-        println!("Chatty: {safe_answer}\n");
+fn print_history(settings: &Settings, sessions: &[history::ChatSession]) {
+    let config = &settings.chat_history;
+    let kept = match config.retention_days {
+        0 => "until you delete them".to_string(),
+        days => format!("for {days} days"),
+    };
+    if config.enabled {
+        println!("Your chats are saved on this computer and kept {kept}.");
+    } else {
+        println!("Chat saving is off.");
+    }
+    if sessions.is_empty() {
+        println!("No saved chats.");
+    }
+    for (idx, session) in sessions.iter().enumerate() {
+        println!("  {}. {}  {}", idx + 1, session.when(), session.title());
     }
 }
 
+/// Ask for the student's history PIN (or a new one the first time). Chats so far are saved
+/// once it is accepted.
+fn open_history_profile(
+    settings: &Settings,
+    session: &mut history::ChatSession,
+    chat_log: &[history::ChatMessage],
+) -> Option<history::Profile> {
+    let label = if history::has_pin(settings) {
+        "History PIN"
+    } else {
+        "Choose a history PIN (only someone with it can open your saved chats)"
+    };
+    let pin = prompt(label, "").unwrap_or_default();
+    match history::open_profile(settings, &pin) {
+        Ok(profile) => {
+            history::purge_expired(settings, &profile);
+            if let Err(e) = history::save_chat(settings, &profile, session, chat_log) {
+                eprintln!("[history] Could not save chat: {e}");
+            }
+            Some(profile)
+        }
+        Err(message) => {
+            println!("{message}");
+            None
+        }
+    }
+}
+
+/// Student commands for their own chat history. There is no teacher equivalent: history is
+/// only shown to the profile that wrote it, after its PIN has been entered.
+fn handle_history_command(
+    settings: &mut Settings,
+    args: &str,
+    profile: &mut Option<history::Profile>,
+    session: &mut history::ChatSession,
+    chat_log: &mut Vec<history::ChatMessage>,
+) {
+    if profile.is_none() {
+        *profile = open_history_profile(settings, session, chat_log);
+    }
+    let Some(profile) = profile.as_ref() else {
+        return;
+    };
+    let sessions = history::list_sessions(profile);
+    let pick = |n: &str| {
+        n.parse::<usize>()
+            .ok()
+            .and_then(|n| n.checked_sub(1))
+            .and_then(|n| sessions.get(n))
+    };
+    match args.split_once(' ').map(|(cmd, rest)| (cmd, rest.trim())) {
+        None if args.is_empty() => print_history(settings, &sessions),
+        None if args == "on" || args == "off" => {
+            settings.chat_history.enabled = args == "on";
+            print_history(settings, &sessions);
+        }
+        Some(("show", n)) => match pick(n) {
+            Some(saved) => {
+                for message in &saved.messages {
+                    println!("{}: {}", message.sender, message.text);
+                }
+            }
+            None => println!("No saved chat {n}; type 'history' to list them."),
+        },
+        Some(("export", rest)) => {
+            let (n, format) = rest.split_once(' ').unwrap_or((rest, ""));
+            match (pick(n), export::ExportFormat::parse(format)) {
+                (Some(saved), Some(format)) => export_chat(
+                    settings,
+                    Path::new(&settings.base_path),
+                    Some(profile),
+                    saved,
+                    &saved.messages,
+                    format,
+                ),
+                _ => println!("Usage: history export <n> md|html"),
+            }
+        }
+        Some(("delete", "all")) => match history::delete_all(profile) {
+            Ok(count) => {
+                *session = history::ChatSession::new();
+                chat_log.clear();
+                println!("Deleted {count} saved chats.");
+            }
+            Err(e) => println!("Could not delete your history: {e}"),
+        },
+        Some(("delete", n)) => match pick(n) {
+            Some(saved) => {
+                if saved.id == session.id {
+                    *session = history::ChatSession::new();
+                    chat_log.clear();
+                }
+                match history::delete_session(profile, &saved.id) {
+                    Ok(()) => println!("Deleted chat {n}."),
+                    Err(e) => println!("Could not delete chat {n}: {e}"),
+                }
+            }
+            None => println!("No saved chat {n}; type 'history' to list them."),
+        },
+        Some(("keep", days)) => match days.parse::<u32>() {
+            Ok(days) => {
+                settings.chat_history.retention_days = days;
+                let removed = history::purge_expired(settings, profile);
+                if removed > 0 {
+                    println!("Deleted {removed} chats older than {days} days.");
+                }
+                print_history(settings, &history::list_sessions(profile));
+            }
+            Err(_) => println!("Usage: history keep <days> (0 = until you delete them)"),
+        },
+        _ => println!(
+            "Usage: history | history show <n> | history export <n> md|html | history delete <n>|all | history on|off | history keep <days>"
+        ),
+    }
+}
+
+fn print_model_profiles(settings: &Settings) {
+    println!(
+        "Default model: {} ({})",
+        settings.model.name, settings.model.path
+    );
+    for profile in &settings.model_profiles {
+        println!(
+            "  {}: {} [{}]{}",
+            profile.name,
+            profile.path,
+            profile.backend.label(),
+            if profile.is_available() {
+                ""
+            } else {
+                " (file missing)"
+            }
+        );
+    }
+    for task in ModelTask::ALL {
+        let route = settings
+            .model_routes
+            .get(&task)
+            .map(|r| r.join(" -> "))
+            .unwrap_or_else(|| "default".to_string());
+        println!(
+            "  {} -> {route} (now using {})",
+            task.label(),
+            settings.model_for(task).name
+        );
+    }
+    for (subject, file) in &settings.subject_adapters {
+        let path = settings.resolve_model_file(file);
+        println!(
+            "  {subject} homework adapter: {}{}",
+            path.display(),
+            if path.exists() { "" } else { " (file missing)" }
+        );
+    }
+    if !settings.subject_adapters.is_empty() {
+        if let Some(err) = local_model::adapter_support_error(settings.model_for(ModelTask::Hint)) {
+            println!("  ERROR: {err} Hints for these subjects will fail until then.");
+        }
+    }
+}
+
+fn handle_profile_command(settings: &mut Settings, args: &str) {
+    let parts: Vec<&str> = args.splitn(3, ' ').collect();
+    match parts.as_slice() {
+        ["add", name, path] => {
+            if settings.model_profiles.iter().any(|p| p.name == *name) {
+                println!("A profile called {name} already exists.");
+                return;
+            }
+            let mut profile = settings.model.clone();
+            profile.name = name.to_string();
+            profile.path = path.trim().to_string();
+            profile.backend = ModelBackend::LlamaCpp;
+            let detail = format!("{} ({}) by teacher", profile.name, profile.path);
+            if let Err(e) = audit::record(
+                Path::new(&settings.base_path),
+                "model_profile_added",
+                &detail,
+            ) {
+                eprintln!("[audit] Could not record new profile: {e}");
+            }
+            settings.model_profiles.push(profile);
+            settings.refresh_model_routes();
+            print_model_profiles(settings);
+        }
+        ["remove", name] => {
+            settings.model_profiles.retain(|p| p.name != *name);
+            for route in settings.model_routes.values_mut() {
+                route.retain(|n| n != name);
+            }
+            settings.model_routes.retain(|_, r| !r.is_empty());
+            settings.refresh_model_routes();
+            print_model_profiles(settings);
+        }
+        _ => println!("Usage: profile add <name> <path> | profile remove <name>"),
+    }
+}
+
+fn handle_route_command(settings: &mut Settings, args: &str) {
+    let Some((task, target)) = args.split_once(' ') else {
+        println!(
+            "Usage: route <chat|hint|premark|authoring|safety> <profile>[,<fallback>...]|default"
+        );
+        return;
+    };
+    let Some(task) = ModelTask::from_name(task.trim()) else {
+        println!("Unknown task {task}; use chat, hint, premark, authoring or safety.");
+        return;
+    };
+    let target = target.trim();
+    if target == "default" {
+        settings.model_routes.remove(&task);
+    } else {
+        let names: Vec<String> = target
+            .split(',')
+            .map(|n| n.trim().to_string())
+            .filter(|n| !n.is_empty())
+            .collect();
+        if let Some(unknown) = names
+            .iter()
+            .find(|n| !settings.model_profiles.iter().any(|p| &p.name == *n))
+        {
+            println!("No profile called {unknown}. Add it with `profile add` first.");
+            return;
+        }
+        settings.model_routes.insert(task, names);
+    }
+    settings.refresh_model_routes();
+    print_model_profiles(settings);
+}
+
+fn handle_adapter_command(settings: &mut Settings, args: &str) {
+    let Some((subject, file)) = args.rsplit_once(' ') else {
+        println!("Usage: adapter <subject> <file in models/>|none");
+        return;
+    };
+    let subject = subject.trim();
+    settings
+        .subject_adapters
+        .retain(|name, _| !name.eq_ignore_ascii_case(subject));
+    if file != "none" {
+        settings
+            .subject_adapters
+            .insert(subject.to_string(), file.to_string());
+    }
+    print_model_profiles(settings);
+}
+
+fn print_budget(settings: &Settings) {
+    let budget = &settings.budget;
+    let show = |limits: &BudgetLimits| {
+        format!(
+            "{} requests/min, {} tokens/session, {}s per answer",
+            limits.requests_per_minute, limits.tokens_per_session, limits.max_generation_secs
+        )
+    };
+    println!(
+        "Class-mode budgets: {} (0 = unlimited)",
+        if budget.enabled { "ON" } else { "OFF" }
+    );
+    println!("  default: {}", show(&budget.limits));
+    for (student, limits) in &budget.per_student {
+        println!("  {student}: {}", show(limits));
+    }
+    let current = &settings.session_student_id;
+    let (recent, tokens) = budget::usage_for(current);
+    println!(
+        "  usage for {current}: {recent} requests in the last minute, {tokens} tokens this session"
+    );
+    println!(
+        "  (charged to the student ID the app started with; usage resets when the app restarts)"
+    );
+}
+
+fn handle_budget_command(settings: &mut Settings, args: &str) {
+    let parts: Vec<&str> = args.split_whitespace().collect();
+    match parts.as_slice() {
+        ["on"] => {
+            settings.budget.enabled = true;
+            println!("Class-mode budgets ENABLED.");
+        }
+        ["off"] => {
+            settings.budget.enabled = false;
+            println!("Class-mode budgets DISABLED.");
+        }
+        ["reset"] => {
+            budget::reset(None);
+            println!("Usage cleared for all students.");
+        }
+        ["reset", student] => {
+            budget::reset(Some(student));
+            println!("Usage cleared for {student}.");
+        }
+        [field @ ("rpm" | "tokens" | "secs"), value, rest @ ..] if rest.len() <= 1 => {
+            let Ok(value) = value.parse::<u32>() else {
+                println!("Limit must be a whole number (0 = unlimited).");
+                return;
+            };
+            let limits = match rest.first() {
+                Some(student) => {
+                    let defaults = settings.budget.limits;
+                    settings
+                        .budget
+                        .per_student
+                        .entry(student.to_string())
+                        .or_insert(defaults)
+                }
+                None => &mut settings.budget.limits,
+            };
+            match *field {
+                "rpm" => limits.requests_per_minute = value,
+                "tokens" => limits.tokens_per_session = value,
+                _ => limits.max_generation_secs = value,
+            }
+            print_budget(settings);
+        }
+        _ => println!("Usage: budget on|off | budget <rpm|tokens|secs> <n> [student_id] | budget reset [student_id]"),
+    }
+}
+
+fn print_janet_categories(settings: &Settings) {
+    for category in safety::categories() {
+        let on = !settings.janet.disabled_categories.contains(&category.name);
+        println!(
+            "  {} ({}): {}, {}",
+            category.name,
+            category.display_name(),
+            category.action.label(),
+            if on { "on" } else { "off" }
+        );
+    }
+}
+
+fn print_janet_locales(settings: &Settings) {
+    let on = &settings.janet.locales;
+    println!(
+        "Janet extra languages: {} (available: {})",
+        if on.is_empty() {
+            "none".to_string()
+        } else {
+            on.join(", ")
+        },
+        safety::available_locales().join(", ")
+    );
+}
+
+fn print_janet_allowlist(settings: &Settings) {
+    let allowlist = &settings.janet.allowlist;
+    if allowlist.is_empty() {
+        println!("Janet allowlist is empty.");
+    } else {
+        println!("Janet allows: {}", allowlist.join(", "));
+    }
+}
+
+fn handle_janet_command(settings: &mut Settings, base_path: &Path, args: &str) {
+    let janet = &mut settings.janet;
+    match args.split_once(' ').map(|(cmd, rest)| (cmd, rest.trim())) {
+        Some(("enable", name)) => {
+            janet.disabled_categories.retain(|c| c != name);
+            print_janet_categories(settings);
+        }
+        Some(("disable", name)) => {
+            if !safety::categories().iter().any(|c| c.name == name) {
+                println!("No Janet category named {name}.");
+            } else if !janet.disabled_categories.iter().any(|c| c == name) {
+                janet.disabled_categories.push(name.to_string());
+            }
+            print_janet_categories(settings);
+        }
+        Some(("locale", rest)) => match rest.split_once(' ').map(|(c, s)| (c, s.trim())) {
+            Some((code, "on")) => {
+                if !safety::available_locales().iter().any(|c| c == code) {
+                    println!("No Janet lists for locale {code}.");
+                } else if !janet.locales.iter().any(|c| c == code) {
+                    janet.locales.push(code.to_string());
+                }
+                print_janet_locales(settings);
+            }
+            Some((code, "off")) => {
+                janet.locales.retain(|c| c != code);
+                print_janet_locales(settings);
+            }
+            _ => println!("Usage: janet locale <code> on|off"),
+        },
+        Some(("allow", word)) => {
+            if !janet.allowlist.iter().any(|w| w.eq_ignore_ascii_case(word)) {
+                janet.allowlist.push(word.to_string());
+            }
+            print_janet_allowlist(settings);
+        }
+        Some(("unallow", word)) => {
+            janet.allowlist.retain(|w| !w.eq_ignore_ascii_case(word));
+            print_janet_allowlist(settings);
+        }
+        None if args == "categories" => print_janet_categories(settings),
+        None if args == "reload" => {
+            safety::load_policies(base_path);
+            print_janet_categories(settings);
+        }
+        Some(("classifier", mode)) => match JanetClassifier::from_name(mode) {
+            Some(mode) => {
+                janet.classifier = mode;
+                println!("Janet model check: {}", mode.label());
+            }
+            None => println!("Usage: janet classifier off|confirm|full"),
+        },
+        None if args == "classifier" => {
+            println!("Janet model check: {}", janet.classifier.label())
+        }
+        Some(("injection", mode @ ("on" | "off"))) => {
+            janet.injection_model_check = mode == "on";
+            println!(
+                "Injection model check: {}",
+                if janet.injection_model_check { "ON" } else { "OFF" }
+            );
+        }
+        None if args == "locales" => print_janet_locales(settings),
+        None if args == "allowlist" => print_janet_allowlist(settings),
+        _ => println!("Usage: janet categories | janet enable|disable <category> | janet reload | janet locales | janet locale <code> on|off | janet classifier off|confirm|full | janet injection on|off | janet allowlist | janet allow <word> | janet unallow <word>"),
+    }
+}
+
+fn print_redaction(settings: &Settings) {
+    let config = &settings.redaction;
+    let state = |on: bool| if on { "on" } else { "off" };
+    println!(
+        "Redaction: emails {}, phones {}, addresses {}, names {}",
+        state(config.emails),
+        state(config.phones),
+        state(config.addresses),
+        state(config.roster_names)
+    );
+    if config.roster.is_empty() {
+        println!("Roster names: none (the student's own name is always included)");
+    } else {
+        println!("Roster names: {}", config.roster.join(", "));
+    }
+}
+
+fn handle_redact_command(settings: &mut Settings, args: &str) {
+    let config = &mut settings.redaction;
+    match args.split_once(' ').map(|(cmd, rest)| (cmd, rest.trim())) {
+        Some(("roster", rest)) => match rest.split_once(' ').map(|(c, n)| (c, n.trim())) {
+            Some(("add", name)) if !name.is_empty() => {
+                if !config.roster.iter().any(|n| n.eq_ignore_ascii_case(name)) {
+                    config.roster.push(name.to_string());
+                }
+            }
+            Some(("remove", name)) => config.roster.retain(|n| !n.eq_ignore_ascii_case(name)),
+            _ => {
+                println!("Usage: redact roster add|remove <name>");
+                return;
+            }
+        },
+        Some((detector, state @ ("on" | "off"))) => {
+            let on = state == "on";
+            match detector {
+                "emails" => config.emails = on,
+                "phones" => config.phones = on,
+                "addresses" => config.addresses = on,
+                "names" => config.roster_names = on,
+                _ => {
+                    println!("Usage: redact emails|phones|addresses|names on|off");
+                    return;
+                }
+            }
+        }
+        _ => {
+            println!("Usage: redact emails|phones|addresses|names on|off | redact roster add|remove <name>");
+            return;
+        }
+    }
+    print_redaction(settings);
+}
+
+fn print_injections(base_path: &Path) {
+    match find_latest_pack(base_path) {
+        Ok(Some((_path, pack))) => {
+            for assignment in &pack.assignments {
+                println!(
+                    "  {} ({}): {}",
+                    assignment.title,
+                    assignment.id,
+                    injection::detection_count(base_path, &assignment.id)
+                );
+            }
+        }
+        _ => println!("No homework pack loaded."),
+    }
+}
+
+fn print_wellbeing(settings: &Settings, base_path: &Path) {
+    if !settings.janet.wellbeing_counter {
+        println!("Wellbeing counter is OFF (wellbeing counter on to start counting).");
+    }
+    let counts = wellbeing::weekly_counts(base_path);
+    if counts.is_empty() {
+        println!("No wellbeing replies counted.");
+    }
+    for (week, count) in counts {
+        println!("  {week}: {count}");
+    }
+}
+
+fn handle_wellbeing_command(settings: &mut Settings, base_path: &Path, args: &str) {
+    match args {
+        "counter on" => settings.janet.wellbeing_counter = true,
+        "counter off" => settings.janet.wellbeing_counter = false,
+        "clear" => {
+            if let Err(e) = wellbeing::clear_counts(base_path) {
+                println!("Could not clear counts: {e}");
+            }
+        }
+        _ => {
+            println!("Usage: wellbeing counter on|off | wellbeing clear");
+            return;
+        }
+    }
+    print_wellbeing(settings, base_path);
+}
+
+fn print_support() {
+    let contacts = wellbeing::support_config().contacts;
+    if contacts.is_empty() {
+        println!("No support contacts; wellbeing replies show the message only.");
+    }
+    for (idx, contact) in contacts.iter().enumerate() {
+        println!("  {}. {}: {}", idx + 1, contact.name, contact.detail);
+    }
+}
+
+fn handle_support_command(base_path: &Path, args: &str) {
+    let mut config = wellbeing::support_config();
+    match args.split_once(' ') {
+        Some(("add", rest)) => match rest.split_once('|') {
+            Some((name, detail)) if !name.trim().is_empty() => {
+                config.contacts.push(wellbeing::SupportContact {
+                    name: name.trim().to_string(),
+                    detail: detail.trim().to_string(),
+                });
+            }
+            _ => {
+                println!("Usage: support add <name> | <detail>");
+                return;
+            }
+        },
+        Some(("remove", n)) => match n.trim().parse::<usize>() {
+            Ok(n) if (1..=config.contacts.len()).contains(&n) => {
+                config.contacts.remove(n - 1);
+            }
+            _ => {
+                println!("No contact number {n}.");
+                return;
+            }
+        },
+        _ => {
+            println!("Usage: support add <name> | <detail> | support remove <n>");
+            return;
+        }
+    }
+    if let Err(e) = wellbeing::save_support(base_path, &config) {
+        println!("Could not save support contacts: {e}");
+    }
+    print_support();
+}
+
+fn print_cache(settings: &Settings) {
+    let cache = &settings.response_cache;
+    println!(
+        "Response cache: {} ({} answers held, kept {}s, at most {})",
+        if cache.enabled { "ON" } else { "OFF" },
+        response_cache::len(),
+        cache.ttl_secs,
+        cache.max_entries
+    );
+}
+
+fn handle_cache_command(settings: &mut Settings, args: &str) {
+    let parts: Vec<&str> = args.split_whitespace().collect();
+    match parts.as_slice() {
+        ["on"] => settings.response_cache.enabled = true,
+        ["off"] => {
+            settings.response_cache.enabled = false;
+            response_cache::clear();
+        }
+        ["clear"] => response_cache::clear(),
+        ["ttl", secs] => match secs.parse() {
+            Ok(secs) => settings.response_cache.ttl_secs = secs,
+            Err(_) => println!("TTL must be a whole number of seconds."),
+        },
+        ["size", n] => match n.parse() {
+            Ok(n) => settings.response_cache.max_entries = n,
+            Err(_) => println!("Size must be a whole number of answers."),
+        },
+        _ => {
+            println!("Usage: cache on|off | cache clear | cache ttl <secs> | cache size <n>");
+            return;
+        }
+    }
+    print_cache(settings);
+}
+
+fn print_performance(settings: &Settings, base_path: &Path) {
+    let setting = |v: u32| {
+        if v == 0 {
+            "auto".to_string()
+        } else {
+            v.to_string()
+        }
+    };
+    println!(
+        "Threads: {}, batch: {} (logged to {})",
+        setting(settings.model.n_threads),
+        setting(settings.model.n_batch),
+        local_model::metrics_log_path(base_path).display()
+    );
+    let fmt = |v: Option<f64>, unit: &str| {
+        v.map(|v| format!("{v:.1}{unit}"))
+            .unwrap_or_else(|| "-".to_string())
+    };
+    for row in local_model::summarize_metrics(base_path) {
+        println!(
+            "  {} ({}) threads {} batch {}: {} requests ({} failed), load {}, prompt {}, {} tok/s, memory {}",
+            row.model,
+            row.backend,
+            setting(row.n_threads),
+            setting(row.n_batch),
+            row.requests,
+            row.failures,
+            fmt(row.mean_load_ms, " ms"),
+            fmt(row.mean_prompt_eval_ms, " ms"),
+            fmt(row.mean_tokens_per_sec, ""),
+            local_model::memory_per_request(&row)
+        );
+    }
+}
+
+fn handle_perf_command(settings: &mut Settings, base_path: &Path, args: &str) {
+    let parts: Vec<&str> = args.split_whitespace().collect();
+    let (field, value) = match parts.as_slice() {
+        [field @ ("threads" | "batch"), value] => (*field, *value),
+        _ => {
+            println!("Usage: perf threads <n> | perf batch <n>  (0 = default)");
+            return;
+        }
+    };
+    let Ok(value) = value.parse::<u32>() else {
+        println!("Value must be a whole number (0 = default).");
+        return;
+    };
+    if field == "threads" {
+        settings.model.n_threads = value;
+    } else {
+        settings.model.n_batch = value;
+    }
+    print_performance(settings, base_path);
+}
+
 fn handle_play_request(settings: &Settings) {
     if !settings.game.enabled {
         println!("\n[Play] Games are currently DISABLED in settings.\n");
@@ -248,6 +1198,47 @@ fn teacher_console(settings: &mut Settings, base_path: &Path) {
         );
         println!("  set_pin               (change teacher PIN; confirm twice)");
         println!("  set_secret            (change secret question + answer)");
+        println!("  backend <llama_cpp|mock|local_http>  (choose inference backend)");
+        println!("  mock_script <path>|default  (reply script for the mock backend)");
+        println!(
+            "  verify_models         (list models/ with GGUF details and check manifest.json)"
+        );
+        println!("  approve_model <file>  (allow students to select a model in models/)");
+        println!("  revoke_model <file>   (remove a model from the approved list)");
+        println!("  profiles              (list model profiles and task routes)");
+        println!("  profile add <name> <path>  (add a GGUF as a named model profile)");
+        println!("  profile remove <name>");
+        println!("  route <chat|hint|premark|authoring|safety> <profile>[,<fallback>...]|default");
+        println!(
+            "  adapter <subject> <file>|none  (LoRA adapter from models/ for a subject's homework)"
+        );
+        println!(
+            "  perf                  (model timings and memory from logs/inference_metrics.jsonl)"
+        );
+        println!("  perf threads <n> | perf batch <n>  (tune the in-process model; 0 = default)");
+        println!("  janet categories      (policy files in config/janet and their actions)");
+        println!("  janet enable|disable <category> | janet reload");
+        println!("  janet locales | janet locale <code> on|off  (extra languages to filter)");
+        println!("  janet classifier off|confirm|full  (model check of flagged / all text)");
+        println!("  janet allowlist       (words Janet never blocks)");
+        println!("  janet allow <word> | janet unallow <word>");
+        println!("  janet injection on|off  (model check of hint questions for rule overrides)");
+        println!("  injections            (hint questions caught trying to override the rules, per assignment)");
+        println!("  wellbeing             (weekly count of wellbeing replies, if switched on)");
+        println!("  wellbeing counter on|off | wellbeing clear");
+        println!("  support               (contacts shown with wellbeing replies)");
+        println!("  support add <name> | <detail> | support remove <n>");
+        println!(
+            "  redact                (personal details removed before text is stored or cached)"
+        );
+        println!("  redact emails|phones|addresses|names on|off | redact roster add|remove <name>");
+        println!("  cache                 (show the shared response cache)");
+        println!("  cache on|off|clear    (share answers to repeated questions; off by default)");
+        println!("  cache ttl <secs> | cache size <n>");
+        println!("  budget                (show class-mode usage limits)");
+        println!("  budget on|off         (enable/disable limits in class mode)");
+        println!("  budget <rpm|tokens|secs> <n> [student_id]  (set a limit; 0 = unlimited)");
+        println!("  budget reset [student_id]  (clear usage so far)");
         println!("  back");
 
         print!("teacher> ");
@@ -323,6 +1314,126 @@ fn teacher_console(settings: &mut Settings, base_path: &Path) {
                 }
                 Err(e) => println!("Failed to read submissions: {}", e),
             },
+            "verify_models" => {
+                let models = model_catalog::discover_local_models(base_path);
+                if models.is_empty() {
+                    println!(
+                        "No model files found in {}.",
+                        base_path.join("models").display()
+                    );
+                }
+                for model in models {
+                    let approved = settings
+                        .approved_models
+                        .iter()
+                        .any(|a| a.file.eq_ignore_ascii_case(&model.file_name()));
+                    println!(
+                        "  {} ({}){}",
+                        model.name,
+                        model.file_name(),
+                        if approved { " [approved]" } else { "" }
+                    );
+                    println!("    {}", model.summary());
+                    if model.is_gguf() {
+                        println!(
+                            "    {}",
+                            model_catalog::verify_model(base_path, &model).label()
+                        );
+                    }
+                }
+            }
+            _ if cmd.starts_with("approve_model ") => {
+                let file = cmd.trim_start_matches("approve_model ").trim();
+                match model_catalog::approve_model(settings, base_path, file) {
+                    Ok(msg) => {
+                        let _ = save_settings(settings, base_path);
+                        println!("{msg}");
+                    }
+                    Err(e) => println!("{e}"),
+                }
+            }
+            _ if cmd.starts_with("revoke_model ") => {
+                let file = cmd.trim_start_matches("revoke_model ").trim();
+                match model_catalog::revoke_model(settings, base_path, file) {
+                    Ok(msg) => {
+                        let _ = save_settings(settings, base_path);
+                        println!("{msg}");
+                    }
+                    Err(e) => println!("{e}"),
+                }
+            }
+            "profiles" => print_model_profiles(settings),
+            _ if cmd.starts_with("profile ") => {
+                handle_profile_command(settings, cmd.trim_start_matches("profile ").trim());
+            }
+            _ if cmd.starts_with("route ") => {
+                handle_route_command(settings, cmd.trim_start_matches("route ").trim());
+            }
+            _ if cmd.starts_with("adapter ") => {
+                handle_adapter_command(settings, cmd.trim_start_matches("adapter ").trim());
+            }
+            "perf" => print_performance(settings, base_path),
+            _ if cmd.starts_with("perf ") => {
+                handle_perf_command(settings, base_path, cmd.trim_start_matches("perf ").trim());
+            }
+            _ if cmd.starts_with("janet ") => {
+                handle_janet_command(settings, base_path, cmd.trim_start_matches("janet ").trim());
+            }
+            "injections" => print_injections(base_path),
+            "redact" => print_redaction(settings),
+            _ if cmd.starts_with("redact ") => {
+                handle_redact_command(settings, cmd.trim_start_matches("redact ").trim());
+            }
+            "wellbeing" => print_wellbeing(settings, base_path),
+            _ if cmd.starts_with("wellbeing ") => {
+                handle_wellbeing_command(
+                    settings,
+                    base_path,
+                    cmd.trim_start_matches("wellbeing ").trim(),
+                );
+            }
+            "support" => print_support(),
+            _ if cmd.starts_with("support ") => {
+                handle_support_command(base_path, cmd.trim_start_matches("support ").trim());
+            }
+            "cache" => print_cache(settings),
+            _ if cmd.starts_with("cache ") => {
+                handle_cache_command(settings, cmd.trim_start_matches("cache ").trim());
+            }
+            "budget" => print_budget(settings),
+            _ if cmd.starts_with("budget ") => {
+                handle_budget_command(settings, cmd.trim_start_matches("budget ").trim());
+            }
+            _ if cmd.starts_with("backend ") => {
+                let choice = cmd.trim_start_matches("backend ").trim();
+                let backend = match choice {
+                    "llama_cpp" => Some(ModelBackend::LlamaCpp),
+                    "mock" => Some(ModelBackend::Mock),
+                    "local_http" => Some(ModelBackend::LocalHttp),
+                    _ => None,
+                };
+                match backend {
+                    Some(b) => {
+                        settings.model.backend = b;
+                        local_model::clear_cached_model();
+                        println!("Inference backend set to {}.", b.label());
+                    }
+                    None => println!("Usage: backend <llama_cpp|mock|local_http>"),
+                }
+            }
+            _ if cmd.starts_with("mock_script ") => {
+                let path = cmd.trim_start_matches("mock_script ").trim();
+                settings.model.script = if path == "default" {
+                    String::new()
+                } else {
+                    path.to_string()
+                };
+                if settings.model.script.is_empty() {
+                    println!("Mock backend uses the built-in demo script.");
+                } else {
+                    println!("Mock backend script set to {}.", settings.model.script);
+                }
+            }
             _ if cmd.starts_with("import_pack ") => {
                 let parts: Vec<&str> = cmd.splitn(2, ' ').collect();
                 if parts.len() < 2 {
@@ -450,6 +1561,8 @@ fn create_pack_interactive(base_path: &Path) -> io::Result<PathBuf> {
         .starts_with('y');
     let max_score = prompt("Max score (int, optional)", "")?;
     let instructions = prompt("Instructions (one line)", "Add details here.")?;
+    let answer_key = prompt("Answer key / model answer (one line, optional)", "")?;
+    let answer_fingerprint = hint_guard::fingerprint(&answer_key, &instructions);
 
     let assignment = HomeworkAssignment {
         id: assignment_id,
@@ -470,6 +1583,8 @@ fn create_pack_interactive(base_path: &Path) -> io::Result<PathBuf> {
         } else {
             max_score.parse().ok()
         },
+        adapter: None,
+        answer_fingerprint,
     };
 
     create_pack(base_path, &school_id, &class_id, assignment)
@@ -511,6 +1626,8 @@ fn create_pack_multi_interactive(base_path: &Path) -> io::Result<PathBuf> {
             .starts_with('y');
         let max_score = prompt("Max score (int, optional)", "")?;
         let instructions = prompt("Instructions (one line)", "Add details here.")?;
+        let answer_key = prompt("Answer key / model answer (one line, optional)", "")?;
+        let answer_fingerprint = hint_guard::fingerprint(&answer_key, &instructions);
 
         let assignment = HomeworkAssignment {
             id: assignment_id,
@@ -531,6 +1648,8 @@ fn create_pack_multi_interactive(base_path: &Path) -> io::Result<PathBuf> {
             } else {
                 max_score.parse().ok()
             },
+            adapter: None,
+            answer_fingerprint,
         };
         assignments.push(assignment);
     }
@@ -0,0 +1,817 @@
+use crate::audit;
+use crate::settings::{ApprovedModel, Settings};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+const GGUF_MAGIC: &[u8; 4] = b"GGUF";
+const MANIFEST_FILE: &str = "manifest.json";
+const HASH_CACHE_FILE: &str = ".hash_cache.json";
+
+/// Header facts read from a GGUF file. Fields are `None` when the file does not declare them.
+#[derive(Debug, Clone, Default)]
+pub struct GgufInfo {
+    pub architecture: Option<String>,
+    pub model_name: Option<String>,
+    pub parameter_count: Option<u64>,
+    pub quantization: Option<String>,
+    pub context_length: Option<u64>,
+    pub block_count: Option<u64>,
+    pub embedding_length: Option<u64>,
+    pub head_count: Option<u64>,
+    pub head_count_kv: Option<u64>,
+}
+
+impl GgufInfo {
+    /// Bytes of f16 KV cache needed for `n_ctx` tokens, when the header has the shape fields.
+    pub fn kv_cache_bytes(&self, n_ctx: u64) -> Option<u64> {
+        let layers = self.block_count?;
+        let embd = self.embedding_length?;
+        // Grouped-query attention shrinks the K/V width by head_count_kv / head_count.
+        let kv_width = match (self.head_count, self.head_count_kv) {
+            (Some(h), Some(kv)) if h > 0 && kv > 0 => embd * kv / h,
+            _ => embd,
+        };
+        Some(2 * layers * n_ctx * kv_width * 2)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LocalModelFile {
+    pub name: String,
+    pub path: PathBuf,
+    pub size_bytes: u64,
+    /// Parsed header, or the reason the file is not a usable GGUF.
+    pub info: Result<GgufInfo, String>,
+}
+
+impl LocalModelFile {
+    pub fn file_name(&self) -> String {
+        self.path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .map(|n| n.to_string())
+            .unwrap_or_else(|| self.path.to_string_lossy().to_string())
+    }
+
+    /// Hover text: the model's own name from its metadata plus the full path.
+    pub fn details(&self) -> String {
+        let declared = self
+            .info
+            .as_ref()
+            .ok()
+            .and_then(|i| i.model_name.clone())
+            .unwrap_or_else(|| self.name.clone());
+        format!("{declared}\n{}", self.path.display())
+    }
+
+    pub fn is_gguf(&self) -> bool {
+        self.info.is_ok()
+    }
+
+    /// One-line summary for menus: architecture, size class, quantisation, context, file size.
+    pub fn summary(&self) -> String {
+        match &self.info {
+            Ok(info) => format!(
+                "{} | {} params | {} | ctx {} | {}",
+                info.architecture.as_deref().unwrap_or("unknown arch"),
+                info.parameter_count
+                    .map(format_param_count)
+                    .unwrap_or_else(|| "?".to_string()),
+                info.quantization.as_deref().unwrap_or("unknown quant"),
+                info.context_length
+                    .map(|c| c.to_string())
+                    .unwrap_or_else(|| "?".to_string()),
+                format_bytes(self.size_bytes),
+            ),
+            Err(e) => format!("{} | {}", e, format_bytes(self.size_bytes)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub file: String,
+    pub sha256: String,
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+/// Optional `models/manifest.json` listing expected SHA-256 digests, maintained by IT staff.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ModelManifest {
+    #[serde(default)]
+    pub models: Vec<ManifestEntry>,
+}
+
+impl ModelManifest {
+    pub fn expected_for(&self, file_name: &str) -> Option<&str> {
+        self.models
+            .iter()
+            .find(|m| m.file.eq_ignore_ascii_case(file_name))
+            .map(|m| m.sha256.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChecksumStatus {
+    /// Digest matches the manifest entry.
+    Verified,
+    /// File is not listed in `models/manifest.json` (or there is no manifest).
+    NotListed,
+    Mismatch {
+        expected: String,
+        actual: String,
+    },
+    Failed(String),
+}
+
+impl ChecksumStatus {
+    pub fn label(&self) -> String {
+        match self {
+            ChecksumStatus::Verified => "checksum OK".to_string(),
+            ChecksumStatus::NotListed => "not in manifest".to_string(),
+            ChecksumStatus::Mismatch { .. } => "CHECKSUM MISMATCH - copy again".to_string(),
+            ChecksumStatus::Failed(e) => format!("could not verify: {e}"),
+        }
+    }
+}
+
+pub fn models_dir(base: &Path) -> PathBuf {
+    base.join("models")
+}
+
+pub fn discover_local_models(base: &Path) -> Vec<LocalModelFile> {
+    let models_dir = models_dir(base);
+    if let Err(err) = fs::create_dir_all(&models_dir) {
+        eprintln!("[models] Could not ensure models dir: {err}");
+        return Vec::new();
+    }
+
+    let entries = match fs::read_dir(&models_dir) {
+        Ok(read) => read,
+        Err(err) => {
+            eprintln!("[models] Could not read models dir: {err}");
+            return Vec::new();
+        }
+    };
+
+    let mut models = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            continue;
+        }
+
+        let file_name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or_default();
+        if file_name.starts_with('.')
+            || file_name.eq_ignore_ascii_case("gitkeep")
+            || file_name.eq_ignore_ascii_case(MANIFEST_FILE)
+        {
+            continue;
+        }
+
+        let name = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or(file_name)
+            .to_string();
+        let size_bytes = entry.metadata().map(|m| m.len()).unwrap_or(0);
+        let info = read_gguf_info(&path).map_err(|e| e.to_string());
+        models.push(LocalModelFile {
+            name,
+            path,
+            size_bytes,
+            info,
+        });
+    }
+
+    models.sort_by_key(|m| m.name.to_lowercase());
+    models
+}
+
+pub fn load_manifest(base: &Path) -> Option<ModelManifest> {
+    let path = models_dir(base).join(MANIFEST_FILE);
+    let contents = fs::read_to_string(&path).ok()?;
+    match serde_json::from_str(&contents) {
+        Ok(m) => Some(m),
+        Err(e) => {
+            eprintln!("[models] Invalid {}: {e}", path.display());
+            None
+        }
+    }
+}
+
+/// Hash the file and compare it with the manifest entry, if there is one.
+pub fn verify_model(base: &Path, model: &LocalModelFile) -> ChecksumStatus {
+    let manifest = load_manifest(base).unwrap_or_default();
+    let Some(expected) = manifest.expected_for(&model.file_name()) else {
+        return ChecksumStatus::NotListed;
+    };
+    match file_sha256(base, &model.path, true) {
+        Ok(actual) if actual.eq_ignore_ascii_case(expected.trim()) => ChecksumStatus::Verified,
+        Ok(actual) => ChecksumStatus::Mismatch {
+            expected: expected.trim().to_lowercase(),
+            actual,
+        },
+        Err(e) => ChecksumStatus::Failed(e.to_string()),
+    }
+}
+
+pub fn is_listed_approved(settings: &Settings, file_name: &str) -> bool {
+    settings
+        .approved_models
+        .iter()
+        .any(|a| a.file.eq_ignore_ascii_case(file_name))
+}
+
+/// The allowlist entry matching this digest, if any. Approval follows the file's content,
+/// not its name.
+pub fn approved_by_hash<'a>(settings: &'a Settings, sha256: &str) -> Option<&'a ApprovedModel> {
+    settings
+        .approved_models
+        .iter()
+        .find(|a| a.sha256.eq_ignore_ascii_case(sha256))
+}
+
+/// Add a file in `models/` to the allowlist. Caller saves settings.
+pub fn approve_model(
+    settings: &mut Settings,
+    base: &Path,
+    file_name: &str,
+) -> Result<String, String> {
+    let path = models_dir(base).join(file_name);
+    if !path.is_file() {
+        return Err(format!("No such model file: {}", path.display()));
+    }
+    read_gguf_info(&path).map_err(|e| format!("{file_name}: {e}"))?;
+    let sha256 =
+        file_sha256(base, &path, false).map_err(|e| format!("Could not hash {file_name}: {e}"))?;
+
+    settings
+        .approved_models
+        .retain(|a| !a.file.eq_ignore_ascii_case(file_name));
+    settings.approved_models.push(ApprovedModel {
+        file: file_name.to_string(),
+        sha256: sha256.clone(),
+    });
+    settings.refresh_model_routes();
+    if let Err(e) = audit::record(
+        base,
+        "model_approved",
+        &format!("{file_name} sha256 {sha256}"),
+    ) {
+        eprintln!("[audit] Could not record approval: {e}");
+    }
+    Ok(format!("Approved {file_name} for student use."))
+}
+
+/// Remove a file from the allowlist. Caller saves settings.
+pub fn revoke_model(
+    settings: &mut Settings,
+    base: &Path,
+    file_name: &str,
+) -> Result<String, String> {
+    let before = settings.approved_models.len();
+    settings
+        .approved_models
+        .retain(|a| !a.file.eq_ignore_ascii_case(file_name));
+    if settings.approved_models.len() == before {
+        return Err(format!("{file_name} was not on the approved list."));
+    }
+    settings.refresh_model_routes();
+    if let Err(e) = audit::record(base, "model_revoked", file_name) {
+        eprintln!("[audit] Could not record revocation: {e}");
+    }
+    Ok(format!("Removed {file_name} from the approved list."))
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct HashCacheEntry {
+    size: u64,
+    modified: u64,
+    #[serde(default)]
+    modified_nanos: u32,
+    /// Device, inode and change time on Unix. A file copied over another keeps neither, even
+    /// when its size and mtime were made to match.
+    #[serde(default)]
+    file_id: Vec<u64>,
+    sha256: String,
+}
+
+impl HashCacheEntry {
+    fn stamp(meta: &fs::Metadata, sha256: String) -> Self {
+        let modified = meta
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .unwrap_or_default();
+        Self {
+            size: meta.len(),
+            modified: modified.as_secs(),
+            modified_nanos: modified.subsec_nanos(),
+            file_id: file_id(meta),
+            sha256,
+        }
+    }
+
+    fn same_file(&self, other: &HashCacheEntry) -> bool {
+        self.size == other.size
+            && self.modified == other.modified
+            && self.modified_nanos == other.modified_nanos
+            && self.file_id == other.file_id
+    }
+}
+
+#[cfg(unix)]
+fn file_id(meta: &fs::Metadata) -> Vec<u64> {
+    use std::os::unix::fs::MetadataExt;
+    vec![
+        meta.dev(),
+        meta.ino(),
+        meta.ctime() as u64,
+        meta.ctime_nsec() as u64,
+    ]
+}
+
+#[cfg(not(unix))]
+fn file_id(_meta: &fs::Metadata) -> Vec<u64> {
+    Vec::new()
+}
+
+/// SHA-256 of a model file, remembered in `models/.hash_cache.json` by full path, size,
+/// nanosecond mtime and (on Unix) file identity, so multi-gigabyte files are only read once but
+/// a replaced file is not taken for the one that was hashed. `refresh` ignores the cache
+/// (explicit verification).
+pub fn file_sha256(base: &Path, path: &Path, refresh: bool) -> io::Result<String> {
+    let meta = fs::metadata(path)?;
+    let current = HashCacheEntry::stamp(&meta, String::new());
+    let key = fs::canonicalize(path)
+        .unwrap_or_else(|_| path.to_path_buf())
+        .to_string_lossy()
+        .to_string();
+
+    let cache_path = models_dir(base).join(HASH_CACHE_FILE);
+    let mut cache: HashMap<String, HashCacheEntry> = fs::read_to_string(&cache_path)
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default();
+    if let Some(hit) = cache.get(&key).filter(|_| !refresh) {
+        if hit.same_file(&current) {
+            return Ok(hit.sha256.clone());
+        }
+    }
+
+    let mut reader = BufReader::with_capacity(1 << 20, File::open(path)?);
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 1 << 20];
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    let digest = format!("{:x}", hasher.finalize());
+
+    cache.insert(
+        key,
+        HashCacheEntry {
+            sha256: digest.clone(),
+            ..current
+        },
+    );
+    if let Ok(json) = serde_json::to_string_pretty(&cache) {
+        if let Err(e) = fs::write(&cache_path, json) {
+            eprintln!("[models] Could not write hash cache: {e}");
+        }
+    }
+    Ok(digest)
+}
+
+/// Read the GGUF header (key/value metadata and tensor shapes) without loading any weights.
+pub fn read_gguf_info(path: &Path) -> io::Result<GgufInfo> {
+    let file = File::open(path)?;
+    let file_len = file.metadata()?.len();
+    let mut r = BufReader::new(file);
+
+    let mut magic = [0u8; 4];
+    r.read_exact(&mut magic)
+        .map_err(|_| invalid("not a GGUF file (too short)"))?;
+    if &magic != GGUF_MAGIC {
+        return Err(invalid("not a GGUF file"));
+    }
+    let version = read_u32(&mut r)?;
+    if version < 2 {
+        return Err(invalid(&format!("unsupported GGUF version {version}")));
+    }
+    let tensor_count = read_u64(&mut r)?;
+    let kv_count = read_u64(&mut r)?;
+
+    let mut info = GgufInfo::default();
+    let mut file_type: Option<u64> = None;
+    // Shape keys are prefixed with the architecture ("llama.block_count"); collect them all and
+    // resolve once the architecture is known.
+    let mut arch_ints: HashMap<String, u64> = HashMap::new();
+
+    for _ in 0..kv_count {
+        let key = read_string(&mut r)?;
+        let value_type = read_u32(&mut r)?;
+        match key.as_str() {
+            "general.architecture" => {
+                info.architecture = read_string_value(&mut r, value_type, file_len)?
+            }
+            "general.name" => info.model_name = read_string_value(&mut r, value_type, file_len)?,
+            "general.parameter_count" => {
+                info.parameter_count = read_int_value(&mut r, value_type, file_len)?
+            }
+            "general.file_type" => file_type = read_int_value(&mut r, value_type, file_len)?,
+            k if ARCH_INT_SUFFIXES.iter().any(|s| k.ends_with(s)) => {
+                if let Some(v) = read_int_value(&mut r, value_type, file_len)? {
+                    arch_ints.insert(k.to_string(), v);
+                }
+            }
+            _ => skip_value(&mut r, value_type, file_len)?,
+        }
+    }
+
+    let arch_value = |suffix: &str| -> Option<u64> {
+        info.architecture
+            .as_ref()
+            .and_then(|a| arch_ints.get(&format!("{a}{suffix}")).copied())
+            .or_else(|| {
+                arch_ints
+                    .iter()
+                    .filter(|(k, _)| k.ends_with(suffix))
+                    .map(|(_, v)| *v)
+                    .max()
+            })
+    };
+    info.context_length = arch_value(".context_length");
+    info.block_count = arch_value(".block_count");
+    info.embedding_length = arch_value(".embedding_length");
+    info.head_count = arch_value(".attention.head_count");
+    info.head_count_kv = arch_value(".attention.head_count_kv");
+    info.quantization = file_type.map(file_type_name);
+
+    if info.parameter_count.is_none() {
+        let mut total: u64 = 0;
+        for _ in 0..tensor_count {
+            let _name = read_string(&mut r)?;
+            let n_dims = read_u32(&mut r)?;
+            let mut elements: u64 = 1;
+            for _ in 0..n_dims {
+                elements = elements.saturating_mul(read_u64(&mut r)?);
+            }
+            let _ggml_type = read_u32(&mut r)?;
+            let _offset = read_u64(&mut r)?;
+            total = total.saturating_add(elements);
+        }
+        if total > 0 {
+            info.parameter_count = Some(total);
+        }
+    }
+
+    Ok(info)
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+fn read_u32(r: &mut impl Read) -> io::Result<u32> {
+    let mut b = [0u8; 4];
+    r.read_exact(&mut b)?;
+    Ok(u32::from_le_bytes(b))
+}
+
+fn read_u64(r: &mut impl Read) -> io::Result<u64> {
+    let mut b = [0u8; 8];
+    r.read_exact(&mut b)?;
+    Ok(u64::from_le_bytes(b))
+}
+
+fn read_string(r: &mut impl Read) -> io::Result<String> {
+    let len = read_u64(r)?;
+    if len > 1 << 20 {
+        return Err(invalid("GGUF string too long"));
+    }
+    let mut buf = vec![0u8; len as usize];
+    r.read_exact(&mut buf)?;
+    Ok(String::from_utf8_lossy(&buf).to_string())
+}
+
+const ARCH_INT_SUFFIXES: [&str; 5] = [
+    ".context_length",
+    ".block_count",
+    ".embedding_length",
+    ".attention.head_count",
+    ".attention.head_count_kv",
+];
+
+// GGUF metadata value types.
+const T_U8: u32 = 0;
+const T_I8: u32 = 1;
+const T_U16: u32 = 2;
+const T_I16: u32 = 3;
+const T_U32: u32 = 4;
+const T_I32: u32 = 5;
+const T_F32: u32 = 6;
+const T_BOOL: u32 = 7;
+const T_STRING: u32 = 8;
+const T_ARRAY: u32 = 9;
+const T_U64: u32 = 10;
+const T_I64: u32 = 11;
+const T_F64: u32 = 12;
+
+fn scalar_size(value_type: u32) -> Option<u64> {
+    match value_type {
+        T_U8 | T_I8 | T_BOOL => Some(1),
+        T_U16 | T_I16 => Some(2),
+        T_U32 | T_I32 | T_F32 => Some(4),
+        T_U64 | T_I64 | T_F64 => Some(8),
+        _ => None,
+    }
+}
+
+fn read_string_value<R: Read + Seek>(
+    r: &mut R,
+    value_type: u32,
+    file_len: u64,
+) -> io::Result<Option<String>> {
+    if value_type == T_STRING {
+        Ok(Some(read_string(r)?))
+    } else {
+        skip_value(r, value_type, file_len)?;
+        Ok(None)
+    }
+}
+
+fn read_int_value<R: Read + Seek>(
+    r: &mut R,
+    value_type: u32,
+    file_len: u64,
+) -> io::Result<Option<u64>> {
+    let v = match value_type {
+        T_U8 | T_I8 => {
+            let mut b = [0u8; 1];
+            r.read_exact(&mut b)?;
+            b[0] as u64
+        }
+        T_U16 | T_I16 => {
+            let mut b = [0u8; 2];
+            r.read_exact(&mut b)?;
+            u16::from_le_bytes(b) as u64
+        }
+        T_U32 | T_I32 => read_u32(r)? as u64,
+        T_U64 | T_I64 => read_u64(r)?,
+        _ => {
+            skip_value(r, value_type, file_len)?;
+            return Ok(None);
+        }
+    };
+    Ok(Some(v))
+}
+
+/// Seek `n` bytes forward, refusing to go past the end of the file. Lengths come straight from
+/// the header, so a damaged file must not be able to send the reader anywhere.
+fn skip_bytes<R: Read + Seek>(r: &mut R, n: u64, file_len: u64) -> io::Result<()> {
+    let pos = r.stream_position()?;
+    if n > file_len.saturating_sub(pos) {
+        return Err(invalid("GGUF value runs past the end of the file"));
+    }
+    r.seek(SeekFrom::Start(pos + n))?;
+    Ok(())
+}
+
+fn skip_value<R: Read + Seek>(r: &mut R, value_type: u32, file_len: u64) -> io::Result<()> {
+    if let Some(size) = scalar_size(value_type) {
+        return skip_bytes(r, size, file_len);
+    }
+    match value_type {
+        T_STRING => {
+            let len = read_u64(r)?;
+            skip_bytes(r, len, file_len)?;
+        }
+        T_ARRAY => {
+            let elem_type = read_u32(r)?;
+            let count = read_u64(r)?;
+            if let Some(size) = scalar_size(elem_type) {
+                let bytes = size
+                    .checked_mul(count)
+                    .ok_or_else(|| invalid("GGUF array too large"))?;
+                skip_bytes(r, bytes, file_len)?;
+            } else {
+                // Every element takes at least one byte, so a count beyond the file is damage,
+                // not a long vocab.
+                if count > file_len {
+                    return Err(invalid("GGUF array too large"));
+                }
+                // Strings (e.g. tokenizer vocab) and nested arrays have to be walked.
+                for _ in 0..count {
+                    skip_value(r, elem_type, file_len)?;
+                }
+            }
+        }
+        other => return Err(invalid(&format!("unknown GGUF value type {other}"))),
+    }
+    Ok(())
+}
+
+/// Names for llama.cpp's `general.file_type` (LLAMA_FTYPE_*).
+fn file_type_name(file_type: u64) -> String {
+    let name = match file_type {
+        0 => "F32",
+        1 => "F16",
+        2 => "Q4_0",
+        3 => "Q4_1",
+        7 => "Q8_0",
+        8 => "Q5_0",
+        9 => "Q5_1",
+        10 => "Q2_K",
+        11 => "Q3_K_S",
+        12 => "Q3_K_M",
+        13 => "Q3_K_L",
+        14 => "Q4_K_S",
+        15 => "Q4_K_M",
+        16 => "Q5_K_S",
+        17 => "Q5_K_M",
+        18 => "Q6_K",
+        19 => "IQ2_XXS",
+        20 => "IQ2_XS",
+        21 => "Q2_K_S",
+        22 => "IQ3_XS",
+        23 => "IQ3_XXS",
+        24 => "IQ1_S",
+        25 => "IQ4_NL",
+        26 => "IQ3_S",
+        27 => "IQ3_M",
+        28 => "IQ2_S",
+        29 => "IQ2_M",
+        30 => "IQ4_XS",
+        31 => "IQ1_M",
+        32 => "BF16",
+        _ => return format!("type {file_type}"),
+    };
+    name.to_string()
+}
+
+pub fn format_param_count(n: u64) -> String {
+    if n >= 1_000_000_000 {
+        format!("{:.1}B", n as f64 / 1e9)
+    } else if n >= 1_000_000 {
+        format!("{:.0}M", n as f64 / 1e6)
+    } else {
+        n.to_string()
+    }
+}
+
+pub fn format_bytes(n: u64) -> String {
+    const GB: f64 = 1024.0 * 1024.0 * 1024.0;
+    const MB: f64 = 1024.0 * 1024.0;
+    if n as f64 >= GB {
+        format!("{:.2} GB", n as f64 / GB)
+    } else {
+        format!("{:.0} MB", n as f64 / MB)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gguf_string(out: &mut Vec<u8>, s: &str) {
+        out.extend_from_slice(&(s.len() as u64).to_le_bytes());
+        out.extend_from_slice(s.as_bytes());
+    }
+
+    fn header(kv_count: u64) -> Vec<u8> {
+        let mut out = GGUF_MAGIC.to_vec();
+        out.extend_from_slice(&3u32.to_le_bytes());
+        out.extend_from_slice(&0u64.to_le_bytes());
+        out.extend_from_slice(&kv_count.to_le_bytes());
+        out
+    }
+
+    fn kv_string(out: &mut Vec<u8>, key: &str, value: &str) {
+        gguf_string(out, key);
+        out.extend_from_slice(&T_STRING.to_le_bytes());
+        gguf_string(out, value);
+    }
+
+    fn kv_u32(out: &mut Vec<u8>, key: &str, value: u32) {
+        gguf_string(out, key);
+        out.extend_from_slice(&T_U32.to_le_bytes());
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn write_temp(name: &str, bytes: &[u8]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("chatty_gguf_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    #[test]
+    fn reads_header_fields() {
+        let mut bytes = header(7);
+        kv_string(&mut bytes, "general.architecture", "llama");
+        kv_string(&mut bytes, "general.name", "Tiny Llama");
+        kv_u32(&mut bytes, "general.file_type", 15);
+        kv_u32(&mut bytes, "llama.context_length", 4096);
+        kv_u32(&mut bytes, "llama.block_count", 22);
+        // A string array the reader has to walk past.
+        gguf_string(&mut bytes, "tokenizer.ggml.tokens");
+        bytes.extend_from_slice(&T_ARRAY.to_le_bytes());
+        bytes.extend_from_slice(&T_STRING.to_le_bytes());
+        bytes.extend_from_slice(&2u64.to_le_bytes());
+        gguf_string(&mut bytes, "<s>");
+        gguf_string(&mut bytes, "</s>");
+        kv_u32(&mut bytes, "llama.embedding_length", 2048);
+
+        let info = read_gguf_info(&write_temp("tiny.gguf", &bytes)).unwrap();
+        assert_eq!(info.architecture.as_deref(), Some("llama"));
+        assert_eq!(info.model_name.as_deref(), Some("Tiny Llama"));
+        assert_eq!(info.quantization.as_deref(), Some("Q4_K_M"));
+        assert_eq!(info.context_length, Some(4096));
+        assert_eq!(info.block_count, Some(22));
+        assert_eq!(info.embedding_length, Some(2048));
+        assert_eq!(info.kv_cache_bytes(1024), Some(2 * 22 * 1024 * 2048 * 2));
+    }
+
+    #[test]
+    fn rejects_files_that_are_not_gguf() {
+        let err = read_gguf_info(&write_temp("notes.gguf", b"hello world")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let err = read_gguf_info(&write_temp("short.gguf", b"GG")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn lengths_past_the_end_of_the_file_are_invalid() {
+        // A skipped string claiming more bytes than the file holds, including a length that
+        // would be negative as an i64.
+        for len in [1_000u64, u64::MAX] {
+            let mut bytes = header(1);
+            gguf_string(&mut bytes, "general.description");
+            bytes.extend_from_slice(&T_STRING.to_le_bytes());
+            bytes.extend_from_slice(&len.to_le_bytes());
+            let err = read_gguf_info(&write_temp("long_string.gguf", &bytes)).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "length {len}");
+        }
+
+        for elem_type in [T_U32, T_STRING] {
+            let mut bytes = header(1);
+            gguf_string(&mut bytes, "tokenizer.ggml.scores");
+            bytes.extend_from_slice(&T_ARRAY.to_le_bytes());
+            bytes.extend_from_slice(&elem_type.to_le_bytes());
+            bytes.extend_from_slice(&u64::MAX.to_le_bytes());
+            let err = read_gguf_info(&write_temp("long_array.gguf", &bytes)).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "type {elem_type}");
+        }
+    }
+
+    #[test]
+    fn formats_sizes() {
+        assert_eq!(format_param_count(7_240_000_000), "7.2B");
+        assert_eq!(format_param_count(135_000_000), "135M");
+        assert_eq!(format_bytes(512 * 1024 * 1024), "512 MB");
+        assert_eq!(file_type_name(99), "type 99");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_file_swapped_in_with_the_same_size_and_mtime_is_hashed_again() {
+        let base = std::env::temp_dir().join(format!("chatty_hash_cache_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&base);
+        fs::create_dir_all(models_dir(&base)).unwrap();
+        let path = models_dir(&base).join("model.gguf");
+        fs::write(&path, b"GGUF approved").unwrap();
+        let approved = file_sha256(&base, &path, false).unwrap();
+        assert_eq!(file_sha256(&base, &path, false).unwrap(), approved);
+
+        let mtime = fs::metadata(&path).unwrap().modified().unwrap();
+        let swap = models_dir(&base).join("swap.gguf");
+        fs::write(&swap, b"GGUF replaced").unwrap();
+        File::options()
+            .write(true)
+            .open(&swap)
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+        fs::rename(&swap, &path).unwrap();
+        let meta = fs::metadata(&path).unwrap();
+        assert_eq!(meta.modified().unwrap(), mtime);
+        assert_eq!(meta.len(), b"GGUF approved".len() as u64);
+
+        assert_ne!(file_sha256(&base, &path, false).unwrap(), approved);
+    }
+}
@@ -39,6 +39,17 @@ pub struct ModuleManifest {
     pub icon: Option<String>,
     #[serde(default)]
     pub permissions: Vec<String>,
+    /// LoRA adapter shipped with the module for one subject (e.g. early reading).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub adapter: Option<ModuleAdapter>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModuleAdapter {
+    /// Assignment subject the adapter is for, matched case-insensitively.
+    pub subject: String,
+    /// Adapter GGUF, relative to the module folder.
+    pub file: String,
 }
 
 #[derive(Debug, Clone)]
@@ -129,6 +140,7 @@ fn ensure_builtin_modules(modules_root: &Path) -> io::Result<()> {
             },
             icon: None,
             permissions: vec![],
+            adapter: None,
         },
     )?;
 
@@ -147,6 +159,7 @@ fn ensure_builtin_modules(modules_root: &Path) -> io::Result<()> {
             },
             icon: None,
             permissions: vec![],
+            adapter: None,
         },
     )?;
 
@@ -166,6 +179,19 @@ fn ensure_module(modules_root: &Path, folder_name: &str, manifest: ModuleManifes
     Ok(())
 }
 
+/// Adapter file a module provides for `subject`, if any module declares one.
+pub fn module_adapter_for(base: &Path, subject: &str) -> Option<PathBuf> {
+    let subject = subject.trim();
+    load_modules(base).ok()?.into_iter().find_map(|m| {
+        let adapter = m.manifest.adapter?;
+        adapter
+            .subject
+            .trim()
+            .eq_ignore_ascii_case(subject)
+            .then(|| m.folder.join(adapter.file))
+    })
+}
+
 pub fn role_allowed(manifest: &ModuleManifest, role: &str) -> bool {
     manifest.roles.iter().any(|r| r.eq_ignore_ascii_case(role))
 }
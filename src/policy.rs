@@ -0,0 +1,51 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// School-wide policy switches read from `config/policy.json`.
+/// Missing or unreadable files fall back to the locked-down defaults.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PolicyConfig {
+    #[serde(default)]
+    pub allow_external_exe_modules: bool,
+    #[serde(default)]
+    pub allow_network: bool,
+    #[serde(default = "default_true")]
+    pub allow_file_export: bool,
+    #[serde(default = "default_true")]
+    pub allow_clipboard: bool,
+    /// Permit the OpenAI-compatible backend that talks to a llama-server on this machine.
+    #[serde(default)]
+    pub allow_localhost_inference: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for PolicyConfig {
+    fn default() -> Self {
+        Self {
+            allow_external_exe_modules: false,
+            allow_network: false,
+            allow_file_export: true,
+            allow_clipboard: true,
+            allow_localhost_inference: false,
+        }
+    }
+}
+
+pub fn policy_path(base: &Path) -> PathBuf {
+    base.join("config").join("policy.json")
+}
+
+pub fn load_policy(base: &Path) -> PolicyConfig {
+    let path = policy_path(base);
+    match fs::read_to_string(&path) {
+        Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+            eprintln!("[policy] Invalid {}: {e}; using defaults", path.display());
+            PolicyConfig::default()
+        }),
+        Err(_) => PolicyConfig::default(),
+    }
+}
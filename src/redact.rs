@@ -0,0 +1,438 @@
+use crate::settings::Settings;
+
+/// Words that end a street address ("12 Smith Street", "4 Acacia Ave").
+const STREET_SUFFIXES: &[&str] = &[
+    "street",
+    "st",
+    "road",
+    "rd",
+    "avenue",
+    "ave",
+    "lane",
+    "ln",
+    "drive",
+    "dr",
+    "court",
+    "ct",
+    "crescent",
+    "cres",
+    "close",
+    "way",
+    "place",
+    "pl",
+    "boulevard",
+    "blvd",
+    "terrace",
+    "tce",
+    "parade",
+    "pde",
+    "grove",
+    "highway",
+    "hwy",
+    "square",
+    "sq",
+];
+
+/// Words allowed between the house number and the suffix ("12 Old Mill Road").
+const MAX_STREET_WORDS: usize = 3;
+
+/// Words that are not part of a street name, so "3 apples in the way" is not an address.
+const NOT_STREET_WORDS: &[&str] = &[
+    "a", "an", "the", "in", "on", "at", "of", "to", "and", "or", "is", "are", "for", "by", "my",
+];
+
+/// Digits a phone number has at least and at most, across all its groups.
+const PHONE_DIGITS: std::ops::RangeInclusive<usize> = 8..=15;
+
+/// Group lengths of numbers written without a leading "+" or "0" that are still phone
+/// numbers: "555-123-4567", "1800 123 456", "9876 5432".
+const PHONE_LAYOUTS: &[&[usize]] = &[&[3, 3, 4], &[4, 3, 3], &[4, 4]];
+
+/// Characters that put a number in a sum rather than a phone number ("12 345 678 + 9").
+const ARITHMETIC: &[char] = &['+', '-', '*', '/', '=', '<', '>', '×', '÷'];
+
+/// A piece of `text` to replace, as a byte range and its placeholder.
+struct Span {
+    start: usize,
+    end: usize,
+    label: &'static str,
+}
+
+/// Whitespace-separated words with their byte ranges, edge punctuation trimmed.
+fn words(text: &str) -> Vec<(usize, usize, &str)> {
+    let mut out = Vec::new();
+    let mut start = None;
+    for (idx, c) in text.char_indices().chain([(text.len(), ' ')]) {
+        if !c.is_whitespace() {
+            start.get_or_insert(idx);
+            continue;
+        }
+        let Some(from) = start.take() else {
+            continue;
+        };
+        let raw = &text[from..idx];
+        let trimmed = raw.trim_start_matches(|c: char| "<([{\"'".contains(c));
+        let lead = raw.len() - trimmed.len();
+        let trimmed = trimmed.trim_end_matches(|c: char| ">)]}\"',.;:!?".contains(c));
+        if !trimmed.is_empty() {
+            out.push((from + lead, from + lead + trimmed.len(), trimmed));
+        }
+    }
+    out
+}
+
+fn is_email(word: &str) -> bool {
+    let Some((local, domain)) = word.split_once('@') else {
+        return false;
+    };
+    !local.is_empty()
+        && domain
+            .rsplit_once('.')
+            .is_some_and(|(host, tld)| !host.is_empty() && tld.len() >= 2)
+        && domain
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '.' || c == '-')
+}
+
+fn emails(text: &str, out: &mut Vec<Span>) {
+    for (start, end, word) in words(text) {
+        if is_email(word) {
+            out.push(Span {
+                start,
+                end,
+                label: "[email]",
+            });
+        }
+    }
+}
+
+/// Three groups that read as a date: day, month and year either way round ("18 10 2026",
+/// "2026-10-18").
+fn is_date(groups: &[&str]) -> bool {
+    let lens: Vec<usize> = groups.iter().map(|g| g.len()).collect();
+    matches!(lens.as_slice(), [1 | 2, 1 | 2, 2 | 4] | [4, 1 | 2, 1 | 2])
+}
+
+/// Whether the nearest non-space character before `start` or after `end` is an operator.
+fn in_arithmetic(text: &str, start: usize, end: usize) -> bool {
+    let before = text[..start].trim_end().chars().next_back();
+    let after = text[end..].trim_start().chars().next();
+    [before, after]
+        .iter()
+        .flatten()
+        .any(|c| ARITHMETIC.contains(c))
+}
+
+/// Runs of digit groups joined by single spaces, dashes, dots or brackets, with an optional
+/// leading "+". A run is a phone number when it starts with "+" or "0" ("+61 412 345 678",
+/// "(02) 9876 5432", "0412345678") or has a known layout (`PHONE_LAYOUTS`). Decimals
+/// ("3.14159265"), dates ("2026-10-18", "18 10 2026"), year ranges ("1914-1918"), large
+/// numbers ("12 345 678") and numbers in a sum are not.
+fn phones(text: &str, out: &mut Vec<Span>) {
+    let bytes = text.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        let starts = bytes[i].is_ascii_digit()
+            || (matches!(bytes[i], b'+' | b'(')
+                && bytes.get(i + 1).is_some_and(|b| b.is_ascii_digit()));
+        let after_word = i > 0 && (bytes[i - 1].is_ascii_alphanumeric() || bytes[i - 1] == b'/');
+        if !starts || after_word {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        let mut end = i;
+        let mut digits = 0;
+        let mut groups = Vec::new();
+        let mut separators = Vec::new();
+        let mut j = i;
+        if matches!(bytes[j], b'+' | b'(') {
+            j += 1;
+        }
+        loop {
+            let group_start = j;
+            while j < bytes.len() && bytes[j].is_ascii_digit() {
+                j += 1;
+            }
+            if j == group_start {
+                break;
+            }
+            digits += j - group_start;
+            groups.push(&text[group_start..j]);
+            end = j;
+            if j < bytes.len() && bytes[j] == b')' {
+                j += 1;
+            }
+            match (bytes.get(j), bytes.get(j + 1)) {
+                (Some(&sep @ (b' ' | b'-' | b'.')), Some(next))
+                    if next.is_ascii_digit() || *next == b'(' =>
+                {
+                    separators.push(sep);
+                    j += 1;
+                    if bytes[j] == b'(' {
+                        j += 1;
+                    }
+                }
+                _ => break,
+            }
+        }
+        let run = &text[start..end];
+        let decimal = groups.len() == 2 && separators == [b'.'];
+        let date =
+            groups.len() == 3 && separators.iter().all(|s| *s == separators[0]) && is_date(&groups);
+        let years = groups.len() == 2
+            && separators == [b'-']
+            && groups
+                .iter()
+                .all(|g| g.len() == 4 && (g.starts_with('1') || g.starts_with('2')));
+        let prefixed = run.starts_with('+') || run.trim_start_matches('(').starts_with('0');
+        let layout: Vec<usize> = groups.iter().map(|g| g.len()).collect();
+        let known_layout = PHONE_LAYOUTS.contains(&layout.as_slice());
+        let trailing_word = bytes.get(end).is_some_and(|b| b.is_ascii_alphabetic());
+        if PHONE_DIGITS.contains(&digits)
+            && !decimal
+            && !date
+            && !years
+            && (prefixed || known_layout)
+            && !trailing_word
+            && !in_arithmetic(text, start, end)
+        {
+            out.push(Span {
+                start,
+                end,
+                label: "[phone]",
+            });
+        }
+        i = end.max(i + 1);
+    }
+}
+
+fn is_house_number(word: &str) -> bool {
+    let digits = word.chars().take_while(char::is_ascii_digit).count();
+    (1..=5).contains(&digits)
+        && word[digits..]
+            .chars()
+            .all(|c| c.is_ascii_alphabetic() || c == '/' || c == '-')
+        && word.len() - digits <= 2
+}
+
+/// A house number, up to `MAX_STREET_WORDS` words, then a street suffix.
+fn addresses(text: &str, out: &mut Vec<Span>) {
+    let words = words(text);
+    for (idx, (start, _, word)) in words.iter().enumerate() {
+        if !is_house_number(word) {
+            continue;
+        }
+        for (between, (_, end, word)) in words.iter().skip(idx + 1).enumerate() {
+            let word = word.trim_end_matches('.').to_lowercase();
+            if between > 0 && STREET_SUFFIXES.contains(&word.as_str()) {
+                out.push(Span {
+                    start: *start,
+                    end: *end,
+                    label: "[address]",
+                });
+                break;
+            }
+            if between == MAX_STREET_WORDS
+                || NOT_STREET_WORDS.contains(&word.as_str())
+                || !word.chars().all(char::is_alphabetic)
+            {
+                break;
+            }
+        }
+    }
+}
+
+/// Alphanumeric words with their byte ranges, for whole-word name matching.
+fn name_words(text: &str) -> Vec<(usize, usize, String)> {
+    let mut out = Vec::new();
+    let mut start = None;
+    for (idx, c) in text.char_indices().chain([(text.len(), ' ')]) {
+        if c.is_alphanumeric() || c == '\'' || c == '-' {
+            start.get_or_insert(idx);
+        } else if let Some(from) = start.take() {
+            out.push((from, idx, text[from..idx].to_lowercase()));
+        }
+    }
+    out
+}
+
+fn names(text: &str, roster: &[String], out: &mut Vec<Span>) {
+    let words = name_words(text);
+    for name in roster {
+        let parts: Vec<String> = name_words(name).into_iter().map(|(_, _, w)| w).collect();
+        if parts.is_empty() {
+            continue;
+        }
+        for window in words.windows(parts.len()) {
+            if window.iter().zip(&parts).all(|((_, _, w), p)| w == p) {
+                out.push(Span {
+                    start: window[0].0,
+                    end: window[parts.len() - 1].1,
+                    label: "[name]",
+                });
+            }
+        }
+    }
+}
+
+/// `text` with the personal details the teacher's detectors find replaced by placeholders:
+/// "[email]", "[phone]", "[address]" and "[name]" (the student's own name and the roster).
+/// Runs before any student text is written to disk or logs, and before a question goes into
+/// a prompt whose answer may be cached.
+pub fn redact(settings: &Settings, text: &str) -> String {
+    let config = &settings.redaction;
+    let mut spans = Vec::new();
+    if config.emails {
+        emails(text, &mut spans);
+    }
+    if config.addresses {
+        addresses(text, &mut spans);
+    }
+    if config.phones {
+        phones(text, &mut spans);
+    }
+    if config.roster_names {
+        let own = settings.student.student_name.trim();
+        let mut roster = config.roster.clone();
+        // The shipped placeholder profile name is not a real student's.
+        if !own.is_empty() && own != "Student Name" {
+            roster.push(own.to_string());
+        }
+        names(text, &roster, &mut spans);
+    }
+    if spans.is_empty() {
+        return text.to_string();
+    }
+
+    // Earlier and longer spans win where detectors overlap.
+    spans.sort_by_key(|s| (s.start, std::cmp::Reverse(s.end)));
+    let mut out = String::with_capacity(text.len());
+    let mut at = 0;
+    for span in spans {
+        if span.start < at {
+            continue;
+        }
+        out.push_str(&text[at..span.start]);
+        out.push_str(span.label);
+        at = span.end;
+    }
+    out.push_str(&text[at..]);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::default_settings;
+    use std::path::Path;
+
+    fn settings() -> Settings {
+        let mut settings = default_settings(Path::new("/tmp/chatty-redact-missing"));
+        settings.student.student_name = "Student Name".to_string();
+        settings
+    }
+
+    fn redacted(text: &str) -> String {
+        redact(&settings(), text)
+    }
+
+    #[test]
+    fn emails_are_redacted() {
+        assert_eq!(
+            redacted("Email me at sam.lee+hw@school.edu.au, thanks"),
+            "Email me at [email], thanks"
+        );
+        assert_eq!(redacted("<jo@example.org>"), "<[email]>");
+        for text in ["meet @ 3pm", "user@localhost", "a@b.c", "@handle"] {
+            assert_eq!(redacted(text), text);
+        }
+    }
+
+    #[test]
+    fn phone_numbers_are_redacted() {
+        for (text, expected) in [
+            ("call 0412 345 678 tonight", "call [phone] tonight"),
+            ("my number is 0412345678.", "my number is [phone]."),
+            ("+61 412 345 678", "[phone]"),
+            ("ring (02) 9876 5432", "ring [phone]"),
+            ("US: 555-123-4567", "US: [phone]"),
+            ("(555) 123-4567", "[phone]"),
+            ("free call 1800 123 456", "free call [phone]"),
+            ("home 9876 5432", "home [phone]"),
+        ] {
+            assert_eq!(redacted(text), expected, "{text}");
+        }
+    }
+
+    #[test]
+    fn numbers_that_are_not_phones_are_kept() {
+        for text in [
+            "Round 3.14159265 to 2 places",
+            "0.12345678 is small",
+            "Due 2026-10-18 or 18/10/2026",
+            "born 05 11 2012",
+            "05.11.2026",
+            "The war lasted 1914-1918",
+            "The population is 12 345 678 people",
+            "What is 12 345 678 + 9?",
+            "1 234 567 890 stars",
+            "0412 345 678 - 2",
+            "12345678",
+            "Order 12345678kg",
+            "page 12",
+        ] {
+            assert_eq!(redacted(text), text, "{text}");
+        }
+    }
+
+    #[test]
+    fn street_addresses_are_redacted() {
+        assert_eq!(
+            redacted("I live at 12 Old Mill Road near school"),
+            "I live at [address] near school"
+        );
+        assert_eq!(redacted("4 Acacia Ave."), "[address].");
+        assert_eq!(redacted("Unit 3, 45A Smith St"), "Unit 3, [address]");
+        for text in [
+            "3 apples in the way",
+            "Take 2 steps to the right",
+            "Chapter 12 Old Mill Farm describes a road",
+            "Read pages 12 to 20",
+        ] {
+            assert_eq!(redacted(text), text, "{text}");
+        }
+    }
+
+    #[test]
+    fn roster_names_match_whole_words_only() {
+        let mut settings = settings();
+        settings.redaction.roster = vec!["Ann Lee".to_string(), "Max".to_string()];
+        assert_eq!(
+            redact(&settings, "Ann Lee and max helped me"),
+            "[name] and [name] helped me"
+        );
+        assert_eq!(
+            redact(&settings, "Annie Leeson used the maximum"),
+            "Annie Leeson used the maximum"
+        );
+        assert_eq!(redact(&settings, "Ann said hi"), "Ann said hi");
+    }
+
+    #[test]
+    fn the_students_own_name_is_redacted() {
+        let mut settings = settings();
+        assert_eq!(redact(&settings, "Student Name here"), "Student Name here");
+        settings.student.student_name = "Priya Shah".to_string();
+        assert_eq!(
+            redact(&settings, "I'm priya shah, 0412 345 678"),
+            "I'm [name], [phone]"
+        );
+        settings.redaction.roster_names = false;
+        settings.redaction.phones = false;
+        assert_eq!(
+            redact(&settings, "I'm Priya Shah, 0412 345 678"),
+            "I'm Priya Shah, 0412 345 678"
+        );
+    }
+}
@@ -0,0 +1,310 @@
+use crate::local_model::CompletionRequest;
+use crate::model_catalog::file_sha256;
+use crate::safety;
+use crate::settings::{ModelConfig, Settings};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+struct Entry {
+    answer: String,
+    stored: Instant,
+    last_hit: Instant,
+}
+
+/// Answers this app session, keyed by `CacheKey`. Memory only, so nothing a student asked is
+/// written to disk and a restart empties it.
+static CACHE: Lazy<Mutex<HashMap<String, Entry>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Everything that decides an answer: model file hash, adapter, the full system prompt (capsule
+/// plus assignment context), the assignment ID, the rest of the user turn (retrieved class
+/// material and reply instructions) and the normalised question. Two requests only share an
+/// answer when all of these match.
+pub struct CacheKey(String);
+
+/// Key for a student request, or None when the teacher has not turned the cache on.
+/// The first request after a model change hashes the model file (remembered in `models/`).
+pub fn key_for(
+    settings: &Settings,
+    model: &ModelConfig,
+    request: &CompletionRequest,
+    assignment_id: Option<&str>,
+    question: &str,
+) -> Option<CacheKey> {
+    if !settings.response_cache.enabled {
+        return None;
+    }
+    let base = Path::new(&settings.base_path);
+    let mut hasher = Sha256::new();
+    for part in [
+        model_identity(base, model),
+        request
+            .adapter
+            .as_deref()
+            .map(|a| file_identity(base, a))
+            .unwrap_or_default(),
+        request.system.clone(),
+        assignment_id.unwrap_or_default().to_string(),
+        // The question is matched loosely below; everything else in the turn exactly.
+        request.user.replacen(question, "", 1),
+        normalize_question(question),
+    ] {
+        hasher.update(part.as_bytes());
+        hasher.update([0u8]);
+    }
+    Some(CacheKey(format!("{:x}", hasher.finalize())))
+}
+
+fn file_identity(base: &Path, path: &Path) -> String {
+    file_sha256(base, path, false).unwrap_or_else(|_| path.display().to_string())
+}
+
+/// Model file hash; backends without a local file (or a missing one) fall back to their
+/// configuration, which still separates different models.
+fn model_identity(base: &Path, model: &ModelConfig) -> String {
+    match file_sha256(base, Path::new(&model.path), false) {
+        Ok(hash) => hash,
+        Err(_) => format!(
+            "{}|{}|{}|{}",
+            model.backend.label(),
+            model.name,
+            model.path,
+            model.endpoint
+        ),
+    }
+}
+
+/// Lowercase, single-spaced, without surrounding punctuation: "What is  photosynthesis?" and
+/// "what is photosynthesis" are the same question.
+pub fn normalize_question(question: &str) -> String {
+    question
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .trim_matches(|c: char| c.is_ascii_punctuation())
+        .trim()
+        .to_lowercase()
+}
+
+fn blocked_now(settings: &Settings, answer: &str) -> bool {
+    safety::check_output(settings, answer).is_err()
+}
+
+/// Cached answer for `key`, if it is still fresh and Janet (with today's settings) would let
+/// it through. Stale or now-blocked entries are dropped. Janet may run a model call, so the
+/// check happens without holding the cache lock.
+pub fn lookup(settings: &Settings, key: &CacheKey) -> Option<String> {
+    let ttl = Duration::from_secs(settings.response_cache.ttl_secs);
+    let (answer, fresh) = {
+        let cache = CACHE.lock();
+        let entry = cache.get(&key.0)?;
+        (entry.answer.clone(), entry.stored.elapsed() < ttl)
+    };
+    if !fresh || blocked_now(settings, &answer) {
+        CACHE.lock().remove(&key.0);
+        return None;
+    }
+    if let Some(entry) = CACHE.lock().get_mut(&key.0) {
+        entry.last_hit = Instant::now();
+    }
+    Some(answer)
+}
+
+/// Remember `answer` for `key`. Answers Janet would block are never stored; when the cache is
+/// full the least recently used entry makes room.
+pub fn store(settings: &Settings, key: CacheKey, answer: &str) {
+    let config = &settings.response_cache;
+    if config.max_entries == 0 || blocked_now(settings, answer) {
+        return;
+    }
+    let ttl = Duration::from_secs(config.ttl_secs);
+    let mut cache = CACHE.lock();
+    cache.retain(|_, e| e.stored.elapsed() < ttl);
+    while cache.len() >= config.max_entries {
+        let Some(oldest) = cache
+            .iter()
+            .min_by_key(|(_, e)| e.last_hit)
+            .map(|(k, _)| k.clone())
+        else {
+            break;
+        };
+        cache.remove(&oldest);
+    }
+    let now = Instant::now();
+    cache.insert(
+        key.0,
+        Entry {
+            answer: answer.to_string(),
+            stored: now,
+            last_hit: now,
+        },
+    );
+}
+
+/// Forget every cached answer (teacher action).
+pub fn clear() {
+    CACHE.lock().clear();
+}
+
+pub fn len() -> usize {
+    CACHE.lock().len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::default_settings;
+    use std::path::PathBuf;
+
+    /// The cache is global; these tests clear and count it, so they take turns.
+    static CACHE_LOCK: Mutex<()> = parking_lot::const_mutex(());
+
+    fn settings() -> Settings {
+        let mut settings = default_settings(Path::new("/tmp/chatty-cache-missing"));
+        settings.response_cache.enabled = true;
+        settings
+    }
+
+    fn request(system: &str, adapter: Option<&str>) -> CompletionRequest {
+        CompletionRequest {
+            system: system.to_string(),
+            user: String::new(),
+            max_tokens: 64,
+            grammar: None,
+            time_limit: None,
+            adapter: adapter.map(PathBuf::from),
+        }
+    }
+
+    fn key(settings: &Settings, system: &str, assignment: Option<&str>, question: &str) -> String {
+        key_for(
+            settings,
+            &settings.model,
+            &request(system, None),
+            assignment,
+            question,
+        )
+        .unwrap()
+        .0
+    }
+
+    #[test]
+    fn questions_are_normalised() {
+        assert_eq!(
+            normalize_question("  What is   Photosynthesis?? "),
+            "what is photosynthesis"
+        );
+        assert_eq!(normalize_question("...hi!"), "hi");
+    }
+
+    #[test]
+    fn keys_separate_everything_that_changes_the_answer() {
+        let settings = settings();
+        let base = key(&settings, "capsule", Some("hw-1"), "What is a noun?");
+        assert_eq!(
+            base,
+            key(&settings, "capsule", Some("hw-1"), "what is a noun")
+        );
+        assert_ne!(
+            base,
+            key(&settings, "other capsule", Some("hw-1"), "What is a noun?")
+        );
+        assert_ne!(
+            base,
+            key(&settings, "capsule", Some("hw-2"), "What is a noun?")
+        );
+        assert_ne!(base, key(&settings, "capsule", None, "What is a noun?"));
+        let with_adapter = key_for(
+            &settings,
+            &settings.model,
+            &request("capsule", Some("maths.gguf")),
+            Some("hw-1"),
+            "What is a noun?",
+        )
+        .unwrap()
+        .0;
+        assert_ne!(base, with_adapter);
+
+        let mut other_model = settings.clone();
+        other_model.model.path = "/tmp/chatty-cache-missing/other.gguf".to_string();
+        assert_ne!(
+            base,
+            key(&other_model, "capsule", Some("hw-1"), "What is a noun?")
+        );
+
+        // Same question, different retrieved material.
+        let with_material = |material: &str, question: &str| {
+            let mut req = request("capsule", None);
+            req.user = format!("{material}Student question: {question}\nRespond with one hint.");
+            key_for(&settings, &settings.model, &req, Some("hw-1"), question)
+                .unwrap()
+                .0
+        };
+        let notes_a = with_material("[notes_a.md] Nouns name people.\n", "What is a noun?");
+        assert_eq!(
+            notes_a,
+            with_material("[notes_a.md] Nouns name people.\n", "what is a noun")
+        );
+        assert_ne!(
+            notes_a,
+            with_material("[notes_b.md] Nouns name places.\n", "What is a noun?")
+        );
+
+        let mut off = settings.clone();
+        off.response_cache.enabled = false;
+        assert!(key_for(&off, &off.model, &request("capsule", None), None, "hi").is_none());
+    }
+
+    #[test]
+    fn stored_answers_are_returned_until_they_expire() {
+        let _lock = CACHE_LOCK.lock();
+        clear();
+        let mut settings = settings();
+        let k = || CacheKey("expiry".to_string());
+        store(&settings, k(), "Nouns name things.");
+        assert_eq!(
+            lookup(&settings, &k()).as_deref(),
+            Some("Nouns name things.")
+        );
+
+        settings.response_cache.ttl_secs = 0;
+        assert_eq!(lookup(&settings, &k()), None);
+        assert_eq!(len(), 0);
+    }
+
+    #[test]
+    fn janet_blocked_answers_are_never_served() {
+        let _lock = CACHE_LOCK.lock();
+        clear();
+        let settings = settings();
+        store(&settings, CacheKey("rude".to_string()), "That is shit.");
+        assert_eq!(len(), 0);
+
+        // Stored while Janet was off, then switched back on.
+        let mut janet_off = settings.clone();
+        janet_off.janet.enabled = false;
+        store(&janet_off, CacheKey("rude".to_string()), "That is shit.");
+        assert_eq!(len(), 1);
+        assert_eq!(lookup(&settings, &CacheKey("rude".to_string())), None);
+        assert_eq!(len(), 0);
+    }
+
+    #[test]
+    fn least_recently_used_entry_makes_room() {
+        let _lock = CACHE_LOCK.lock();
+        clear();
+        let mut settings = settings();
+        settings.response_cache.max_entries = 2;
+        store(&settings, CacheKey("a".to_string()), "A");
+        store(&settings, CacheKey("b".to_string()), "B");
+        assert!(lookup(&settings, &CacheKey("a".to_string())).is_some());
+        store(&settings, CacheKey("c".to_string()), "C");
+        assert_eq!(len(), 2);
+        assert!(lookup(&settings, &CacheKey("a".to_string())).is_some());
+        assert!(lookup(&settings, &CacheKey("b".to_string())).is_none());
+        assert!(lookup(&settings, &CacheKey("c".to_string())).is_some());
+    }
+}
@@ -0,0 +1,450 @@
+use crate::homework_pack::{HomeworkAssignment, HomeworkPack};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+/// Passages injected into a prompt. Small models lose track of the question with more.
+pub const MAX_PASSAGES: usize = 3;
+/// Target chunk size in characters; paragraphs are merged up to roughly this length.
+const CHUNK_CHARS: usize = 600;
+/// Files larger than this are skipped rather than chunked (likely not notes).
+const MAX_FILE_BYTES: u64 = 1024 * 1024;
+const TEXT_EXTENSIONS: &[&str] = &["txt", "md", "csv"];
+
+// BM25 parameters (the usual defaults).
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+
+const STOPWORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "can", "do", "does", "for", "from",
+    "how", "i", "in", "is", "it", "its", "me", "my", "of", "on", "or", "so", "that", "the", "this",
+    "to", "was", "what", "when", "where", "which", "who", "why", "with", "you", "your",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceKind {
+    /// A file attached to an assignment in the homework pack.
+    Attachment,
+    /// Revision material under `revision/`.
+    Revision,
+    /// Teacher notes under `notes/`.
+    TeacherNote,
+}
+
+impl SourceKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            SourceKind::Attachment => "attachment",
+            SourceKind::Revision => "revision",
+            SourceKind::TeacherNote => "teacher notes",
+        }
+    }
+}
+
+/// A retrieved chunk of local material and where it came from.
+#[derive(Debug, Clone)]
+pub struct Passage {
+    pub kind: SourceKind,
+    /// File name shown to students, e.g. `soil_moisture_gradient.txt`.
+    pub source: String,
+    /// Assignment the attachment belongs to; None for revision material and notes.
+    pub assignment_id: Option<String>,
+    pub text: String,
+}
+
+impl Passage {
+    pub fn citation(&self) -> String {
+        format!("{}: {}", self.kind.label(), self.source)
+    }
+}
+
+struct Chunk {
+    passage: Passage,
+    terms: HashMap<String, u32>,
+    len: usize,
+}
+
+/// Keyword (BM25) index over attachments, revision banks and teacher notes.
+/// Everything stays on disk and in memory; nothing is sent to the model until it is retrieved.
+pub struct RetrievalIndex {
+    chunks: Vec<Chunk>,
+    doc_freq: HashMap<String, usize>,
+    avg_len: f64,
+}
+
+/// Files the index was built from, with size and mtime, so edits trigger a rebuild.
+type Signature = Vec<(PathBuf, u64, u64)>;
+type CachedIndex = (Signature, Arc<RetrievalIndex>);
+
+static INDEX_CACHE: Lazy<Mutex<Option<CachedIndex>>> = Lazy::new(|| Mutex::new(None));
+
+pub fn notes_dir(base: &Path) -> PathBuf {
+    base.join("notes")
+}
+
+pub fn revision_dir(base: &Path) -> PathBuf {
+    base.join("revision")
+}
+
+fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .map(|w| w.to_lowercase())
+        .filter(|w| w.chars().count() > 1 && !STOPWORDS.contains(&w.as_str()))
+        .collect()
+}
+
+fn is_text_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| TEXT_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+/// Pack attachments are stored relative to the pack; look next to the installed pack, in the
+/// data folder's `homework/`, and in the repo's `resources/` (where the sample bundle keeps its
+/// attachments). Absolute paths and `..` are refused, so a pack cannot pull other files on the
+/// computer into a prompt.
+fn resolve_attachment(base: &Path, attachment: &str) -> Option<PathBuf> {
+    let rel = Path::new(attachment);
+    if !rel
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        eprintln!("[retrieval] Ignoring attachment outside the homework folders: {attachment}");
+        return None;
+    }
+    let homework = base.join("homework");
+    let resources = env::current_dir()
+        .unwrap_or_else(|_| PathBuf::from("."))
+        .join("resources");
+    [
+        homework.join("assigned").join(rel),
+        homework.join(rel),
+        resources.join(rel),
+    ]
+    .into_iter()
+    .find(|p| p.is_file())
+}
+
+fn collect_dir(dir: &Path, kind: SourceKind, out: &mut Vec<(PathBuf, SourceKind, Option<String>)>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_dir(&path, kind, out);
+        } else if is_text_file(&path) {
+            out.push((path, kind, None));
+        }
+    }
+}
+
+fn source_files(
+    base: &Path,
+    pack: Option<&HomeworkPack>,
+) -> Vec<(PathBuf, SourceKind, Option<String>)> {
+    let mut files = Vec::new();
+    if let Some(pack) = pack {
+        for assignment in &pack.assignments {
+            for attachment in &assignment.attachments {
+                match resolve_attachment(base, attachment) {
+                    Some(path) if is_text_file(&path) => {
+                        files.push((path, SourceKind::Attachment, Some(assignment.id.clone())))
+                    }
+                    Some(_) => {}
+                    None => eprintln!("[retrieval] Attachment not found: {attachment}"),
+                }
+            }
+        }
+    }
+    collect_dir(&revision_dir(base), SourceKind::Revision, &mut files);
+    collect_dir(&notes_dir(base), SourceKind::TeacherNote, &mut files);
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+    files
+}
+
+/// Split on blank lines and merge paragraphs up to `CHUNK_CHARS`, so a chunk is a few related
+/// lines rather than a whole file.
+fn chunk_text(text: &str) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    for para in text.split("\n\n").map(str::trim).filter(|p| !p.is_empty()) {
+        if !current.is_empty() && current.len() + para.len() > CHUNK_CHARS {
+            chunks.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push_str("\n\n");
+        }
+        current.push_str(para);
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+impl RetrievalIndex {
+    fn build(files: &[(PathBuf, SourceKind, Option<String>)]) -> Self {
+        let mut chunks = Vec::new();
+        for (path, kind, assignment_id) in files {
+            let text = match fs::read_to_string(path) {
+                Ok(t) => t.replace("\r\n", "\n"),
+                Err(e) => {
+                    eprintln!("[retrieval] Could not read {}: {e}", path.display());
+                    continue;
+                }
+            };
+            let source = path
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_else(|| path.display().to_string());
+            for piece in chunk_text(&text) {
+                let tokens = tokenize(&piece);
+                if tokens.is_empty() {
+                    continue;
+                }
+                let mut terms = HashMap::new();
+                for t in &tokens {
+                    *terms.entry(t.clone()).or_insert(0) += 1;
+                }
+                chunks.push(Chunk {
+                    passage: Passage {
+                        kind: *kind,
+                        source: source.clone(),
+                        assignment_id: assignment_id.clone(),
+                        text: piece,
+                    },
+                    terms,
+                    len: tokens.len(),
+                });
+            }
+        }
+
+        let mut doc_freq = HashMap::new();
+        for chunk in &chunks {
+            for term in chunk.terms.keys() {
+                *doc_freq.entry(term.clone()).or_insert(0) += 1;
+            }
+        }
+        let avg_len = if chunks.is_empty() {
+            0.0
+        } else {
+            chunks.iter().map(|c| c.len).sum::<usize>() as f64 / chunks.len() as f64
+        };
+        Self {
+            chunks,
+            doc_freq,
+            avg_len,
+        }
+    }
+
+    /// Best `limit` passages for `query`. Only the attachments of `assignment_id` are searched,
+    /// and none at all without one, so one task's field notes do not leak into another's hints
+    /// or into free chat.
+    pub fn search(&self, query: &str, assignment_id: Option<&str>, limit: usize) -> Vec<Passage> {
+        let mut query_terms = tokenize(query);
+        query_terms.sort();
+        query_terms.dedup();
+        if query_terms.is_empty() || self.chunks.is_empty() {
+            return Vec::new();
+        }
+
+        let n = self.chunks.len() as f64;
+        let mut scored: Vec<(f64, &Chunk)> = self
+            .chunks
+            .iter()
+            .filter(|c| match c.passage.assignment_id.as_deref() {
+                Some(owner) => assignment_id == Some(owner),
+                None => true,
+            })
+            .map(|chunk| {
+                let score = query_terms
+                    .iter()
+                    .filter_map(|term| {
+                        let tf = *chunk.terms.get(term)? as f64;
+                        let df = *self.doc_freq.get(term).unwrap_or(&0) as f64;
+                        let idf = ((n - df + 0.5) / (df + 0.5) + 1.0).ln();
+                        let norm = 1.0 - B + B * chunk.len as f64 / self.avg_len.max(1.0);
+                        Some(idf * tf * (K1 + 1.0) / (tf + K1 * norm))
+                    })
+                    .sum::<f64>();
+                (score, chunk)
+            })
+            .filter(|(score, _)| *score > 0.0)
+            .collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        scored
+            .into_iter()
+            .take(limit)
+            .map(|(_, c)| c.passage.clone())
+            .collect()
+    }
+}
+
+/// Current index for `base` and `pack`, rebuilt only when a source file is added, removed or
+/// edited.
+pub fn index_for(base: &Path, pack: Option<&HomeworkPack>) -> Arc<RetrievalIndex> {
+    let files = source_files(base, pack);
+    let signature: Signature = files
+        .iter()
+        .map(|(path, _, _)| {
+            let meta = fs::metadata(path).ok();
+            let size = meta.as_ref().map(|m| m.len()).unwrap_or(0);
+            let modified = meta
+                .and_then(|m| m.modified().ok())
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            (path.clone(), size, modified)
+        })
+        .collect();
+
+    let mut cache = INDEX_CACHE.lock();
+    if let Some((cached_sig, index)) = cache.as_ref() {
+        if *cached_sig == signature {
+            return index.clone();
+        }
+    }
+    let usable: Vec<_> = files
+        .into_iter()
+        .zip(&signature)
+        .filter(|(_, (_, size, _))| *size <= MAX_FILE_BYTES)
+        .map(|(file, _)| file)
+        .collect();
+    let index = Arc::new(RetrievalIndex::build(&usable));
+    *cache = Some((signature, index.clone()));
+    index
+}
+
+/// Passages for a homework hint: the question plus the assignment title, so short questions
+/// like "what does point C mean?" still find the attached notes.
+pub fn passages_for_hint(
+    base: &Path,
+    pack: Option<&HomeworkPack>,
+    assignment: &HomeworkAssignment,
+    question: &str,
+) -> Vec<Passage> {
+    let query = format!("{question} {} {}", assignment.title, assignment.subject);
+    index_for(base, pack).search(&query, Some(&assignment.id), MAX_PASSAGES)
+}
+
+/// Passages for the Chat tab: revision material and teacher notes only.
+pub fn passages_for_chat(base: &Path, pack: Option<&HomeworkPack>, question: &str) -> Vec<Passage> {
+    index_for(base, pack).search(question, None, MAX_PASSAGES)
+}
+
+/// Prompt block listing passages with their sources; empty when nothing matched.
+pub fn format_passages(passages: &[Passage]) -> String {
+    if passages.is_empty() {
+        return String::new();
+    }
+    let mut out = String::from("Class material that may help (use it, do not copy it out):\n");
+    for (idx, p) in passages.iter().enumerate() {
+        out.push_str(&format!("[{}] ({})\n{}\n", idx + 1, p.citation(), p.text));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_base(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("chatty_retrieval_{name}_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write(path: &Path, text: &str) -> PathBuf {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, text).unwrap();
+        path.to_path_buf()
+    }
+
+    #[test]
+    fn tokenize_drops_stopwords_and_single_letters() {
+        assert_eq!(
+            tokenize("What is the Water-cycle? A cycle!"),
+            vec!["water", "cycle", "cycle"]
+        );
+    }
+
+    #[test]
+    fn chunks_merge_paragraphs_up_to_the_limit() {
+        let para = "x".repeat(CHUNK_CHARS / 2);
+        let text = format!("{para}\n\n{para}\n\n{para}\n\n\n\n");
+        let chunks = chunk_text(&text);
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0], format!("{para}\n\n{para}"));
+    }
+
+    #[test]
+    fn search_ranks_by_bm25_and_respects_assignments() {
+        let base = temp_base("search");
+        let files = vec![
+            (
+                write(
+                    &base.join("soil.txt"),
+                    "Soil moisture is highest near the stream.",
+                ),
+                SourceKind::Attachment,
+                Some("hw-soil".to_string()),
+            ),
+            (
+                write(
+                    &base.join("volcano.txt"),
+                    "Volcanoes erupt magma. Magma cools into rock.",
+                ),
+                SourceKind::Revision,
+                None,
+            ),
+            (
+                write(
+                    &base.join("rocks.txt"),
+                    "Igneous rock forms from cooled magma or lava.",
+                ),
+                SourceKind::TeacherNote,
+                None,
+            ),
+        ];
+        let index = RetrievalIndex::build(&files);
+
+        let hits = index.search("magma", None, MAX_PASSAGES);
+        assert_eq!(hits.len(), 2);
+        // Two mentions in a short chunk beat one.
+        assert_eq!(hits[0].source, "volcano.txt");
+
+        assert!(index.search("soil moisture", None, MAX_PASSAGES).is_empty());
+        assert!(index
+            .search("soil moisture", Some("hw-other"), MAX_PASSAGES)
+            .is_empty());
+        let hits = index.search("soil moisture", Some("hw-soil"), MAX_PASSAGES);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].citation(), "attachment: soil.txt");
+
+        assert!(index.search("the and of", None, MAX_PASSAGES).is_empty());
+        assert_eq!(index.search("rock magma", None, 1).len(), 1);
+    }
+
+    #[test]
+    fn attachments_resolve_only_inside_homework_folders() {
+        let base = temp_base("attach");
+        write(&base.join("homework/assigned/notes/field.txt"), "notes");
+        let secret = write(&base.join("config/settings.json"), "{}");
+
+        assert_eq!(
+            resolve_attachment(&base, "notes/field.txt"),
+            Some(base.join("homework/assigned/notes/field.txt"))
+        );
+        assert_eq!(resolve_attachment(&base, "config/settings.json"), None);
+        assert_eq!(resolve_attachment(&base, "../config/settings.json"), None);
+        assert_eq!(resolve_attachment(&base, &secret.to_string_lossy()), None);
+    }
+}
@@ -1,4 +1,6 @@
+use crate::model_catalog;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
@@ -8,9 +10,87 @@ pub const APP_FOLDER_NAME: &str = "Chatty-EDU";
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct JanetConfig {
     pub enabled: bool,
-    pub block_swears: bool,
-    pub block_mature_topics: bool,
-    pub fallback_message: String,
+    /// Categories from `config/janet/` (by file name) a teacher has turned off.
+    #[serde(default)]
+    pub disabled_categories: Vec<String>,
+    /// Extra languages whose word lists are used alongside the main ones ("es", "fr", "ar",
+    /// "vi"), for classes where students also write in those languages.
+    #[serde(default)]
+    pub locales: Vec<String>,
+    /// Words Janet never blocks (a local place name, an author), matched as whole words.
+    #[serde(default)]
+    pub allowlist: Vec<String>,
+    #[serde(default)]
+    pub classifier: JanetClassifier,
+    /// Opt-in weekly count of wellbeing replies for the teacher (no names, no text).
+    #[serde(default)]
+    pub wellbeing_counter: bool,
+    /// Also ask the model whether a hint question tries to override the hint rules, for
+    /// wordings the injection patterns miss.
+    #[serde(default)]
+    pub injection_model_check: bool,
+}
+
+/// How the optional model classifier works alongside Janet's word lists.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum JanetClassifier {
+    /// Word lists only, for machines too slow to run a model check as well.
+    #[default]
+    Off,
+    /// Text the word lists flag is borderline: the model confirms the category or clears an
+    /// innocent use ("kill" in a food-chain question). Other text is not sent to the model.
+    ConfirmHits,
+    /// As `ConfirmHits`, and text the lists let through is classified too, to catch
+    /// paraphrases. One extra model call per message.
+    Full,
+}
+
+impl JanetClassifier {
+    pub const ALL: [JanetClassifier; 3] = [
+        JanetClassifier::Off,
+        JanetClassifier::ConfirmHits,
+        JanetClassifier::Full,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            JanetClassifier::Off => "Word lists only",
+            JanetClassifier::ConfirmHits => "Model checks flagged text",
+            JanetClassifier::Full => "Model checks all text",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "off" => Some(JanetClassifier::Off),
+            "confirm" | "confirm_hits" => Some(JanetClassifier::ConfirmHits),
+            "full" => Some(JanetClassifier::Full),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ModelBackend {
+    /// In-process llama.cpp over a GGUF in `models/`.
+    #[default]
+    LlamaCpp,
+    /// Scripted replies for tests and demos; `script` points at an optional script JSON.
+    Mock,
+    /// OpenAI-compatible llama-server on localhost (needs policy permission).
+    LocalHttp,
+}
+
+impl ModelBackend {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ModelBackend::LlamaCpp => "Local GGUF (llama.cpp)",
+            ModelBackend::Mock => "Scripted demo (mock)",
+            ModelBackend::LocalHttp => "Local server (localhost)",
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -18,6 +98,208 @@ pub struct ModelConfig {
     pub name: String,
     pub path: String,
     pub max_tokens: u32,
+    #[serde(default)]
+    pub backend: ModelBackend,
+    #[serde(default = "default_backend_endpoint")]
+    pub endpoint: String,
+    /// Unload the in-process model after this many idle seconds (0 keeps it loaded).
+    #[serde(default = "default_idle_unload_secs")]
+    pub idle_unload_secs: u64,
+    /// Load even when the memory check says the model will not fit (teacher override).
+    #[serde(default)]
+    pub allow_low_memory_load: bool,
+    /// CPU threads for the in-process backend (0 = llama.cpp's default for this machine).
+    #[serde(default)]
+    pub n_threads: u32,
+    /// Prompt tokens evaluated per batch (0 = default). Larger is faster with enough RAM.
+    #[serde(default)]
+    pub n_batch: u32,
+    /// Reply script for the mock backend (JSON with `rules` and `default_reply`). Empty uses
+    /// the built-in demo script.
+    #[serde(default)]
+    pub script: String,
+}
+
+/// What a model request is for. Each task can be routed to its own model profile.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum ModelTask {
+    Chat,
+    Hint,
+    Premark,
+    /// Question drafts for teachers in the pack builder.
+    Authoring,
+    /// Janet's optional classifier (see `JanetClassifier`); a small fast model is enough.
+    Safety,
+}
+
+impl ModelTask {
+    pub const ALL: [ModelTask; 5] = [
+        ModelTask::Chat,
+        ModelTask::Hint,
+        ModelTask::Premark,
+        ModelTask::Authoring,
+        ModelTask::Safety,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ModelTask::Chat => "Chat",
+            ModelTask::Hint => "Homework hints",
+            ModelTask::Premark => "Pre-mark",
+            ModelTask::Authoring => "Question authoring",
+            ModelTask::Safety => "Safety check",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "chat" => Some(ModelTask::Chat),
+            "hint" | "hints" => Some(ModelTask::Hint),
+            "premark" => Some(ModelTask::Premark),
+            "authoring" => Some(ModelTask::Authoring),
+            "safety" => Some(ModelTask::Safety),
+            _ => None,
+        }
+    }
+}
+
+impl ModelConfig {
+    /// Whether this model can be used right now. Only in-process GGUFs need a file on disk;
+    /// the other backends report their own errors when called.
+    pub fn is_available(&self) -> bool {
+        self.backend != ModelBackend::LlamaCpp || Path::new(&self.path).is_file()
+    }
+}
+
+/// A model the teacher has cleared for student use, pinned by content hash so a renamed or
+/// swapped file does not inherit the approval.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ApprovedModel {
+    pub file: String,
+    pub sha256: String,
+}
+
+/// Usage limits for one student in class mode. 0 means no limit for that dimension.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BudgetLimits {
+    pub requests_per_minute: u32,
+    /// Generated tokens per app session (reset when the app restarts or a teacher resets it).
+    pub tokens_per_session: u32,
+    pub max_generation_secs: u32,
+}
+
+impl Default for BudgetLimits {
+    fn default() -> Self {
+        Self {
+            requests_per_minute: 6,
+            tokens_per_session: 4000,
+            max_generation_secs: 60,
+        }
+    }
+}
+
+/// Class-mode budgets so one student cannot keep the shared CPU busy.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BudgetConfig {
+    #[serde(default = "default_budget_enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub limits: BudgetLimits,
+    /// Overrides keyed by student ID (e.g. extra allowance for a support plan).
+    #[serde(default)]
+    pub per_student: BTreeMap<String, BudgetLimits>,
+}
+
+impl Default for BudgetConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_budget_enabled(),
+            limits: BudgetLimits::default(),
+            per_student: BTreeMap::new(),
+        }
+    }
+}
+
+impl BudgetConfig {
+    pub fn limits_for(&self, student_id: &str) -> BudgetLimits {
+        self.per_student
+            .get(student_id)
+            .copied()
+            .unwrap_or(self.limits)
+    }
+}
+
+/// Shared answers for repeated questions. Off by default: a teacher turns it on for a lesson
+/// where many students ask the same thing.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ResponseCacheConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_cache_ttl_secs")]
+    pub ttl_secs: u64,
+    #[serde(default = "default_cache_max_entries")]
+    pub max_entries: usize,
+}
+
+impl Default for ResponseCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ttl_secs: default_cache_ttl_secs(),
+            max_entries: default_cache_max_entries(),
+        }
+    }
+}
+
+/// Which personal details are replaced ("[phone]", "[name]") before student text is stored,
+/// logged or put in a prompt whose answer may be cached. All on by default.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RedactionConfig {
+    #[serde(default = "default_redact")]
+    pub emails: bool,
+    #[serde(default = "default_redact")]
+    pub phones: bool,
+    #[serde(default = "default_redact")]
+    pub addresses: bool,
+    #[serde(default = "default_redact")]
+    pub roster_names: bool,
+    /// Names to redact besides the student's own (classmates, staff), matched as whole words.
+    #[serde(default)]
+    pub roster: Vec<String>,
+}
+
+impl Default for RedactionConfig {
+    fn default() -> Self {
+        Self {
+            emails: true,
+            phones: true,
+            addresses: true,
+            roster_names: true,
+            roster: Vec::new(),
+        }
+    }
+}
+
+/// The student's own chat history, saved per profile under `history/`. The student sets it
+/// from the Chat tab.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChatHistoryConfig {
+    #[serde(default = "default_history_enabled")]
+    pub enabled: bool,
+    /// Days a chat is kept after its last message; older chats are deleted (0 = until the
+    /// student deletes them).
+    #[serde(default = "default_history_retention_days")]
+    pub retention_days: u32,
+}
+
+impl Default for ChatHistoryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_history_enabled(),
+            retention_days: default_history_retention_days(),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -60,8 +342,40 @@ pub struct Settings {
     pub teacher_secret_answer: String,
     #[serde(default)]
     pub student: StudentProfile,
+    /// Student ID when the app started. Class-mode budgets are charged to it, so editing the ID
+    /// during a lesson does not start a fresh allowance. Not saved.
+    #[serde(skip)]
+    pub session_student_id: String,
     pub janet: JanetConfig,
     pub model: ModelConfig,
+    #[serde(default)]
+    pub approved_models: Vec<ApprovedModel>,
+    #[serde(default)]
+    pub budget: BudgetConfig,
+    /// Extra named models (e.g. a small fast one for chat, a larger one for pre-marking).
+    /// `model` stays the default used when a task has no route.
+    #[serde(default)]
+    pub model_profiles: Vec<ModelConfig>,
+    /// Task -> profile names in order of preference; missing models fall through to the next
+    /// name and finally to `model`.
+    #[serde(default)]
+    pub model_routes: BTreeMap<ModelTask, Vec<String>>,
+    /// Task -> profile chosen by `refresh_model_routes`. Not saved.
+    #[serde(skip)]
+    routed_models: BTreeMap<ModelTask, String>,
+    /// Memory the in-process cache may use for loaded models, in MB (0 = decide from free RAM).
+    #[serde(default)]
+    pub model_memory_budget_mb: u64,
+    /// Subject -> LoRA adapter applied on top of the base model for that subject's homework.
+    /// Relative paths are inside `models/`.
+    #[serde(default)]
+    pub subject_adapters: BTreeMap<String, String>,
+    #[serde(default)]
+    pub response_cache: ResponseCacheConfig,
+    #[serde(default)]
+    pub redaction: RedactionConfig,
+    #[serde(default)]
+    pub chat_history: ChatHistoryConfig,
     pub voice: VoiceConfig,
     pub game: GameConfig,
     #[serde(default)]
@@ -78,6 +392,85 @@ pub struct StudentProfile {
     pub class_id: String,
 }
 
+impl Settings {
+    /// Model for `task` as picked by the last `refresh_model_routes`, else the default model.
+    pub fn model_for(&self, task: ModelTask) -> &ModelConfig {
+        self.routed_models
+            .get(&task)
+            .and_then(|name| self.model_profiles.iter().find(|p| &p.name == name))
+            .unwrap_or(&self.model)
+    }
+
+    /// Pick each task's model: the first routed profile that is available (and, for anything a
+    /// student triggers, approved). This reads and may hash model files, so it runs when routes,
+    /// profiles or approvals change rather than on every request.
+    pub fn refresh_model_routes(&mut self) {
+        let mut routed = BTreeMap::new();
+        for (&task, route) in &self.model_routes {
+            for name in route {
+                match self.model_profiles.iter().find(|p| &p.name == name) {
+                    Some(profile) if profile.is_available() => {
+                        if self.route_approved(task, profile) {
+                            routed.insert(task, name.clone());
+                            break;
+                        }
+                        eprintln!(
+                            "[models] {} model '{name}' is not on the approved list; trying the next one",
+                            task.label()
+                        );
+                    }
+                    Some(profile) => eprintln!(
+                        "[models] {} model '{name}' not found at {}; trying the next one",
+                        task.label(),
+                        profile.path
+                    ),
+                    None => eprintln!(
+                        "[models] {} route names unknown profile '{name}'",
+                        task.label()
+                    ),
+                }
+            }
+        }
+        self.routed_models = routed;
+    }
+
+    /// A routed GGUF only answers students when its file is on the approved list, the same rule
+    /// as a student switching models, so a profile made from an unapproved file cannot reach
+    /// them. The default model and teacher-only authoring are the teacher's choice.
+    fn route_approved(&self, task: ModelTask, profile: &ModelConfig) -> bool {
+        if task == ModelTask::Authoring || profile.backend != ModelBackend::LlamaCpp {
+            return true;
+        }
+        let base = Path::new(&self.base_path);
+        match model_catalog::file_sha256(base, Path::new(&profile.path), false) {
+            Ok(sha256) => model_catalog::approved_by_hash(self, &sha256).is_some(),
+            Err(e) => {
+                eprintln!("[models] Could not hash {}: {e}", profile.path);
+                false
+            }
+        }
+    }
+
+    /// Adapter the teacher mapped to `subject` (case-insensitive), resolved against `models/`.
+    pub fn adapter_for_subject(&self, subject: &str) -> Option<PathBuf> {
+        let subject = subject.trim();
+        self.subject_adapters
+            .iter()
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case(subject))
+            .map(|(_, file)| self.resolve_model_file(file))
+    }
+
+    /// `file` as-is when absolute, otherwise inside `<base>/models/`.
+    pub fn resolve_model_file(&self, file: &str) -> PathBuf {
+        let path = Path::new(file);
+        if path.is_absolute() {
+            path.to_path_buf()
+        } else {
+            Path::new(&self.base_path).join("models").join(path)
+        }
+    }
+}
+
 pub fn default_teacher_pin() -> String {
     "0000".to_string()
 }
@@ -90,6 +483,38 @@ pub fn default_secret_answer() -> String {
     "math".to_string()
 }
 
+pub fn default_backend_endpoint() -> String {
+    "http://127.0.0.1:8080".to_string()
+}
+
+pub fn default_idle_unload_secs() -> u64 {
+    600
+}
+
+pub fn default_budget_enabled() -> bool {
+    true
+}
+
+pub fn default_redact() -> bool {
+    true
+}
+
+pub fn default_history_enabled() -> bool {
+    true
+}
+
+pub fn default_history_retention_days() -> u32 {
+    30
+}
+
+pub fn default_cache_ttl_secs() -> u64 {
+    900
+}
+
+pub fn default_cache_max_entries() -> usize {
+    200
+}
+
 pub fn default_homework_hints_only() -> bool {
     true
 }
@@ -115,6 +540,7 @@ pub fn ensure_base_folders(base: &Path) -> io::Result<()> {
         base.join("homework").join("assigned"),
         base.join("homework").join("completed"),
         base.join("revision"),
+        base.join("notes"),
         base.join("modules"),
         base.join("logs"),
         base.join("config"),
@@ -148,10 +574,22 @@ pub fn load_or_init_settings(base: &Path) -> io::Result<Settings> {
         if settings.base_path != base.to_string_lossy() {
             settings.base_path = base.to_string_lossy().to_string();
         }
+        settings.session_student_id = settings.student.student_id.clone();
+        settings.refresh_model_routes();
         return Ok(settings);
     }
 
-    let settings = Settings {
+    let settings = default_settings(base);
+    let json = serde_json::to_string_pretty(&settings)
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("JSON encode error: {e}")))?;
+    fs::write(&config_path, json)?;
+
+    Ok(settings)
+}
+
+/// Settings written on first run, before a teacher changes anything.
+pub fn default_settings(base: &Path) -> Settings {
+    Settings {
         version: "0.2.0".to_string(),
         base_path: base.to_string_lossy().to_string(),
         mode: "gui".to_string(),
@@ -166,11 +604,15 @@ pub fn load_or_init_settings(base: &Path) -> io::Result<Settings> {
             student_name: "Student Name".to_string(),
             class_id: "class-placeholder".to_string(),
         },
+        session_student_id: "student-id-placeholder".to_string(),
         janet: JanetConfig {
             enabled: true,
-            block_swears: true,
-            block_mature_topics: true,
-            fallback_message: "Let's switch topics. I'm here for school-safe chat and study tips.".to_string(),
+            disabled_categories: Vec::new(),
+            locales: Vec::new(),
+            allowlist: Vec::new(),
+            classifier: JanetClassifier::Off,
+            wellbeing_counter: false,
+            injection_model_check: false,
         },
         model: ModelConfig {
             name: "phi-mini-placeholder".to_string(),
@@ -180,7 +622,24 @@ pub fn load_or_init_settings(base: &Path) -> io::Result<Settings> {
                 .to_string_lossy()
                 .to_string(),
             max_tokens: 256,
+            backend: ModelBackend::default(),
+            endpoint: default_backend_endpoint(),
+            idle_unload_secs: default_idle_unload_secs(),
+            allow_low_memory_load: false,
+            n_threads: 0,
+            n_batch: 0,
+            script: String::new(),
         },
+        approved_models: Vec::new(),
+        budget: BudgetConfig::default(),
+        model_profiles: Vec::new(),
+        model_routes: BTreeMap::new(),
+        routed_models: BTreeMap::new(),
+        model_memory_budget_mb: 0,
+        subject_adapters: BTreeMap::new(),
+        response_cache: ResponseCacheConfig::default(),
+        redaction: RedactionConfig::default(),
+        chat_history: ChatHistoryConfig::default(),
         voice: VoiceConfig {
             enabled: false,
             engine: "os_tts".to_string(),
@@ -191,13 +650,7 @@ pub fn load_or_init_settings(base: &Path) -> io::Result<Settings> {
             available_games: vec!["chattybox".to_string(), "chattyclysm".to_string()],
         },
         ui: UiSettings::default(),
-    };
-
-    let json = serde_json::to_string_pretty(&settings)
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("JSON encode error: {e}")))?;
-    fs::write(&config_path, json)?;
-
-    Ok(settings)
+    }
 }
 
 pub fn save_settings(settings: &Settings, base: &Path) -> io::Result<()> {
@@ -207,3 +660,70 @@ pub fn save_settings(settings: &Settings, base: &Path) -> io::Result<()> {
     fs::write(&config_path, json)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn routed_settings(name: &str) -> (Settings, PathBuf) {
+        let base =
+            std::env::temp_dir().join(format!("chatty_routes_{name}_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&base);
+        fs::create_dir_all(base.join("models")).unwrap();
+        let file = base.join("models").join("small.gguf");
+        fs::write(&file, b"GGUF small model").unwrap();
+        let mut settings = default_settings(&base);
+        let mut profile = settings.model.clone();
+        profile.name = "small".to_string();
+        profile.path = file.to_string_lossy().to_string();
+        settings.model_profiles.push(profile);
+        for task in ModelTask::ALL {
+            settings
+                .model_routes
+                .insert(task, vec!["small".to_string()]);
+        }
+        settings.refresh_model_routes();
+        (settings, file)
+    }
+
+    #[test]
+    fn unapproved_routed_models_do_not_reach_students() {
+        let (settings, _) = routed_settings("unapproved");
+        assert_eq!(
+            settings.model_for(ModelTask::Chat).name,
+            settings.model.name
+        );
+        assert_eq!(
+            settings.model_for(ModelTask::Hint).name,
+            settings.model.name
+        );
+        // Authoring output only goes to the teacher.
+        assert_eq!(settings.model_for(ModelTask::Authoring).name, "small");
+    }
+
+    #[test]
+    fn approved_routed_models_are_used() {
+        let (mut settings, file) = routed_settings("approved");
+        let base = PathBuf::from(&settings.base_path);
+        let sha256 = model_catalog::file_sha256(&base, &file, true).unwrap();
+        settings.approved_models.push(ApprovedModel {
+            file: "small.gguf".to_string(),
+            sha256,
+        });
+        // Routes are only re-checked when something changes.
+        assert_eq!(
+            settings.model_for(ModelTask::Chat).name,
+            settings.model.name
+        );
+        settings.refresh_model_routes();
+        assert_eq!(settings.model_for(ModelTask::Chat).name, "small");
+
+        // Swapping the file behind the profile drops it back to the default model.
+        fs::write(&file, b"GGUF a different model").unwrap();
+        settings.refresh_model_routes();
+        assert_eq!(
+            settings.model_for(ModelTask::Chat).name,
+            settings.model.name
+        );
+    }
+}
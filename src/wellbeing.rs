@@ -0,0 +1,202 @@
+use crate::settings::Settings;
+use chrono::Datelike;
+use once_cell::sync::Lazy;
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Shipped contacts, written to `config/support.json` the first time so the school can add
+/// its counsellor and local helplines.
+const DEFAULT_SUPPORT: &str = include_str!("../config/support.json");
+
+/// Weeks of counts kept; older weeks are dropped when a new one is recorded.
+const WEEKS_KEPT: usize = 12;
+
+/// One person or service a student can turn to.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SupportContact {
+    pub name: String,
+    /// Where or how to reach them (a room, opening hours, a helpline number).
+    pub detail: String,
+}
+
+/// School support contacts shown with wellbeing replies. Stored locally in
+/// `config/support.json`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SupportConfig {
+    #[serde(default)]
+    pub contacts: Vec<SupportContact>,
+}
+
+static SUPPORT: Lazy<RwLock<SupportConfig>> = Lazy::new(|| {
+    RwLock::new(serde_json::from_str(DEFAULT_SUPPORT).expect("shipped support.json should parse"))
+});
+/// Serialises read-modify-write of the counts file.
+static COUNT_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+pub fn support_path(base: &Path) -> PathBuf {
+    base.join("config").join("support.json")
+}
+
+pub fn counts_path(base: &Path) -> PathBuf {
+    base.join("logs").join("wellbeing_counts.json")
+}
+
+/// Load `config/support.json`, writing the shipped contacts there first when it is missing.
+pub fn load_support(base: &Path) {
+    let path = support_path(base);
+    if !path.exists() {
+        if let Err(e) = fs::write(&path, DEFAULT_SUPPORT) {
+            eprintln!("[wellbeing] Could not write {}: {e}", path.display());
+        }
+    }
+    match fs::read_to_string(&path).map(|c| serde_json::from_str::<SupportConfig>(&c)) {
+        Ok(Ok(config)) => *SUPPORT.write() = config,
+        Ok(Err(e)) => eprintln!(
+            "[wellbeing] Invalid {}: {e}; keeping the contacts already loaded",
+            path.display()
+        ),
+        Err(_) => {}
+    }
+}
+
+pub fn support_config() -> SupportConfig {
+    SUPPORT.read().clone()
+}
+
+pub fn save_support(base: &Path, config: &SupportConfig) -> io::Result<()> {
+    let json = serde_json::to_string_pretty(config)
+        .map_err(|e| io::Error::other(format!("JSON encode error: {e}")))?;
+    fs::write(support_path(base), json)?;
+    *SUPPORT.write() = config.clone();
+    Ok(())
+}
+
+/// `intro` (the wellbeing category's calm message) followed by the school's contacts.
+pub fn support_text(intro: &str) -> String {
+    format_support(intro, &SUPPORT.read().contacts)
+}
+
+fn format_support(intro: &str, contacts: &[SupportContact]) -> String {
+    let mut text = intro.trim().to_string();
+    for contact in contacts {
+        text.push_str(&format!("\n- {}: {}", contact.name, contact.detail));
+    }
+    text
+}
+
+fn week_label(date: chrono::NaiveDate) -> String {
+    let week = date.iso_week();
+    format!("{}-W{:02}", week.year(), week.week())
+}
+
+fn read_counts(path: &Path) -> BTreeMap<String, u32> {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|c| serde_json::from_str(&c).ok())
+        .unwrap_or_default()
+}
+
+/// Count one wellbeing reply, when the teacher has opted in. Only a total per week is kept:
+/// no student, no text, no time of day, so the count shows whether support is being asked for
+/// without pointing at anyone.
+pub fn record_support_shown(settings: &Settings) {
+    if !settings.janet.wellbeing_counter {
+        return;
+    }
+    let path = counts_path(Path::new(&settings.base_path));
+    let _guard = COUNT_LOCK.lock();
+    let mut counts = read_counts(&path);
+    *counts
+        .entry(week_label(chrono::Local::now().date_naive()))
+        .or_default() += 1;
+    while counts.len() > WEEKS_KEPT {
+        counts.pop_first();
+    }
+    let written = serde_json::to_string_pretty(&counts)
+        .map_err(io::Error::other)
+        .and_then(|json| {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&path, json)
+        });
+    if let Err(e) = written {
+        eprintln!("[wellbeing] Could not write {}: {e}", path.display());
+    }
+}
+
+/// Weekly totals, oldest first, as ("2025-W07", count).
+pub fn weekly_counts(base: &Path) -> Vec<(String, u32)> {
+    read_counts(&counts_path(base)).into_iter().collect()
+}
+
+pub fn clear_counts(base: &Path) -> io::Result<()> {
+    let _guard = COUNT_LOCK.lock();
+    match fs::remove_file(counts_path(base)) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_base(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("chatty_wellbeing_{name}_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("config")).unwrap();
+        dir
+    }
+
+    fn contact(name: &str, detail: &str) -> SupportContact {
+        SupportContact {
+            name: name.to_string(),
+            detail: detail.to_string(),
+        }
+    }
+
+    #[test]
+    fn support_text_lists_every_contact_after_the_message() {
+        let contacts = [
+            contact("School counsellor", "Room 4, lunchtimes"),
+            contact("Kids Helpline", "1800 55 1800"),
+        ];
+        assert_eq!(
+            format_support("  You matter.\n", &contacts),
+            "You matter.\n- School counsellor: Room 4, lunchtimes\n- Kids Helpline: 1800 55 1800"
+        );
+        assert_eq!(format_support("You matter.", &[]), "You matter.");
+    }
+
+    #[test]
+    fn support_config_is_written_loaded_and_kept_when_invalid() {
+        let base = temp_base("config");
+        load_support(&base);
+        let shipped: SupportConfig = serde_json::from_str(DEFAULT_SUPPORT).unwrap();
+        let written = fs::read_to_string(support_path(&base)).unwrap();
+        assert_eq!(written, DEFAULT_SUPPORT);
+        assert_eq!(support_config().contacts, shipped.contacts);
+
+        let school = SupportConfig {
+            contacts: vec![contact("Ms Rivera", "Library, Tuesdays")],
+        };
+        save_support(&base, &school).unwrap();
+        load_support(&base);
+        assert_eq!(support_config().contacts, school.contacts);
+        assert!(support_text("Talk to someone.").contains("- Ms Rivera: Library, Tuesdays"));
+
+        // A broken edit keeps the contacts already loaded.
+        fs::write(support_path(&base), "{ not json").unwrap();
+        load_support(&base);
+        assert_eq!(support_config().contacts, school.contacts);
+
+        *SUPPORT.write() = shipped;
+        let _ = fs::remove_dir_all(&base);
+    }
+}